@@ -0,0 +1,463 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::hdr::{HdrInfo, probe_side_data, supports_hdr, tonemap_filter};
+use crate::media::MediaInfo;
+use crate::preset::Preset;
+use crate::trim::{Trim, sample_ranges};
+
+/// 检测黑边时截取的样本段数和每段长度
+const CROP_SAMPLES: usize = 6;
+const CROP_SAMPLE_LENGTH: Duration = Duration::from_secs(2);
+/// 亮度低于该值 (0-255) 的像素算作黑边
+const CROP_LIMIT: u32 = 24;
+/// 至少要有这个比例的样本检测结果与最终裁剪一致，否则认为不稳定
+const CROP_MIN_AGREEMENT: f64 = 0.5;
+/// 裁剪后至少保留原画面面积的比例，更小的检测结果多半是暗场
+const CROP_MIN_AREA: f64 = 0.5;
+/// 宽和高都裁掉不到这么多像素时不值得裁剪
+const CROP_MIN_PIXELS: u32 = 8;
+
+/// 检测隔行扫描时截取的样本段数和每段长度
+const IDET_SAMPLES: usize = 4;
+const IDET_SAMPLE_LENGTH: Duration = Duration::from_secs(10);
+/// 能判断的帧少于这个数时结果不可靠，按逐行处理
+const IDET_MIN_FRAMES: u64 = 100;
+/// 隔行帧占能判断的帧的比例达到该值时判定为隔行
+const IDET_INTERLACED_RATIO: f64 = 0.25;
+/// 重复场的帧占总帧数的比例达到该值时判定为胶片过带（3:2 下拉约为 40%）
+const IDET_REPEATED_RATIO: f64 = 0.15;
+
+/// 转码前分析输入文件，按预设的分辨率和帧率上限、黑边和隔行扫描检测、HDR 信息生成视频滤镜
+#[derive(Clone, Debug)]
+pub struct Analyzer {
+    pub ffprobe: PathBuf,
+    /// 是否检测并裁掉黑边
+    pub autocrop: bool,
+    /// 隔行内容使用的去隔行滤镜 (bwdif 或 yadif)，None 表示不检测隔行扫描
+    pub deinterlacer: Option<String>,
+}
+
+/// 一个输入文件的分析结果
+#[derive(Clone, Debug, Default)]
+pub struct Analysis {
+    pub info: MediaInfo,
+    /// 检测到的黑边裁剪，没有黑边或结果不可靠时为 None
+    pub crop: Option<Crop>,
+    /// 检测到的扫描方式，没有检测时为 Progressive
+    pub scan: Scan,
+    /// 按扫描方式添加的去隔行滤镜，逐行视频为 None
+    pub deinterlace: Option<String>,
+    /// HDR 信息，SDR 视频为 None
+    pub hdr: Option<HdrInfo>,
+}
+
+/// 视频的扫描方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Scan {
+    #[default]
+    Progressive,
+    /// 隔行扫描，例如 DV 和电视录像
+    Interlaced,
+    /// 胶片过带 (telecine)，24 帧的内容以重复场的方式做成 30 帧隔行
+    Telecined,
+}
+
+impl Scan {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Scan::Progressive => "逐行扫描",
+            Scan::Interlaced => "隔行扫描",
+            Scan::Telecined => "胶片过带 (telecine)",
+        }
+    }
+}
+
+/// idet 滤镜对一段视频的统计
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct IdetCounts {
+    /// 多帧检测中判定为隔行 (TFF + BFF) 的帧数
+    pub interlaced: u64,
+    pub progressive: u64,
+    pub undetermined: u64,
+    /// 有重复场的帧数
+    pub repeated: u64,
+    /// 重复场检测的总帧数
+    pub frames: u64,
+}
+
+/// 裁剪区域，对应 crop=w:h:x:y
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Crop {
+    pub width: u32,
+    pub height: u32,
+    pub x: u32,
+    pub y: u32,
+}
+
+impl std::fmt::Display for Crop {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}x{} (x={}, y={})",
+            self.width, self.height, self.x, self.y
+        )
+    }
+}
+
+impl Analyzer {
+    /// 该预设是否需要分析输入文件，音频预设不处理画面
+    pub fn needed(&self, preset: &Preset) -> bool {
+        if preset.audio {
+            return false;
+        }
+        ((self.autocrop || self.deinterlacer.is_some()) && can_filter(preset))
+            || preset.max_res.is_some()
+            || preset.max_fps.is_some()
+            || preset.tonemap.is_some()
+            || preset.video_encoder().is_some_and(supports_hdr)
+    }
+
+    /// 读取输入文件信息和 HDR 信息，启用 autocrop 时用 ffmpeg 检测黑边，启用去隔行时检测扫描方式。
+    /// trim 不为 None 时只在剪辑范围内截取检测的样本
+    pub fn analyze(
+        &self,
+        ffmpeg: &Path,
+        input: &Path,
+        trim: Option<Trim>,
+    ) -> Result<Analysis, String> {
+        let info = MediaInfo::probe(&self.ffprobe, input)?;
+        let mut analysis = Analysis {
+            info,
+            ..Default::default()
+        };
+        if let Some(video) = analysis.info.video_stream() {
+            // 母版显示器信息读取失败时仍保留色彩参数
+            let side_data = match HdrInfo::detect(video, "") {
+                Some(_) => probe_side_data(&self.ffprobe, input).unwrap_or_default(),
+                None => String::new(),
+            };
+            analysis.hdr = HdrInfo::detect(video, &side_data);
+        }
+        let (Some(duration), Some(video)) = (analysis.info.duration, analysis.info.video_stream())
+        else {
+            return Ok(analysis);
+        };
+
+        if self.autocrop
+            && let (Some(width), Some(height)) = (video.width, video.height)
+        {
+            let mut detections = Vec::new();
+            for (start, length) in sample_ranges(trim, duration, CROP_SAMPLES, CROP_SAMPLE_LENGTH) {
+                let output = run_filter(
+                    ffmpeg,
+                    input,
+                    start,
+                    length,
+                    &format!("cropdetect=limit={}:round=2:reset=0", CROP_LIMIT),
+                )
+                .map_err(|e| format!("检测黑边失败: {}", e))?;
+                detections.push(parse_cropdetect(&output));
+            }
+            analysis.crop = stable_crop(&detections, width, height);
+        }
+
+        if self.deinterlacer.is_some() {
+            let mut counts = Vec::new();
+            for (start, length) in sample_ranges(trim, duration, IDET_SAMPLES, IDET_SAMPLE_LENGTH) {
+                let output = run_filter(ffmpeg, input, start, length, "idet")
+                    .map_err(|e| format!("检测隔行扫描失败: {}", e))?;
+                counts.extend(parse_idet(&output));
+            }
+            analysis.scan = classify_scan(&counts);
+        }
+        if let Some(deinterlacer) = &self.deinterlacer {
+            analysis.deinterlace = deinterlace_filter(analysis.scan, deinterlacer);
+        }
+
+        Ok(analysis)
+    }
+}
+
+// 对一段视频运行分析滤镜，返回 ffmpeg 的输出（滤镜的统计信息在其中）
+fn run_filter(
+    ffmpeg: &Path,
+    input: &Path,
+    start: Duration,
+    length: Duration,
+    filter: &str,
+) -> Result<String, String> {
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostats", "-ss"])
+        .arg(format!("{:.3}", start.as_secs_f64()))
+        .arg("-t")
+        .arg(format!("{:.3}", length.as_secs_f64()))
+        .arg("-i")
+        .arg(input)
+        .args([
+            "-map", "0:v:0", "-an", "-sn", "-vf", filter, "-f", "null", "-",
+        ])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|e| format!("无法启动 ffmpeg {}: {}", ffmpeg.display(), e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr).into_owned();
+    if output.status.success() {
+        Ok(stderr)
+    } else {
+        Err(stderr.lines().last().unwrap_or("").trim().to_string())
+    }
+}
+
+/// 取 cropdetect 输出中最后一个 crop=w:h:x:y。整段都是黑帧时宽高为负数，返回 None
+pub fn parse_cropdetect(output: &str) -> Option<Crop> {
+    let line = output.lines().rev().find(|l| l.contains("crop="))?;
+    let value = line.rsplit("crop=").next()?.split_whitespace().next()?;
+    let numbers: Vec<i64> = value
+        .split(':')
+        .map(|n| n.parse().ok())
+        .collect::<Option<_>>()?;
+    match numbers[..] {
+        [w, h, x, y] if w > 0 && h > 0 && x >= 0 && y >= 0 => Some(Crop {
+            width: w as u32,
+            height: h as u32,
+            x: x as u32,
+            y: y as u32,
+        }),
+        _ => None,
+    }
+}
+
+/// 由各样本的检测结果确定裁剪区域，不可靠时返回 None：
+/// 取所有样本内容范围的并集，这样任何样本中出现过的画面都不会被裁掉（暗场样本的范围偏小，包含在并集中）；
+/// 有效样本不到一半、与并集一致的样本不到一半、裁剪后面积不到一半或裁掉的太少时都不裁剪
+pub fn stable_crop(detections: &[Option<Crop>], width: u32, height: u32) -> Option<Crop> {
+    let valid: Vec<Crop> = detections.iter().flatten().copied().collect();
+    if valid.is_empty() || (valid.len() as f64) < detections.len() as f64 * CROP_MIN_AGREEMENT {
+        return None;
+    }
+
+    let left = valid.iter().map(|c| c.x).min()?;
+    let top = valid.iter().map(|c| c.y).min()?;
+    let right = valid.iter().map(|c| c.x + c.width).max()?;
+    let bottom = valid.iter().map(|c| c.y + c.height).max()?;
+    let crop = Crop {
+        width: right - left,
+        height: bottom - top,
+        x: left,
+        y: top,
+    };
+
+    let agreeing = valid.iter().filter(|c| **c == crop).count();
+    if (agreeing as f64) < valid.len() as f64 * CROP_MIN_AGREEMENT {
+        return None;
+    }
+
+    // cropdetect 处理的是自动旋转后的画面，ffprobe 的宽高是旋转前的
+    let (width, height) = if right <= width && bottom <= height {
+        (width, height)
+    } else if right <= height && bottom <= width {
+        (height, width)
+    } else {
+        return None;
+    };
+
+    let area = crop.width as f64 * crop.height as f64;
+    if area < width as f64 * height as f64 * CROP_MIN_AREA
+        || (width - crop.width < CROP_MIN_PIXELS && height - crop.height < CROP_MIN_PIXELS)
+    {
+        return None;
+    }
+    Some(crop)
+}
+
+/// 解析 idet 滤镜结束时输出的 "Repeated Fields" 和 "Multi frame detection" 两行
+pub fn parse_idet(output: &str) -> Option<IdetCounts> {
+    // "Multi frame detection: TFF:     0 BFF:     0 Progressive:  1213 Undetermined:    33"
+    fn counts(line: &str) -> Vec<(&str, u64)> {
+        let mut result = Vec::new();
+        let mut tokens = line.split_whitespace().peekable();
+        while let Some(token) = tokens.next() {
+            if let Some(name) = token.strip_suffix(':')
+                && let Some(value) = tokens.peek().and_then(|v| v.parse().ok())
+            {
+                result.push((name, value));
+                tokens.next();
+            }
+        }
+        result
+    }
+    let value = |counts: &[(&str, u64)], name: &str| {
+        counts.iter().find(|(n, _)| *n == name).map(|(_, v)| *v)
+    };
+
+    let multi = output
+        .lines()
+        .rev()
+        .find(|l| l.contains("Multi frame detection:"))?;
+    let multi = counts(multi.split("Multi frame detection:").nth(1)?);
+    let mut result = IdetCounts {
+        interlaced: value(&multi, "TFF")? + value(&multi, "BFF")?,
+        progressive: value(&multi, "Progressive")?,
+        undetermined: value(&multi, "Undetermined")?,
+        ..Default::default()
+    };
+
+    if let Some(line) = output
+        .lines()
+        .rev()
+        .find(|l| l.contains("Repeated Fields:"))
+    {
+        let repeated = counts(line.split("Repeated Fields:").nth(1)?);
+        let (neither, top, bottom) = (
+            value(&repeated, "Neither")?,
+            value(&repeated, "Top")?,
+            value(&repeated, "Bottom")?,
+        );
+        result.repeated = top + bottom;
+        result.frames = neither + top + bottom;
+    }
+    Some(result)
+}
+
+/// 由各样本的 idet 统计判断扫描方式；能判断的帧太少时按逐行处理，不添加滤镜
+pub fn classify_scan(samples: &[IdetCounts]) -> Scan {
+    let total = samples
+        .iter()
+        .fold(IdetCounts::default(), |sum, c| IdetCounts {
+            interlaced: sum.interlaced + c.interlaced,
+            progressive: sum.progressive + c.progressive,
+            undetermined: sum.undetermined + c.undetermined,
+            repeated: sum.repeated + c.repeated,
+            frames: sum.frames + c.frames,
+        });
+
+    let determined = total.interlaced + total.progressive;
+    if determined < IDET_MIN_FRAMES {
+        return Scan::Progressive;
+    }
+    if total.interlaced as f64 >= determined as f64 * IDET_INTERLACED_RATIO {
+        // 胶片过带的内容每 5 帧有 2 帧带重复场，真正的隔行内容几乎没有
+        if total.frames > 0 && total.repeated as f64 >= total.frames as f64 * IDET_REPEATED_RATIO {
+            Scan::Telecined
+        } else {
+            Scan::Interlaced
+        }
+    } else {
+        Scan::Progressive
+    }
+}
+
+/// 扫描方式对应的去隔行滤镜，deinterlacer 为隔行内容使用的滤镜 (bwdif 或 yadif)
+pub fn deinterlace_filter(scan: Scan, deinterlacer: &str) -> Option<String> {
+    match scan {
+        Scan::Progressive => None,
+        // 每帧输出一帧，不改变帧率
+        Scan::Interlaced => Some(format!("{}=mode=send_frame", deinterlacer)),
+        // 按场重新配对还原逐行帧，剩下的隔行帧去隔行，再去掉重复帧还原为原来的帧率
+        Scan::Telecined => Some("fieldmatch,yadif=deint=interlaced,decimate".to_string()),
+    }
+}
+
+// 直接复制视频流的预设无法添加滤镜，音频预设不输出视频或只复制封面
+fn can_filter(preset: &Preset) -> bool {
+    !preset.audio && preset.video_encoder() != Some("copy")
+}
+
+/// 按分析结果和预设生成要添加的视频滤镜，按执行顺序排列
+pub fn video_filters(analysis: &Analysis, preset: &Preset) -> Vec<String> {
+    let mut filters = Vec::new();
+    let Some(video) = analysis.info.video_stream() else {
+        return filters;
+    };
+    let (mut width, mut height) = (video.width, video.height);
+    if !can_filter(preset) {
+        return filters;
+    }
+
+    // 去隔行放在最前面，之后的滤镜处理的都是逐行画面
+    if let Some(deinterlace) = &analysis.deinterlace {
+        filters.push(deinterlace.clone());
+    }
+
+    // 先裁掉黑边，缩放按裁剪后的尺寸计算
+    if let Some(crop) = analysis.crop {
+        filters.push(format!(
+            "crop={}:{}:{}:{}",
+            crop.width, crop.height, crop.x, crop.y
+        ));
+        (width, height) = (Some(crop.width), Some(crop.height));
+    }
+
+    // 分辨率上限为 16:9 的画面（1080 即 1920x1080，竖屏为 1080x1920），超出时等比缩小到其中，
+    // 宽银幕等更宽的画面按宽度限制；iw/ih 为自动旋转后的尺寸
+    if let (Some(max_res), Some(width), Some(height)) = (preset.max_res, width, height) {
+        let max_long = (max_res * 16).div_ceil(9).next_multiple_of(2);
+        if width.max(height) > max_long || width.min(height) > max_res {
+            filters.push(format!(
+                "scale=w='if(gte(iw,ih),{0},{1})':h='if(gte(iw,ih),{1},{0})':force_original_aspect_ratio=decrease:force_divisible_by=2",
+                max_long, max_res
+            ));
+        }
+    }
+
+    if let (Some(max_fps), Some(frame_rate)) = (preset.max_fps, video.frame_rate)
+        && frame_rate > max_fps + 0.01
+    {
+        filters.push(format!("fps={}", max_fps));
+    }
+
+    // 色调映射放在缩放之后，处理的像素更少
+    if let (Some(_), Some(algorithm)) = (&analysis.hdr, &preset.tonemap) {
+        filters.push(tonemap_filter(algorithm));
+    }
+
+    filters
+}
+
+/// 把滤镜加到预设参数中已有的 -vf 之前，没有 -vf 时加上。使用 -filter_complex 的预设无法合并，返回错误
+pub fn with_video_filters(preset: &Preset, filters: &[String]) -> Result<Preset, String> {
+    with_filters(preset, &["-vf", "-filter:v"], filters, true)
+}
+
+/// 把滤镜加到预设参数中已有的 -af 之后（在预设自带的处理之后执行），没有 -af 时加上
+pub fn with_audio_filters(preset: &Preset, filters: &[String]) -> Result<Preset, String> {
+    with_filters(preset, &["-af", "-filter:a"], filters, false)
+}
+
+fn with_filters(
+    preset: &Preset,
+    options: &[&str],
+    filters: &[String],
+    before_existing: bool,
+) -> Result<Preset, String> {
+    if filters.is_empty() {
+        return Ok(preset.clone());
+    }
+
+    let mut tokens: Vec<String> = preset.args().map(|s| s.to_string()).collect();
+    if tokens
+        .iter()
+        .any(|t| t == "-filter_complex" || t == "-lavfi")
+    {
+        return Err("预设使用了 -filter_complex，无法添加滤镜".to_string());
+    }
+
+    let chain = filters.join(",");
+    match tokens.iter().rposition(|t| options.contains(&t.as_str())) {
+        Some(i) if i + 1 < tokens.len() => {
+            tokens[i + 1] = if before_existing {
+                format!("{},{}", chain, tokens[i + 1])
+            } else {
+                format!("{},{}", tokens[i + 1], chain)
+            };
+        }
+        _ => tokens.extend([options[0].to_string(), chain]),
+    }
+
+    let mut preset = preset.clone();
+    preset.params = tokens.join(" ");
+    Ok(preset)
+}
@@ -0,0 +1,23 @@
+use std::path::Path;
+
+use crate::discover::{AUDIO_EXTS, is_video_file};
+use crate::preset::Preset;
+
+/// 能保存封面图片的输出封装
+const COVER_EXTENSIONS: [&str; 3] = ["mp3", "m4a", "flac"];
+
+/// 音频预设的流选择，放在预设参数之前：
+/// 音频文件的视频流是封面图片，输出封装支持时原样复制并标记为封面；视频文件只提取音频
+pub fn with_audio_streams(preset: &Preset, input: &Path) -> Preset {
+    let keep_cover =
+        is_video_file(input, &AUDIO_EXTS) && COVER_EXTENSIONS.contains(&preset.extension());
+    let streams = if keep_cover {
+        "-map 0:a -map 0:v? -c:v copy -disposition:v attached_pic"
+    } else {
+        "-vn -sn -dn"
+    };
+
+    let mut preset = preset.clone();
+    preset.params = format!("{} {}", streams, preset.params);
+    preset
+}
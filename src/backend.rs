@@ -0,0 +1,166 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::job::{Job, JobFailure, JobResult, JobStats};
+use crate::progress::Progress;
+use crate::transcoder::{Control, Event};
+
+/// 转码后端：执行一个转码任务，通过回调报告事件并返回结果。
+/// 默认实现是调用 ffmpeg 的 `Transcoder`，测试时可换成 `FakeBackend`
+pub trait Backend {
+    /// 回调返回 Control::Abort 时应结束转码、删除未完成的输出文件并返回 JobResult::Cancelled
+    fn run(&self, job: &Job, on_event: &mut dyn FnMut(Event) -> Control) -> JobResult;
+}
+
+/// 按脚本依次返回结果的假后端，不需要 ffmpeg，用于测试批量转码、日志等逻辑。
+/// 克隆出的副本共享同一份脚本和任务记录
+#[derive(Clone, Debug, Default)]
+pub struct FakeBackend {
+    state: Arc<Mutex<FakeState>>,
+}
+
+#[derive(Debug, Default)]
+struct FakeState {
+    script: VecDeque<FakeRun>,
+    jobs: Vec<Job>,
+}
+
+/// 假后端的一次转码：依次报告的进度以及最终结果
+#[derive(Clone, Debug)]
+pub struct FakeRun {
+    /// 视频时长，None 表示像 ffmpeg 没有输出 Duration 那样
+    pub total: Option<Duration>,
+    /// 依次报告的进度时间点
+    pub steps: Vec<Duration>,
+    pub outcome: FakeOutcome,
+}
+
+#[derive(Clone, Debug)]
+pub enum FakeOutcome {
+    /// 成功，并写入指定大小（字节）的输出文件
+    Success { output_size: u64 },
+    /// 以指定的退出码失败，失败原因由 stderr 的内容判断，与真正的 ffmpeg 相同
+    Exit { code: i32, stderr: Vec<String> },
+    /// 直接返回给定的失败
+    Failure(JobFailure),
+}
+
+impl FakeRun {
+    /// 成功转码，进度依次为 25%、50%、75%、100%
+    pub fn success(total: Duration, output_size: u64) -> Self {
+        FakeRun {
+            total: Some(total),
+            steps: (1..=4).map(|i| total * i / 4).collect(),
+            outcome: FakeOutcome::Success { output_size },
+        }
+    }
+
+    /// 转码到一半时 ffmpeg 以指定退出码和输出内容失败
+    pub fn exit(total: Duration, code: i32, stderr: &[&str]) -> Self {
+        FakeRun {
+            total: Some(total),
+            steps: vec![total / 4, total / 2],
+            outcome: FakeOutcome::Exit {
+                code,
+                stderr: stderr.iter().map(|s| s.to_string()).collect(),
+            },
+        }
+    }
+
+    /// 没有任何进度直接失败
+    pub fn failure(failure: JobFailure) -> Self {
+        FakeRun {
+            total: None,
+            steps: Vec::new(),
+            outcome: FakeOutcome::Failure(failure),
+        }
+    }
+}
+
+impl FakeBackend {
+    pub fn new() -> Self {
+        FakeBackend::default()
+    }
+
+    /// 追加一次转码的脚本，按追加顺序使用
+    pub fn push(&self, run: FakeRun) -> &Self {
+        self.state.lock().unwrap().script.push_back(run);
+        self
+    }
+
+    /// 至今收到的所有转码任务
+    pub fn jobs(&self) -> Vec<Job> {
+        self.state.lock().unwrap().jobs.clone()
+    }
+
+    /// 尚未使用的脚本数
+    pub fn remaining(&self) -> usize {
+        self.state.lock().unwrap().script.len()
+    }
+}
+
+impl Backend for FakeBackend {
+    fn run(&self, job: &Job, on_event: &mut dyn FnMut(Event) -> Control) -> JobResult {
+        let run = {
+            let mut state = self.state.lock().unwrap();
+            state.jobs.push(job.clone());
+            state.script.pop_front()
+        };
+        let Some(run) = run else {
+            return JobResult::Failed(JobFailure {
+                reason: "FakeBackend 的脚本已用完".to_string(),
+                retryable: false,
+                fallback: false,
+                stderr_tail: Vec::new(),
+            });
+        };
+
+        let result = play(job, run, on_event);
+        on_event(Event::Exited);
+        result
+    }
+}
+
+// 按脚本报告启动和进度，再返回脚本中的结果
+fn play(job: &Job, run: FakeRun, on_event: &mut dyn FnMut(Event) -> Control) -> JobResult {
+    if on_event(Event::Started { pid: 0 }) == Control::Abort {
+        return JobResult::Cancelled;
+    }
+
+    let elapsed = Duration::from_secs(1);
+    for step in run.steps.iter() {
+        let progress = Progress {
+            current_time: *step,
+            total: run.total,
+            speed_str: "1.00x".to_string(),
+            elapsed,
+            pass: None,
+        };
+        if on_event(Event::Progress(&progress)) == Control::Abort {
+            let _ = std::fs::remove_file(&job.output);
+            return JobResult::Cancelled;
+        }
+    }
+
+    match run.outcome {
+        FakeOutcome::Success { output_size } => {
+            if let Err(e) = std::fs::write(&job.output, vec![0u8; output_size as usize]) {
+                return JobResult::Failed(JobFailure::new(format!(
+                    "无法写入输出文件 {}: {}",
+                    job.output.display(),
+                    e
+                )));
+            }
+            JobResult::Success(JobStats {
+                media_duration: run.total,
+                elapsed,
+            })
+        }
+        FakeOutcome::Exit { code, stderr } => JobResult::Failed(JobFailure::from_exit(
+            format!("ffmpeg 退出码 {}", code),
+            stderr,
+        )),
+        FakeOutcome::Failure(failure) => JobResult::Failed(failure),
+    }
+}
@@ -0,0 +1,845 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::analyze::{
+    Analysis, Analyzer, Scan, video_filters, with_audio_filters, with_video_filters,
+};
+use crate::audio::with_audio_streams;
+use crate::backend::Backend;
+use crate::concat::{Concat, ConcatMethod, Concatenator, concat_output_path, group_by_folder};
+use crate::crfsearch::{CrfSearch, CrfSearchError, QualityTarget, with_crf};
+use crate::hdr::{supports_hdr, with_hdr};
+use crate::job::{
+    Job, JobFailure, JobResult, JobStats, copy_file_times, output_path_with_extension,
+};
+use crate::log::{CONTINUATION_INDENT, Logger};
+use crate::loudnorm::{self, LoudnessTarget, normalize_filters};
+use crate::nogain::{NoGainAction, NoGainPolicy};
+use crate::preset::Preset;
+use crate::progress::{format_duration, format_size};
+use crate::remux::{Remuxer, copy_all_streams};
+use crate::replace::{ReplaceOriginal, Replacement, log_record};
+use crate::transcoder::{Control, Event};
+use crate::trim::{self, Trim};
+use crate::verify::Verifier;
+
+/// 批量转码：依次处理每个文件，失败时按重试次数和备用预设重试，并写入日志
+pub struct Batch {
+    pub presets: Vec<Preset>,
+    /// 每个预设使用的 ffmpeg，None 表示该预设不可用
+    pub preset_ffmpeg: Vec<Option<PathBuf>>,
+    /// 转码失败后使用同一预设重试的次数，用完后再改用备用预设
+    pub retries: u32,
+    /// 执行转码的后端，通常为 `Transcoder`
+    pub backend: Box<dyn Backend>,
+    pub logger: Logger,
+    /// 转码成功后校验输出文件，未通过按失败处理；None 表示不校验
+    pub verifier: Option<Verifier>,
+    /// 输出比原文件小得不够多时的处理，None 表示总是保留输出
+    pub no_gain: Option<NoGainPolicy>,
+    /// 转码前分析输入文件并添加滤镜（分辨率和帧率上限、裁剪黑边、去隔行），None 表示不分析
+    pub analyzer: Option<Analyzer>,
+    /// 为设置了目标画质的预设搜索 CRF，None 表示不搜索，直接使用预设中的参数
+    pub crf_search: Option<CrfSearch>,
+    /// 为重新封装的预设检查各个流与输出封装的兼容性，None 表示不检查，复制所有流
+    pub remuxer: Option<Remuxer>,
+    /// 所有文件只转码这一段，None 表示整个文件；输入文件旁的剪辑设置文件优先
+    pub trim: Option<Trim>,
+    /// 合并模式：同一文件夹中的文件按顺序合并为一个输出，None 表示逐个转码
+    pub concat: Option<Concatenator>,
+    /// 转码成功后用输出替换原文件，None 表示保留原文件；应同时设置 verifier，只替换校验通过的输出
+    pub replace: Option<ReplaceOriginal>,
+    /// 全部文件处理完后执行的关机操作，None 表示不关机；批量转码被中止时不执行
+    pub shutdown: Option<Box<dyn Fn() -> std::io::Result<()>>>,
+}
+
+/// 接收批量转码过程中的通知，例如在控制台显示进度
+pub trait BatchObserver {
+    fn file_started(&mut self, _index: usize, _total: usize, _input: &Path) {}
+
+    /// 转码过程中的事件，返回 Control::Abort 则中止当前文件并停止整个批量转码
+    fn event(&mut self, _job: &Job, _event: Event) -> Control {
+        Control::Continue
+    }
+
+    /// 一次尝试失败，next 为接下来重试使用的预设，None 表示不再重试
+    fn attempt_failed(&mut self, _failure: &JobFailure, _next: Option<&Preset>) {}
+
+    fn file_finished(&mut self, _report: &FileReport) {}
+
+    /// 每个文件处理完后询问是否停止批量转码
+    fn stop_requested(&mut self) -> bool {
+        false
+    }
+}
+
+/// 一个文件的处理结果
+#[derive(Clone, Debug)]
+pub struct FileReport {
+    pub input: PathBuf,
+    /// 最后一次尝试的输出文件
+    pub output: PathBuf,
+    /// 最后一次尝试使用的预设（在预设列表中的下标）
+    pub preset: usize,
+    pub attempts: u32,
+    pub outcome: FileOutcome,
+    /// 用输出替换原文件的结果，没有替换时为 None
+    pub replaced: Option<Result<Replacement, String>>,
+}
+
+#[derive(Clone, Debug)]
+pub enum FileOutcome {
+    Converted {
+        stats: JobStats,
+        /// 输入和输出文件的大小（字节），读取不到时为 None
+        sizes: Option<(u64, u64)>,
+    },
+    /// 转码成功但体积减少不足，输出已按 action 处理
+    NoGain {
+        stats: JobStats,
+        sizes: (u64, u64),
+        action: NoGainAction,
+        /// 最终保留在输出位置的文件，删除了输出或处理失败时为 None
+        kept: Option<PathBuf>,
+    },
+    Failed(JobFailure),
+    Cancelled,
+}
+
+impl FileOutcome {
+    /// 输入和输出文件的大小（字节）
+    pub fn sizes(&self) -> Option<(u64, u64)> {
+        match self {
+            FileOutcome::Converted { sizes, .. } => *sizes,
+            FileOutcome::NoGain { sizes, .. } => Some(*sizes),
+            _ => None,
+        }
+    }
+}
+
+impl FileReport {
+    /// 输出相对输入的体积变化百分比，负数表示变小
+    pub fn size_change_percent(&self) -> Option<f64> {
+        self.outcome
+            .sizes()
+            .map(|(input_size, output_size)| size_change_percent(input_size, output_size))
+    }
+}
+
+fn size_change_percent(input_size: u64, output_size: u64) -> f64 {
+    if input_size > 0 {
+        100.0 * (output_size as f64 - input_size as f64) / input_size as f64
+    } else {
+        0.0
+    }
+}
+
+/// 整个批量转码的结果
+#[derive(Clone, Debug, Default)]
+pub struct BatchReport {
+    pub files: Vec<FileReport>,
+    /// 被用户中止或要求在当前文件完成后停止
+    pub stopped: bool,
+    /// 因停止而未处理的文件数
+    pub remaining: usize,
+    pub shutdown: ShutdownStatus,
+}
+
+/// 批量转码结束后的关机情况
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub enum ShutdownStatus {
+    #[default]
+    NotRequested,
+    /// 批量转码被中止，取消关机
+    Cancelled,
+    Scheduled,
+    Failed(String),
+}
+
+impl BatchReport {
+    pub fn converted(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::Converted { .. }))
+    }
+
+    pub fn failed(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::Failed(_)))
+    }
+
+    pub fn cancelled(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::Cancelled))
+    }
+
+    pub fn no_gain(&self) -> usize {
+        self.count(|o| matches!(o, FileOutcome::NoGain { .. }))
+    }
+
+    /// 汇总，例如 "共 3 个文件: 成功 2 个，无收益 1 个，失败 0 个，已取消 0 个  1.20 GB -> 512.00 MB (-58.3%)"
+    pub fn summary(&self) -> String {
+        let mut summary = format!(
+            "共 {} 个文件: 成功 {} 个，无收益 {} 个，失败 {} 个，已取消 {} 个",
+            self.files.len() + self.remaining,
+            self.converted(),
+            self.no_gain(),
+            self.failed(),
+            self.cancelled()
+        );
+        if self.remaining > 0 {
+            summary.push_str(&format!("，未处理 {} 个", self.remaining));
+        }
+
+        // 只统计保留了转码输出的文件
+        let (input_total, output_total) = self
+            .files
+            .iter()
+            .filter_map(|r| match &r.outcome {
+                FileOutcome::Converted { sizes, .. } => *sizes,
+                _ => None,
+            })
+            .fold((0, 0), |(i, o), (input, output)| (i + input, o + output));
+        if input_total > 0 {
+            summary.push_str(&format!(
+                "  {} -> {} ({:.1}%)",
+                format_size(input_total as f64),
+                format_size(output_total as f64),
+                size_change_percent(input_total, output_total)
+            ));
+        }
+
+        summary
+    }
+
+    fn count(&self, f: impl Fn(&FileOutcome) -> bool) -> usize {
+        self.files.iter().filter(|r| f(&r.outcome)).count()
+    }
+}
+
+impl Batch {
+    pub fn run(
+        &self,
+        inputs: &[PathBuf],
+        preset_index: usize,
+        observer: &mut dyn BatchObserver,
+    ) -> BatchReport {
+        let mut report = BatchReport::default();
+        let groups = match &self.concat {
+            Some(_) => group_by_folder(inputs),
+            None => inputs.iter().map(|input| vec![input.clone()]).collect(),
+        };
+        let total = groups.len();
+
+        for (i, group) in groups.iter().enumerate() {
+            observer.file_started(i + 1, total, &group[0]);
+
+            let file_report = self.convert_group(group, preset_index, observer);
+            observer.file_finished(&file_report);
+
+            let cancelled = matches!(file_report.outcome, FileOutcome::Cancelled);
+            report.files.push(file_report);
+
+            if cancelled || observer.stop_requested() {
+                report.stopped = true;
+                report.remaining = total - i - 1;
+                break;
+            }
+        }
+
+        if !report.files.is_empty() {
+            self.logger.log(&format!("汇总: {}", report.summary()));
+        }
+
+        if let Some(shutdown) = &self.shutdown {
+            report.shutdown = if report.stopped {
+                ShutdownStatus::Cancelled
+            } else {
+                match shutdown() {
+                    Ok(()) => {
+                        self.logger.log("已计划关机");
+                        ShutdownStatus::Scheduled
+                    }
+                    Err(e) => {
+                        self.logger.log(&format!("无法计划关机: {}", e));
+                        ShutdownStatus::Failed(e.to_string())
+                    }
+                }
+            };
+        }
+
+        report
+    }
+
+    // 合并模式中一个文件夹有多个文件时合并转码，否则转码单个文件
+    fn convert_group(
+        &self,
+        inputs: &[PathBuf],
+        preset_index: usize,
+        observer: &mut dyn BatchObserver,
+    ) -> FileReport {
+        let input = &inputs[0];
+        let Some(concatenator) = self.concat.as_ref().filter(|_| inputs.len() > 1) else {
+            return self.convert_file(input, None, preset_index, observer);
+        };
+
+        let list =
+            std::env::temp_dir().join(format!("ffmpegConvert_concat_{}.txt", std::process::id()));
+        let concat = match concatenator.prepare(inputs, &list) {
+            Ok(concat) => concat,
+            Err(e) => {
+                let preset = &self.presets[preset_index];
+                let failure = JobFailure::new(format!("无法合并: {}", e));
+                self.logger.log(&format!("输入: {}", input.display()));
+                self.logger.log(&format!("失败: {}", failure.reason));
+                observer.attempt_failed(&failure, None);
+                return FileReport {
+                    input: input.clone(),
+                    output: concat_output_path(input, &preset.subfix, preset.extension()),
+                    preset: preset_index,
+                    attempts: 1,
+                    outcome: FileOutcome::Failed(failure),
+                    replaced: None,
+                };
+            }
+        };
+
+        let report = self.convert_file(input, Some(&concat), preset_index, observer);
+        let _ = std::fs::remove_file(&list);
+        report
+    }
+
+    // 依次尝试所选预设及其备用预设，每个预设最多尝试 1 + retries 次。concat 不为 None 时 input 为合并的第一个文件
+    fn convert_file(
+        &self,
+        input: &Path,
+        concat: Option<&Concat>,
+        preset_index: usize,
+        observer: &mut dyn BatchObserver,
+    ) -> FileReport {
+        let output_path = |preset: &Preset| match concat {
+            Some(_) => concat_output_path(input, &preset.subfix, preset.extension()),
+            None => output_path_with_extension(input, &preset.subfix, preset.extension()),
+        };
+        let mut preset_index = preset_index;
+        let mut tried_presets = vec![preset_index];
+        let mut preset_attempt = 1;
+        let mut attempt = 1;
+        let mut chosen_presets: HashMap<usize, Preset> = HashMap::new();
+        let mut analysis = None;
+
+        // 合并时不剪辑
+        let trim = match trim::read_sidecar(input) {
+            _ if concat.is_some() => None,
+            Ok(Some(trim)) => Some(trim),
+            Ok(None) => self.trim,
+            Err(e) => {
+                let preset = &self.presets[preset_index];
+                let failure = JobFailure {
+                    reason: format!("剪辑设置无效: {}", e),
+                    retryable: false,
+                    fallback: false,
+                    stderr_tail: Vec::new(),
+                };
+                self.logger.log(&format!("输入: {}", input.display()));
+                self.logger.log(&format!("失败: {}", failure.reason));
+                observer.attempt_failed(&failure, None);
+                return FileReport {
+                    input: input.to_path_buf(),
+                    output: output_path(preset),
+                    preset: preset_index,
+                    attempts: 1,
+                    outcome: FileOutcome::Failed(failure),
+                    replaced: None,
+                };
+            }
+        };
+
+        loop {
+            let preset = &self.presets[preset_index];
+            let ffmpeg = self.preset_ffmpeg[preset_index]
+                .clone()
+                .unwrap_or_else(|| PathBuf::from(crate::ffmpeg::executable_name("ffmpeg")));
+            self.logger.log(&format!("输入: {}", input.display()));
+            if let Some(concat) = concat {
+                self.logger.log(&format!(
+                    "合并: {} 个文件，{}",
+                    concat.inputs.len(),
+                    match concat.method {
+                        ConcatMethod::Demuxer { .. } => "编码格式一致，直接拼接",
+                        ConcatMethod::Filter { .. } => "编码格式或分辨率不一致，解码后拼接",
+                    }
+                ));
+            }
+            if let Some(trim) = trim {
+                self.logger.log(&format!("剪辑: {}", trim));
+            }
+
+            // 按输入文件添加滤镜、搜索 CRF，同一文件重试时沿用结果
+            let job_preset = match chosen_presets.get(&preset_index) {
+                Some(chosen) => chosen.clone(),
+                None => {
+                    let mut job = Job::new(input, PathBuf::new(), preset.clone(), &ffmpeg);
+                    job.trim = trim;
+                    job.concat = concat.cloned();
+                    let Some(chosen) = self.prepare_preset(&job, &mut analysis, observer) else {
+                        let output = output_path(preset);
+                        self.logger.log(&format!("已取消: {}", output.display()));
+                        return FileReport {
+                            input: job.input,
+                            output,
+                            preset: preset_index,
+                            attempts: attempt,
+                            outcome: FileOutcome::Cancelled,
+                            replaced: None,
+                        };
+                    };
+                    chosen_presets.insert(preset_index, chosen.clone());
+                    chosen
+                }
+            };
+            let mut job = Job::new(input, output_path(preset), job_preset, ffmpeg);
+            job.trim = trim;
+            // 解码后拼接时滤镜要放进 concat 滤镜图
+            if let Some(concat) = concat {
+                let (concat, preset) = concat.with_preset_filters(&job.preset);
+                job.concat = Some(concat);
+                job.preset = preset;
+            }
+
+            let result = self
+                .backend
+                .run(&job, &mut |event| observer.event(&job, event));
+
+            // 校验未通过的输出文件不可靠，删除后按失败处理（可以重试）
+            let result = match (result, &self.verifier) {
+                (JobResult::Success(stats), Some(verifier)) => match verifier.verify(&job) {
+                    Ok(()) => JobResult::Success(stats),
+                    Err(reason) => {
+                        let _ = std::fs::remove_file(&job.output);
+                        JobResult::Failed(JobFailure::new(reason))
+                    }
+                },
+                (result, _) => result,
+            };
+
+            let failure = match result {
+                JobResult::Success(stats) => {
+                    let sizes = input_size(&job)
+                        .and_then(|i| std::fs::metadata(&job.output).map(|o| (i, o.len())))
+                        .ok();
+                    self.log_success(&job, &stats, sizes);
+                    if attempt > 1 {
+                        self.logger.log(&format!(
+                            "第 {} 次尝试成功，使用预设: {}",
+                            attempt, preset.description
+                        ));
+                    }
+                    // 重新封装和合并不是为了减小体积，不检查收益
+                    let outcome = match sizes {
+                        Some(sizes) if !job.preset.remux && job.concat.is_none() => {
+                            self.check_gain(&job, stats, sizes)
+                        }
+                        _ => FileOutcome::Converted { stats, sizes },
+                    };
+                    self.keep_file_times(&job, &outcome);
+                    // 只转码了一段或合并了多个文件的输出不能替换原文件
+                    let replaced = match (&outcome, &self.replace) {
+                        (FileOutcome::Converted { .. }, Some(replace))
+                            if job.trim.is_none() && job.concat.is_none() =>
+                        {
+                            Some(self.replace_original(replace, &job))
+                        }
+                        _ => None,
+                    };
+                    return FileReport {
+                        input: job.input,
+                        output: job.output,
+                        preset: preset_index,
+                        attempts: attempt,
+                        outcome,
+                        replaced,
+                    };
+                }
+                JobResult::Cancelled => {
+                    self.logger
+                        .log(&format!("已取消: {}", job.output.display()));
+                    return FileReport {
+                        input: job.input,
+                        output: job.output,
+                        preset: preset_index,
+                        attempts: attempt,
+                        outcome: FileOutcome::Cancelled,
+                        replaced: None,
+                    };
+                }
+                JobResult::Failed(failure) => failure,
+            };
+
+            let mut log_content = format!("失败: {} ({})", job.output.display(), failure.reason);
+            for line in failure.stderr_tail.iter() {
+                log_content.push('\n');
+                log_content.push_str(CONTINUATION_INDENT);
+                log_content.push_str("| ");
+                log_content.push_str(line);
+            }
+            self.logger.log(&log_content);
+
+            // 同一预设重试无济于事时直接改用备用预设，与预设无关的失败不再尝试
+            let next = if failure.retryable && preset_attempt <= self.retries {
+                preset_attempt += 1;
+                Some(preset_index)
+            } else if !failure.fallback {
+                None
+            } else {
+                match preset.fallback {
+                    Some(i) if !tried_presets.contains(&i) && self.preset_ffmpeg[i].is_some() => {
+                        tried_presets.push(i);
+                        preset_attempt = 1;
+                        Some(i)
+                    }
+                    _ => None,
+                }
+            };
+
+            observer.attempt_failed(&failure, next.map(|i| &self.presets[i]));
+
+            let Some(next) = next else {
+                return FileReport {
+                    input: job.input,
+                    output: job.output,
+                    preset: preset_index,
+                    attempts: attempt,
+                    outcome: FileOutcome::Failed(failure),
+                    replaced: None,
+                };
+            };
+
+            self.logger.log(&format!(
+                "第 {} 次尝试失败，重试使用预设: {}",
+                attempt, self.presets[next].description
+            ));
+            preset_index = next;
+            attempt += 1;
+        }
+    }
+
+    // 体积减少不足 min_saving% 时按设置删除输出或改用原文件，并记入日志
+    fn check_gain(&self, job: &Job, stats: JobStats, sizes: (u64, u64)) -> FileOutcome {
+        let change = size_change_percent(sizes.0, sizes.1);
+        let Some(policy) = self.no_gain.filter(|p| p.is_no_gain(change)) else {
+            return FileOutcome::Converted {
+                stats,
+                sizes: Some(sizes),
+            };
+        };
+
+        let (kept, result) = match policy.apply(job) {
+            Ok(kept) => (kept, policy.action.description().to_string()),
+            Err(e) => (None, e),
+        };
+        self.logger.log(&format!(
+            "无收益: {} (体积变化 {:.1}%，要求至少减少 {}%)，{}",
+            job.output.display(),
+            change,
+            policy.min_saving,
+            result
+        ));
+
+        FileOutcome::NoGain {
+            stats,
+            sizes,
+            action: policy.action,
+            kept,
+        }
+    }
+
+    /// 按批量转码的流程为任务准备预设：分析输入并添加滤镜、搜索 CRF、处理 HDR 和响度、生成重新封装计划；
+    /// CRF 搜索被中止时返回 None。自检用它让测试片段经过与正常转码相同的处理
+    pub fn prepare(&self, job: &Job, observer: &mut dyn BatchObserver) -> Option<Preset> {
+        self.prepare_preset(job, &mut None, observer)
+    }
+
+    // 为当前文件准备预设：按分析结果添加滤镜，再搜索 CRF；搜索被中止时返回 None。
+    // 合并时分析第一个文件、从中截取 CRF 搜索的样本，响度按合并后的声音测量。
+    // analysis 缓存同一文件的分析结果，分析失败时为 Some(None)
+    fn prepare_preset(
+        &self,
+        job: &Job,
+        analysis: &mut Option<Option<Analysis>>,
+        observer: &mut dyn BatchObserver,
+    ) -> Option<Preset> {
+        // 重新封装只在直接拼接时能复制流，解码后拼接时按预设参数转码
+        if job.preset.remux {
+            return Some(match job.concat.as_ref().map(|concat| &concat.method) {
+                Some(ConcatMethod::Filter { .. }) => job.preset.clone(),
+                _ => self.plan_remux(job),
+            });
+        }
+
+        let mut preset = job.preset.clone();
+        if preset.audio {
+            preset = with_audio_streams(&preset, &job.input);
+        }
+        let mut filters = Vec::new();
+        let mut hdr = None;
+
+        if let Some(analyzer) = &self.analyzer
+            && analyzer.needed(&preset)
+        {
+            let analysis = analysis.get_or_insert_with(|| {
+                match analyzer.analyze(&job.ffmpeg, &job.input, job.trim) {
+                    Ok(mut analysis) => {
+                        // 合并时分析的是第一个文件，其他文件的黑边可能不同，裁剪会切掉画面
+                        if job.concat.is_some() && analysis.crop.take().is_some() {
+                            self.logger.log("合并的各文件黑边可能不同，不裁剪");
+                        }
+                        if let Some(crop) = analysis.crop {
+                            self.logger.log(&format!("检测到黑边: 裁剪为 {}", crop));
+                        }
+                        if analysis.scan != Scan::Progressive {
+                            self.logger
+                                .log(&format!("检测到{}，添加去隔行滤镜", analysis.scan.name()));
+                        }
+                        if let Some(hdr) = &analysis.hdr {
+                            self.logger.log(&format!("检测到 {} 视频", hdr.name()));
+                        }
+                        Some(analysis)
+                    }
+                    Err(e) => {
+                        self.logger.log(&format!("分析失败: {}，不添加滤镜", e));
+                        None
+                    }
+                }
+            });
+            if let Some(analysis) = analysis {
+                hdr = analysis.hdr.clone();
+                filters = video_filters(analysis, &preset);
+                if let Err(e) = with_video_filters(&preset, &filters) {
+                    self.logger.log(&format!("{}，不添加滤镜", e));
+                    filters.clear();
+                } else if !filters.is_empty() {
+                    self.logger.log(&format!("添加滤镜: {}", filters.join(",")));
+                }
+            }
+        }
+
+        // CRF 搜索的样本截取时就加上滤镜，样本转码用不带滤镜的预设，这样输出与样本的画面一致便于比较
+        if let (Some(target), Some(search)) = (preset.quality_target, &self.crf_search) {
+            let mut search_job = Job::new(&job.input, PathBuf::new(), preset, &job.ffmpeg);
+            search_job.trim = job.trim;
+            preset = self.choose_crf(search, &search_job, target, &filters, observer)?;
+        }
+
+        let mut preset = with_video_filters(&preset, &filters).unwrap_or(preset);
+
+        // 不转为 SDR 时保留 HDR 的色彩参数和元数据，否则编码器按 SDR 标记输出，画面发灰
+        if let Some(hdr) = hdr
+            && preset.tonemap.is_none()
+            && preset.video_encoder().is_some_and(supports_hdr)
+        {
+            self.logger
+                .log(&format!("保留 {} 色彩参数和元数据", hdr.name()));
+            preset = with_hdr(&preset, &hdr);
+        }
+
+        if let Some(target) = preset.loudnorm {
+            preset = self.normalize_loudness(job, preset, target);
+        }
+        Some(preset)
+    }
+
+    // 重新封装不加滤镜：按输入的各个流与输出封装的兼容性决定复制还是转码，并记入日志
+    fn plan_remux(&self, job: &Job) -> Preset {
+        let Some(remuxer) = &self.remuxer else {
+            return copy_all_streams(&job.preset);
+        };
+        match remuxer.plan(&job.preset, &job.input) {
+            Ok(plan) => {
+                self.logger.log(&format!("重新封装: {}", plan.summary()));
+                plan.apply(&job.preset)
+            }
+            Err(e) => {
+                self.logger
+                    .log(&format!("无法读取流信息: {}，复制所有流", e));
+                copy_all_streams(&job.preset)
+            }
+        }
+    }
+
+    // 第一遍测量响度并记入日志，返回加上标准化滤镜的预设；测量失败时不调整音量
+    fn normalize_loudness(&self, job: &Job, preset: Preset, target: LoudnessTarget) -> Preset {
+        let audio_filter = preset.option_value(&["-af", "-filter:a"]);
+        let measured = match &job.concat {
+            Some(concat) => loudnorm::measure_concat(&job.ffmpeg, concat, audio_filter, target),
+            None => loudnorm::measure(&job.ffmpeg, &job.input, job.trim, audio_filter, target),
+        };
+        match measured {
+            Ok((measured, sample_rate)) => {
+                self.logger.log(&format!(
+                    "响度: {:.1} LUFS，真峰值 {:.1} dBTP，响度范围 {:.1} LU，{}调整到 {} LUFS",
+                    measured.integrated,
+                    measured.true_peak,
+                    measured.range,
+                    if target.linear_possible(&measured) {
+                        "线性"
+                    } else {
+                        "动态"
+                    },
+                    target.integrated
+                ));
+                let filters = normalize_filters(target, &measured, sample_rate);
+                match with_audio_filters(&preset, &filters) {
+                    Ok(normalized) => normalized,
+                    Err(e) => {
+                        self.logger.log(&format!("{}，不调整音量", e));
+                        preset
+                    }
+                }
+            }
+            Err(e) => {
+                self.logger.log(&format!("响度测量失败: {}，不调整音量", e));
+                preset
+            }
+        }
+    }
+
+    // 搜索达到目标画质的 CRF 并记入日志，返回使用该 CRF 的预设；搜索失败时沿用预设中的 CRF，被中止时返回 None
+    fn choose_crf(
+        &self,
+        search: &CrfSearch,
+        job: &Job,
+        target: QualityTarget,
+        filters: &[String],
+        observer: &mut dyn BatchObserver,
+    ) -> Option<Preset> {
+        let name = target.metric.name();
+        match search.run(
+            self.backend.as_ref(),
+            job,
+            target,
+            filters,
+            &mut |job, event| observer.event(job, event),
+        ) {
+            Ok(choice) => {
+                self.logger.log(&format!(
+                    "CRF 搜索: {} CRF {} ({} {:.4}，目标 {})",
+                    if choice.met {
+                        "选择"
+                    } else {
+                        "达不到目标画质，使用最小的"
+                    },
+                    choice.crf,
+                    name,
+                    choice.score,
+                    target.value
+                ));
+                Some(with_crf(&job.preset, choice.crf))
+            }
+            Err(CrfSearchError::Cancelled) => None,
+            Err(CrfSearchError::Failed(e)) => {
+                self.logger
+                    .log(&format!("CRF 搜索失败: {}，使用预设中的参数", e));
+                Some(job.preset.clone())
+            }
+        }
+    }
+
+    // 保留下来的输出使用原文件的修改时间和创建时间，以免文件按时间排序时错乱
+    fn keep_file_times(&self, job: &Job, outcome: &FileOutcome) {
+        let kept = match outcome {
+            FileOutcome::Converted { .. } => Some(&job.output),
+            FileOutcome::NoGain { kept, .. } => kept.as_ref(),
+            _ => None,
+        };
+        if let Some(kept) = kept.filter(|p| **p != job.input)
+            && let Err(e) = copy_file_times(&job.input, kept)
+        {
+            self.logger
+                .log(&format!("无法复制文件时间到 {}: {}", kept.display(), e));
+        }
+    }
+
+    // 移走原文件，输出改用原文件名，并记入日志供撤销使用
+    fn replace_original(
+        &self,
+        replace: &ReplaceOriginal,
+        job: &Job,
+    ) -> Result<Replacement, String> {
+        let result = replace.replace(&job.input, &job.output);
+        match &result {
+            Ok(replacement) => self
+                .logger
+                .log(&log_record(&job.input, &job.output, replacement)),
+            Err(e) => self.logger.log(&format!(
+                "无法替换原文件: {} ({})，已保留输出",
+                job.input.display(),
+                e
+            )),
+        }
+        result
+    }
+
+    fn log_success(&self, job: &Job, stats: &JobStats, sizes: Option<(u64, u64)>) {
+        let mut log_content = format!("输出: {}\n{}", job.output.display(), CONTINUATION_INDENT);
+
+        let elapsed_secs = stats.elapsed.as_secs().max(1);
+        if let Some(total) = stats.media_duration {
+            log_content.push_str(&format!(
+                "视频时长:{} 速度:{:1.1}x 用时:{}    ",
+                format_duration(&total),
+                total.as_secs_f64() / (elapsed_secs as f64),
+                format_duration(&Duration::from_secs(elapsed_secs))
+            ));
+        } else {
+            log_content.push_str(&format!(
+                "用时:{}    ",
+                format_duration(&Duration::from_secs(elapsed_secs))
+            ));
+        }
+
+        // 文件体积对比，例如: 795.46 MB -> 389.43 MB (-51.0%)
+        if let Some((input_size, output_size)) = sizes {
+            log_content.push_str(&format!(
+                "{} -> {} ({:.1}%)",
+                format_size(input_size as f64),
+                format_size(output_size as f64),
+                size_change_percent(input_size, output_size)
+            ));
+        }
+
+        self.logger.log(&log_content);
+    }
+}
+
+// 输入文件的大小，合并时为各文件之和
+fn input_size(job: &Job) -> std::io::Result<u64> {
+    match &job.concat {
+        Some(concat) => concat
+            .inputs
+            .iter()
+            .map(|input| std::fs::metadata(input).map(|m| m.len()))
+            .sum(),
+        None => std::fs::metadata(&job.input).map(|m| m.len()),
+    }
+}
+
+/// 计划 30 秒后关机（其他平台为 1 分钟后）
+pub fn system_shutdown() -> std::io::Result<()> {
+    // shutdown.exe -s -t 30
+    #[cfg(windows)]
+    let status = std::process::Command::new("shutdown.exe")
+        .arg("-s")
+        .arg("-t")
+        .arg("30")
+        .status()?;
+
+    #[cfg(not(windows))]
+    let status = std::process::Command::new("shutdown")
+        .arg("-h")
+        .arg("+1")
+        .status()?;
+
+    if status.success() {
+        Ok(())
+    } else {
+        Err(std::io::Error::other(format!("shutdown {}", status)))
+    }
+}
@@ -0,0 +1,268 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+use crate::backend::Backend;
+use crate::batch::{BatchObserver, FileOutcome, FileReport};
+use crate::job::{Job, JobResult, output_path_for};
+use crate::media::MediaInfo;
+use crate::preset::Preset;
+use crate::quality::{QualityScores, measure};
+
+/// 预设对比：从样本视频中截取几段，用每个预设转码，比较速度、体积和画质
+pub struct Benchmark {
+    pub ffmpeg: PathBuf,
+    pub ffprobe: PathBuf,
+    /// 截取的段数，均匀分布在视频中
+    pub segments: usize,
+    pub segment_length: Duration,
+    /// 是否计算 VMAF（需要 ffmpeg 带有 libvmaf 滤镜）
+    pub vmaf: bool,
+    /// 存放截取片段和输出文件的临时目录，结束后删除
+    pub dir: PathBuf,
+}
+
+/// 一个预设的对比结果
+#[derive(Clone, Debug)]
+pub struct BenchmarkResult {
+    pub preset: usize,
+    /// 转码用时之和
+    pub encode_time: Duration,
+    /// 各段输出文件大小之和（字节）
+    pub output_size: u64,
+    pub scores: QualityScores,
+    pub error: Option<String>,
+}
+
+/// 对比的总体信息和每个预设的结果
+#[derive(Clone, Debug)]
+pub struct BenchmarkReport {
+    /// 截取的视频总时长
+    pub media_time: Duration,
+    /// 原视频中与截取片段等长部分的大小估计（字节），按原视频的平均码率计算
+    pub source_size: u64,
+    /// 原视频的时长，用于估计整个文件转码后的大小
+    pub source_duration: Duration,
+    pub results: Vec<BenchmarkResult>,
+}
+
+impl BenchmarkResult {
+    /// 速度，视频时长 / 转码用时
+    pub fn speed(&self, media_time: Duration) -> f64 {
+        media_time.as_secs_f64() / self.encode_time.as_secs_f64().max(0.001)
+    }
+}
+
+impl BenchmarkReport {
+    /// 输出相对原视频的体积比 (%)
+    pub fn size_ratio(&self, result: &BenchmarkResult) -> Option<f64> {
+        (self.source_size > 0).then(|| 100.0 * result.output_size as f64 / self.source_size as f64)
+    }
+
+    /// 按截取片段的码率估计整个文件转码后的大小（字节）
+    pub fn estimated_size(&self, result: &BenchmarkResult) -> u64 {
+        let ratio = self.source_duration.as_secs_f64() / self.media_time.as_secs_f64().max(0.001);
+        (result.output_size as f64 * ratio) as u64
+    }
+}
+
+impl Benchmark {
+    pub fn new(ffmpeg: impl Into<PathBuf>, ffprobe: impl Into<PathBuf>) -> Self {
+        Benchmark {
+            ffmpeg: ffmpeg.into(),
+            ffprobe: ffprobe.into(),
+            segments: 3,
+            segment_length: Duration::from_secs(10),
+            vmaf: false,
+            dir: std::env::temp_dir()
+                .join(format!("ffmpegConvert_benchmark_{}", std::process::id())),
+        }
+    }
+
+    /// 对比 presets 中的预设，每项为 (预设序号, 预设, 使用的 ffmpeg)
+    pub fn run(
+        &self,
+        backend: &dyn Backend,
+        input: &Path,
+        presets: &[(usize, &Preset, PathBuf)],
+        observer: &mut dyn BatchObserver,
+    ) -> Result<BenchmarkReport, String> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("无法创建临时目录 {}: {}", self.dir.display(), e))?;
+        let result = self.run_in_dir(backend, input, presets, observer);
+        let _ = std::fs::remove_dir_all(&self.dir);
+        result
+    }
+
+    fn run_in_dir(
+        &self,
+        backend: &dyn Backend,
+        input: &Path,
+        presets: &[(usize, &Preset, PathBuf)],
+        observer: &mut dyn BatchObserver,
+    ) -> Result<BenchmarkReport, String> {
+        let info = MediaInfo::probe(&self.ffprobe, input)?;
+        let Some(source_duration) = info.duration else {
+            return Err(format!("无法读取视频时长: {}", input.display()));
+        };
+        let Some((width, height)) = info
+            .video_stream()
+            .and_then(|v| Some((v.width?, v.height?)))
+        else {
+            return Err(format!("无法读取视频分辨率: {}", input.display()));
+        };
+
+        let ranges = segment_ranges(source_duration, self.segments, self.segment_length);
+        let media_time: Duration = ranges.iter().map(|(_, length)| *length).sum();
+        let source_size = match info.bit_rate {
+            Some(bit_rate) => (bit_rate as f64 / 8.0 * media_time.as_secs_f64()) as u64,
+            None => {
+                let file_size = std::fs::metadata(input).map(|m| m.len()).unwrap_or(0);
+                (file_size as f64 * media_time.as_secs_f64() / source_duration.as_secs_f64()) as u64
+            }
+        };
+
+        // 先把各段无损截取出来，作为转码的输入和画质比较的参考
+        let mut segments = Vec::new();
+        for (i, (start, length)) in ranges.iter().enumerate() {
+            let segment = self.dir.join(format!("segment{}.mkv", i + 1));
+            cut_segment(&self.ffmpeg, input, &segment, *start, *length, None)?;
+            segments.push(segment);
+        }
+
+        let total = presets.len() * segments.len();
+        let mut results = Vec::new();
+
+        for (n, (preset_index, preset, ffmpeg)) in presets.iter().enumerate() {
+            let mut result = BenchmarkResult {
+                preset: *preset_index,
+                encode_time: Duration::ZERO,
+                output_size: 0,
+                scores: QualityScores::default(),
+                error: None,
+            };
+            let mut scores = Vec::new();
+
+            for (i, segment) in segments.iter().enumerate() {
+                observer.file_started(n * segments.len() + i + 1, total, segment);
+                let job = Job::new(
+                    segment,
+                    output_path_for(segment, &format!("{}_{}", preset.subfix, preset_index)),
+                    (*preset).clone(),
+                    ffmpeg,
+                );
+
+                let job_result = backend.run(&job, &mut |event| observer.event(&job, event));
+                let outcome = match job_result {
+                    JobResult::Success(stats) => {
+                        result.encode_time += stats.elapsed;
+                        let output_size = std::fs::metadata(&job.output).map_or(0, |m| m.len());
+                        result.output_size += output_size;
+                        match measure(&self.ffmpeg, &job.output, segment, width, height, self.vmaf)
+                        {
+                            Ok(s) => scores.push(s),
+                            Err(e) => result.error = Some(e),
+                        }
+                        let input_size = std::fs::metadata(segment).map_or(0, |m| m.len());
+                        FileOutcome::Converted {
+                            stats,
+                            sizes: Some((input_size, output_size)),
+                        }
+                    }
+                    JobResult::Failed(failure) => {
+                        result.error = Some(failure.reason.clone());
+                        FileOutcome::Failed(failure)
+                    }
+                    JobResult::Cancelled => FileOutcome::Cancelled,
+                };
+                let cancelled = matches!(outcome, FileOutcome::Cancelled);
+
+                observer.file_finished(&FileReport {
+                    input: segment.clone(),
+                    output: job.output.clone(),
+                    preset: *preset_index,
+                    attempts: 1,
+                    outcome,
+                    replaced: None,
+                });
+                let _ = std::fs::remove_file(&job.output);
+
+                if cancelled {
+                    return Err("预设对比已取消".to_string());
+                }
+                if result.error.is_some() {
+                    break;
+                }
+            }
+
+            result.scores = QualityScores::average(&scores);
+            results.push(result);
+        }
+
+        Ok(BenchmarkReport {
+            media_time,
+            source_size,
+            source_duration,
+            results,
+        })
+    }
+}
+
+/// 截取一段视频（和第一条音轨），用 ffv1/flac 无损编码，用作转码的输入和画质比较的参考。
+/// video_filter 为截取时对视频添加的滤镜
+pub fn cut_segment(
+    ffmpeg: &Path,
+    input: &Path,
+    segment: &Path,
+    start: Duration,
+    length: Duration,
+    video_filter: Option<&str>,
+) -> Result<(), String> {
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(["-hide_banner", "-v", "error", "-ss"])
+        .arg(format!("{:.3}", start.as_secs_f64()))
+        .arg("-t")
+        .arg(format!("{:.3}", length.as_secs_f64()))
+        .arg("-i")
+        .arg(input)
+        .args([
+            "-map", "0:v:0", "-map", "0:a:0?", "-c:v", "ffv1", "-c:a", "flac",
+        ]);
+    if let Some(filter) = video_filter {
+        command.arg("-vf").arg(filter);
+    }
+    let output = command
+        .arg("-y")
+        .arg(segment)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("无法启动 ffmpeg {}: {}", ffmpeg.display(), e))?;
+
+    if output.status.success() {
+        Ok(())
+    } else {
+        Err(format!(
+            "无法截取视频片段: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// 在视频中均匀选取 count 段，每段 length 长，返回 (开始时间, 长度)；视频太短时只取一段
+pub fn segment_ranges(
+    duration: Duration,
+    count: usize,
+    length: Duration,
+) -> Vec<(Duration, Duration)> {
+    if count == 0 || duration <= length * count as u32 {
+        return vec![(Duration::ZERO, duration.min(length * count.max(1) as u32))];
+    }
+
+    (1..=count)
+        .map(|i| {
+            let center = duration * i as u32 / (count as u32 + 1);
+            (center.saturating_sub(length / 2), length)
+        })
+        .collect()
+}
@@ -1,6 +1,9 @@
-use winres;
-
 fn main() {
+    // 图标和版本信息只有 Windows 程序需要，其他平台构建库时跳过
+    if std::env::var("CARGO_CFG_TARGET_OS").as_deref() != Ok("windows") {
+        return;
+    }
+
     let mut res = winres::WindowsResource::new();
     res.set_icon("icon.ico")
     .set("InternalName", "ffmpegConvert.exe")
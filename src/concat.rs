@@ -0,0 +1,328 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::media::{MediaInfo, StreamInfo};
+use crate::preset::Preset;
+
+/// 合并时没有读到帧率使用的帧率
+const DEFAULT_FRAME_RATE: f64 = 30.0;
+
+/// 把多个输入文件按顺序合并为一个输出
+#[derive(Clone, Debug)]
+pub struct Concat {
+    pub inputs: Vec<PathBuf>,
+    pub method: ConcatMethod,
+    /// 各文件时长之和，有文件读不到时长时为 None
+    pub duration: Option<Duration>,
+    /// 用 concat 滤镜拼接时，在拼接之后处理画面和声音的滤镜（取自预设的 -vf 和 -af）
+    pub video_filter: Option<String>,
+    pub audio_filter: Option<String>,
+}
+
+/// 合并方式
+#[derive(Clone, Debug, PartialEq)]
+pub enum ConcatMethod {
+    /// 各文件的流一致，用 concat 分离器按文件列表直接拼接，不需要额外解码
+    Demuxer { list: PathBuf },
+    /// 流不一致（如分辨率或编码格式不同），用 concat 滤镜解码后拼接，
+    /// 画面统一缩放到第一个文件的分辨率和帧率；有文件没有音轨时输出不含音频
+    Filter {
+        width: u32,
+        height: u32,
+        frame_rate: f64,
+        audio: bool,
+    },
+}
+
+/// 读取各输入文件的流信息，选择合并方式并生成文件列表
+#[derive(Clone, Debug)]
+pub struct Concatenator {
+    pub ffprobe: PathBuf,
+}
+
+impl Concatenator {
+    /// list 为 concat 分离器使用的文件列表的路径，只在各文件的流一致时写入
+    pub fn prepare(&self, inputs: &[PathBuf], list: &Path) -> Result<Concat, String> {
+        let infos = inputs
+            .iter()
+            .map(|input| MediaInfo::probe(&self.ffprobe, input))
+            .collect::<Result<Vec<_>, _>>()?;
+        let concat = plan_concat(inputs, &infos, list)?;
+        if let ConcatMethod::Demuxer { list } = &concat.method {
+            std::fs::write(list, concat_list(inputs, &infos))
+                .map_err(|e| format!("无法写入文件列表 {}: {}", list.display(), e))?;
+        }
+        Ok(concat)
+    }
+}
+
+/// 按各文件的流信息选择合并方式，infos 与 inputs 一一对应
+pub fn plan_concat(inputs: &[PathBuf], infos: &[MediaInfo], list: &Path) -> Result<Concat, String> {
+    let first = infos.first().ok_or("没有要合并的文件")?;
+    for (input, info) in inputs.iter().zip(infos) {
+        if info.video_stream().is_none() {
+            return Err(format!("{} 中没有视频流", input.display()));
+        }
+    }
+
+    let duration = infos
+        .iter()
+        .map(|info| info.duration)
+        .sum::<Option<Duration>>();
+    let method = if infos.iter().all(|info| streams_match(first, info)) {
+        ConcatMethod::Demuxer {
+            list: list.to_path_buf(),
+        }
+    } else {
+        let video = first.video_stream().expect("已检查各文件都有视频流");
+        ConcatMethod::Filter {
+            width: video.width.unwrap_or(1920),
+            height: video.height.unwrap_or(1080),
+            frame_rate: video.frame_rate.unwrap_or(DEFAULT_FRAME_RATE),
+            audio: infos
+                .iter()
+                .all(|info| info.audio_streams().next().is_some()),
+        }
+    };
+
+    Ok(Concat {
+        inputs: inputs.to_vec(),
+        method,
+        duration,
+        video_filter: None,
+        audio_filter: None,
+    })
+}
+
+/// 两个文件能否用 concat 分离器直接拼接：流的数量、类型、编码格式和参数都相同
+pub fn streams_match(a: &MediaInfo, b: &MediaInfo) -> bool {
+    let same = |x: &StreamInfo, y: &StreamInfo| {
+        x.codec_type == y.codec_type
+            && x.codec_name == y.codec_name
+            && x.width == y.width
+            && x.height == y.height
+            && x.pix_fmt == y.pix_fmt
+            && x.sample_rate == y.sample_rate
+            && x.channels == y.channels
+    };
+    a.streams.len() == b.streams.len() && a.streams.iter().zip(&b.streams).all(|(x, y)| same(x, y))
+}
+
+/// concat 分离器的文件列表，写上每个文件的时长，ffmpeg 据此报告总时长
+pub fn concat_list(inputs: &[PathBuf], infos: &[MediaInfo]) -> String {
+    let mut list = String::from("ffconcat version 1.0\n");
+    for (input, info) in inputs.iter().zip(infos) {
+        // 单引号内的单引号写成 '\''
+        list.push_str(&format!(
+            "file '{}'\n",
+            input.to_string_lossy().replace('\'', r"'\''")
+        ));
+        if let Some(duration) = info.duration {
+            list.push_str(&format!("duration {:.3}\n", duration.as_secs_f64()));
+        }
+    }
+    list
+}
+
+impl Concat {
+    /// 代替 `-i 输入文件` 的参数：concat 分离器读取文件列表，或者依次读取各文件并用 concat 滤镜拼接
+    pub fn input_args(&self) -> Vec<String> {
+        match &self.method {
+            ConcatMethod::Demuxer { list } => vec![
+                "-f".to_string(),
+                "concat".to_string(),
+                "-safe".to_string(),
+                "0".to_string(),
+                "-i".to_string(),
+                list.to_string_lossy().into_owned(),
+            ],
+            ConcatMethod::Filter { audio, .. } => {
+                let mut args = Vec::new();
+                for input in self.inputs.iter() {
+                    args.extend(["-i".to_string(), input.to_string_lossy().into_owned()]);
+                }
+                args.extend([
+                    "-filter_complex".to_string(),
+                    self.filter_graph(),
+                    "-map".to_string(),
+                    "[v]".to_string(),
+                ]);
+                if *audio {
+                    args.extend(["-map".to_string(), "[a]".to_string()]);
+                }
+                args
+            }
+        }
+    }
+
+    // 各文件的画面等比缩放并补黑边到相同大小、统一帧率，音频统一为 48 kHz 立体声，再用 concat 滤镜拼接
+    fn filter_graph(&self) -> String {
+        let ConcatMethod::Filter {
+            width,
+            height,
+            frame_rate,
+            audio,
+        } = &self.method
+        else {
+            return String::new();
+        };
+
+        let mut chains = Vec::new();
+        let mut pads = String::new();
+        for i in 0..self.inputs.len() {
+            chains.push(format!(
+                "[{i}:v:0]scale={w}:{h}:force_original_aspect_ratio=decrease,pad={w}:{h}:(ow-iw)/2:(oh-ih)/2,setsar=1,fps={fps:.3}[v{i}]",
+                i = i,
+                w = width,
+                h = height,
+                fps = frame_rate
+            ));
+            pads.push_str(&format!("[v{}]", i));
+            if *audio {
+                chains.push(format!(
+                    "[{i}:a:0]aresample=48000,aformat=channel_layouts=stereo[a{i}]",
+                    i = i
+                ));
+                pads.push_str(&format!("[a{}]", i));
+            }
+        }
+        let video_out = if self.video_filter.is_some() {
+            "[vc]"
+        } else {
+            "[v]"
+        };
+        let audio_out = match (audio, &self.audio_filter) {
+            (false, _) => "",
+            (true, Some(_)) => "[ac]",
+            (true, None) => "[a]",
+        };
+        chains.push(format!(
+            "{}concat=n={}:v=1:a={}{}{}",
+            pads,
+            self.inputs.len(),
+            if *audio { 1 } else { 0 },
+            video_out,
+            audio_out
+        ));
+        if let Some(filter) = &self.video_filter {
+            chains.push(format!("[vc]{}[v]", filter));
+        }
+        if let (true, Some(filter)) = (audio, &self.audio_filter) {
+            chains.push(format!("[ac]{}[a]", filter));
+        }
+        chains.join(";")
+    }
+
+    /// 用 concat 滤镜拼接时 -vf/-af 不能与 -filter_complex 同时使用：把预设（已加上分析得到的滤镜）中的
+    /// -vf 和 -af 移到拼接之后，返回去掉这两个选项的预设。直接拼接时原样返回
+    pub fn with_preset_filters(&self, preset: &Preset) -> (Concat, Preset) {
+        let mut concat = self.clone();
+        if !matches!(self.method, ConcatMethod::Filter { .. }) {
+            return (concat, preset.clone());
+        }
+
+        let mut tokens = Vec::new();
+        let mut args = preset.args();
+        while let Some(arg) = args.next() {
+            match arg {
+                "-vf" | "-filter:v" => concat.video_filter = args.next().map(str::to_string),
+                "-af" | "-filter:a" => concat.audio_filter = args.next().map(str::to_string),
+                _ => tokens.push(arg),
+            }
+        }
+        let mut preset = preset.clone();
+        preset.params = tokens.join(" ");
+        (concat, preset)
+    }
+
+    /// 测量合并后的响度用的参数：读取各文件的第一条音轨，按转码时的方式拼接后经过 filter 处理。
+    /// 用 concat 滤镜拼接且有文件没有音轨时输出不含音频，返回 None
+    pub fn loudness_args(&self, filter: &str) -> Option<Vec<String>> {
+        let mut args: Vec<String> = match &self.method {
+            ConcatMethod::Demuxer { .. } => {
+                let mut args = self.input_args();
+                args.extend(
+                    ["-map", "0:a:0?", "-vn", "-sn", "-dn", "-af", filter].map(String::from),
+                );
+                args
+            }
+            ConcatMethod::Filter { audio: false, .. } => return None,
+            ConcatMethod::Filter { .. } => {
+                let mut args = Vec::new();
+                let mut pads = String::new();
+                let mut chains = Vec::new();
+                for (i, input) in self.inputs.iter().enumerate() {
+                    args.extend(["-i".to_string(), input.to_string_lossy().into_owned()]);
+                    chains.push(format!(
+                        "[{i}:a:0]aresample=48000,aformat=channel_layouts=stereo[a{i}]",
+                        i = i
+                    ));
+                    pads.push_str(&format!("[a{}]", i));
+                }
+                chains.push(format!(
+                    "{}concat=n={}:v=0:a=1,{}[a]",
+                    pads,
+                    self.inputs.len(),
+                    filter
+                ));
+                args.extend([
+                    "-filter_complex".to_string(),
+                    chains.join(";"),
+                    "-map".to_string(),
+                    "[a]".to_string(),
+                ]);
+                args
+            }
+        };
+        args.extend(["-f", "null", "-"].map(String::from));
+        Some(args)
+    }
+}
+
+/// 合并后应有的媒体信息，用于校验输出：时长为各文件之和，视频帧数在各文件帧率相同时为各文件之和
+pub fn combined_info(infos: &[MediaInfo]) -> MediaInfo {
+    let Some(first) = infos.first() else {
+        return MediaInfo::default();
+    };
+    let mut combined = first.clone();
+    combined.duration = infos.iter().map(|info| info.duration).sum();
+
+    let videos: Vec<Option<&StreamInfo>> = infos.iter().map(|info| info.video_stream()).collect();
+    let frame_rate = first.video_stream().and_then(|v| v.frame_rate);
+    let frames = if videos
+        .iter()
+        .all(|v| v.is_some_and(|v| v.frame_rate == frame_rate))
+    {
+        videos.iter().map(|v| v.and_then(|v| v.frames)).sum()
+    } else {
+        None
+    };
+    if let Some(video) = combined
+        .streams
+        .iter_mut()
+        .find(|s| s.codec_type == "video")
+    {
+        video.frames = frames;
+    }
+    combined
+}
+
+/// 按所在文件夹分组，保持文件的顺序，每组合并为一个输出
+pub fn group_by_folder(inputs: &[PathBuf]) -> Vec<Vec<PathBuf>> {
+    let mut groups: Vec<Vec<PathBuf>> = Vec::new();
+    for input in inputs {
+        match groups
+            .iter_mut()
+            .find(|group| group[0].parent() == input.parent())
+        {
+            Some(group) => group.push(input.clone()),
+            None => groups.push(vec![input.clone()]),
+        }
+    }
+    groups
+}
+
+/// 合并的输出文件与第一个文件同目录，文件名为第一个文件名加上 "_concat" 和预设的后缀
+pub fn concat_output_path(first: &Path, subfix: &str, extension: &str) -> PathBuf {
+    crate::job::output_path_with_extension(first, &format!("_concat{}", subfix), extension)
+}
@@ -0,0 +1,324 @@
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+use crate::crfsearch::{Metric, QualityTarget, crf_range};
+use crate::ffmpeg::{MIN_FFMPEG_VERSION, parse_version};
+use crate::hdr::TONEMAP_ALGORITHMS;
+use crate::loudnorm::{DEFAULT_TRUE_PEAK, LoudnessTarget};
+use crate::nogain::NoGainAction;
+use crate::preset::Preset;
+use crate::replace::ReplaceMode;
+use crate::target::{RateTarget, parse_bitrate, parse_size};
+use crate::verify::VerifyMode;
+
+/// 运行设置，默认值可被配置文件中的 `名称 = 值` 行覆盖
+#[derive(Clone, Debug)]
+pub struct Settings {
+    /// 超过多少分钟没有进度则判定 ffmpeg 卡住，0 表示不检测
+    pub stall_timeout_minutes: u64,
+    /// 单个文件的转码用时上限，为视频时长的倍数，0 表示不限制
+    pub timeout_ratio: f64,
+    /// 转码失败后使用同一预设重试的次数
+    pub retries: u32,
+    pub ffmpeg: Option<PathBuf>,
+    pub ffprobe: Option<PathBuf>,
+    /// 配置文件中 `ffmpeg.名称 = 路径` 定义的其他 ffmpeg，供预设选项 ffmpeg=名称 使用
+    pub ffmpeg_builds: HashMap<String, PathBuf>,
+    pub min_ffmpeg_version: String,
+    /// 转码成功后对输出文件的校验方式
+    pub verify: VerifyMode,
+    /// 校验时输出与输入时长允许的误差（秒）
+    pub verify_tolerance: f64,
+    /// 输出至少要比原文件小多少 (%) 才保留，None 表示总是保留
+    pub min_saving: Option<f64>,
+    /// 输出没有达到 min_saving 时的处理方式
+    pub no_gain: NoGainAction,
+    /// 转码并校验成功后是否用输出替换原文件
+    pub replace: ReplaceMode,
+    /// replace 为 archive 时原文件移到的文件夹，相对路径相对于原文件所在的文件夹
+    pub archive_dir: PathBuf,
+    /// 是否检测并裁掉视频中的黑边
+    pub autocrop: bool,
+    /// 是否检测隔行扫描并自动去隔行
+    pub deinterlace: bool,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Settings {
+            stall_timeout_minutes: 10,
+            timeout_ratio: 0.0,
+            retries: 0,
+            ffmpeg: None,
+            ffprobe: None,
+            ffmpeg_builds: HashMap::new(),
+            min_ffmpeg_version: MIN_FFMPEG_VERSION.to_string(),
+            verify: VerifyMode::Off,
+            verify_tolerance: 1.0,
+            min_saving: None,
+            no_gain: NoGainAction::Delete,
+            replace: ReplaceMode::Off,
+            archive_dir: PathBuf::from("原文件"),
+            autocrop: false,
+            deinterlace: false,
+        }
+    }
+}
+
+impl Settings {
+    pub fn set(&mut self, key: &str, value: &str) -> Result<(), String> {
+        let ok = match key {
+            "stall_timeout" => value
+                .parse()
+                .map(|v| self.stall_timeout_minutes = v)
+                .is_ok(),
+            "timeout_ratio" => value.parse().map(|v| self.timeout_ratio = v).is_ok(),
+            "retries" => value.parse().map(|v| self.retries = v).is_ok(),
+            "ffmpeg" => {
+                self.ffmpeg = Some(PathBuf::from(value));
+                true
+            }
+            "ffprobe" => {
+                self.ffprobe = Some(PathBuf::from(value));
+                true
+            }
+            "min_ffmpeg_version" => {
+                self.min_ffmpeg_version = value.to_string();
+                parse_version(value).is_some()
+            }
+            "verify" => VerifyMode::parse(value).map(|v| self.verify = v).is_some(),
+            "verify_tolerance" => value
+                .parse()
+                .ok()
+                .filter(|v: &f64| *v >= 0.0)
+                .map(|v| self.verify_tolerance = v)
+                .is_some(),
+            "min_saving" => value.parse().map(|v| self.min_saving = Some(v)).is_ok(),
+            "no_gain" => NoGainAction::parse(value)
+                .map(|v| self.no_gain = v)
+                .is_some(),
+            "replace" => ReplaceMode::parse(value)
+                .map(|v| self.replace = v)
+                .is_some(),
+            "archive_dir" => {
+                self.archive_dir = PathBuf::from(value);
+                !value.is_empty()
+            }
+            "autocrop" => parse_switch(value).map(|v| self.autocrop = v).is_some(),
+            "deinterlace" => match value {
+                "auto" => Some(true),
+                _ => parse_switch(value),
+            }
+            .map(|v| self.deinterlace = v)
+            .is_some(),
+            _ if key.starts_with("ffmpeg.") => {
+                self.ffmpeg_builds
+                    .insert(key["ffmpeg.".len()..].to_string(), PathBuf::from(value));
+                true
+            }
+            _ => return Err(format!("未知的配置项: {}", key)),
+        };
+
+        if ok {
+            Ok(())
+        } else {
+            Err(format!("配置项 {} 的值无效: {}", key, value))
+        }
+    }
+}
+
+// 开关类配置项的值: on/off、true/false、1/0
+fn parse_switch(value: &str) -> Option<bool> {
+    match value.to_ascii_lowercase().as_str() {
+        "on" | "true" | "1" => Some(true),
+        "off" | "false" | "0" => Some(false),
+        _ => None,
+    }
+}
+
+/// 读取配置文件中的额外预设和运行设置，返回其中无效内容的提示
+pub fn load_config(path: &Path, presets: &mut Vec<Preset>, settings: &mut Settings) -> Vec<String> {
+    match std::fs::read_to_string(path) {
+        Ok(content) => parse_config(&content, presets, settings),
+        Err(_) => Vec::new(),
+    }
+}
+
+/// 解析配置文本，格式见 README
+pub fn parse_config(
+    content: &str,
+    presets: &mut Vec<Preset>,
+    settings: &mut Settings,
+) -> Vec<String> {
+    let mut warnings = Vec::new();
+
+    for line in content.lines() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+            continue; // 跳过空行和注释行
+        }
+
+        // 不含 '#' 的 `名称 = 值` 行是运行设置
+        if !line.contains('#') {
+            if let Some((key, value)) = line.split_once('=')
+                && let Err(e) = settings.set(key.trim(), value.trim())
+            {
+                warnings.push(e);
+            }
+            continue;
+        }
+
+        // 按 '#' 分割，依次是参数、输出文件名称的附加后缀、描述（可选）、选项（可选）
+        let parts: Vec<&str> = line.split('#').map(|s| s.trim()).collect();
+        if parts.len() < 2 {
+            continue; // 至少需要参数和输出后缀
+        }
+
+        let params_part = parts[0];
+        let subfix_part = parts[1];
+        let desc_part = if parts.len() > 2 { parts[2] } else { parts[0] };
+        let options_part = if parts.len() > 3 { parts[3] } else { "" };
+
+        // 过滤无意义行：参数部分不能为空且应包含 '-'（简单判断）
+        if params_part.is_empty() || !params_part.contains('-') {
+            continue;
+        }
+
+        let mut preset = Preset::new(params_part, subfix_part, desc_part);
+
+        // 选项为空格分隔的 `名称=值`，例如 `fallback=1` 表示失败后改用菜单中的第 1 个预设，
+        // `ffmpeg=amf` 表示使用配置文件中 `ffmpeg.amf = 路径` 指定的 ffmpeg，
+        // `target_size=25M` / `target_bitrate=2500k` 表示按目标大小 / 视频码率转码，
+        // `target_ssim=0.98` / `target_psnr=42` / `target_vmaf=93` 表示搜索达到目标画质的 CRF，
+        // `max_res=1080` / `max_fps=30` 表示输入超过时缩小分辨率 / 降低帧率，
+        // `tonemap=hable` 表示 HDR 输入用该算法转为 SDR，
+        // `loudnorm=-16` / `loudnorm_tp=-1.5` 表示把响度标准化到 -16 LUFS / 真峰值不超过 -1.5 dBTP，
+        // `audio=1` 表示音频模式的预设，`ext=m4a` 表示输出文件的扩展名，
+        // `remux=1` 表示只重新封装，输出封装不支持的流才按参数转码
+        let mut loudnorm_tp = None;
+        for option in options_part.split_whitespace() {
+            match option.split_once('=') {
+                Some(("ffmpeg", v)) => preset.ffmpeg_build = Some(v.to_string()),
+                Some(("fallback", v)) => match v.parse::<usize>() {
+                    Ok(n) if n > 0 => preset.fallback = Some(n - 1),
+                    _ => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some(("target_size", v)) => match parse_size(v) {
+                    Some(size) => preset.target = Some(RateTarget::Size(size)),
+                    None => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some(("target_bitrate", v)) => match parse_bitrate(v) {
+                    Some(bitrate) => preset.target = Some(RateTarget::Bitrate(bitrate)),
+                    None => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some((name @ ("target_ssim" | "target_psnr" | "target_vmaf"), v)) => {
+                    let metric = match name {
+                        "target_ssim" => Metric::Ssim,
+                        "target_psnr" => Metric::Psnr,
+                        _ => Metric::Vmaf,
+                    };
+                    match v.parse::<f64>() {
+                        Ok(value) => preset.quality_target = Some(QualityTarget { metric, value }),
+                        Err(_) => warnings.push(format!("预设选项无效: {}", option)),
+                    }
+                }
+                Some(("max_res", v)) => match v.parse::<u32>() {
+                    Ok(n) if n > 0 => preset.max_res = Some(n),
+                    _ => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some(("max_fps", v)) => match v.parse::<f64>() {
+                    Ok(fps) if fps > 0.0 => preset.max_fps = Some(fps),
+                    _ => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some(("tonemap", v)) if TONEMAP_ALGORITHMS.contains(&v) => {
+                    preset.tonemap = Some(v.to_string());
+                }
+                Some(("tonemap", _)) => warnings.push(format!("预设选项无效: {}", option)),
+                Some(("loudnorm", v)) => match v.parse::<f64>() {
+                    Ok(lufs) if (-70.0..=-5.0).contains(&lufs) => {
+                        preset.loudnorm = Some(LoudnessTarget {
+                            integrated: lufs,
+                            true_peak: DEFAULT_TRUE_PEAK,
+                        });
+                    }
+                    _ => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some(("remux", v)) => match parse_switch(v) {
+                    Some(remux) => preset.remux = remux,
+                    None => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some(("audio", v)) => match parse_switch(v) {
+                    Some(audio) => preset.audio = audio,
+                    None => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                Some(("ext", v))
+                    if !v.is_empty() && v.chars().all(|c| c.is_ascii_alphanumeric()) =>
+                {
+                    preset.extension = Some(v.to_ascii_lowercase());
+                }
+                Some(("ext", _)) => warnings.push(format!("预设选项无效: {}", option)),
+                Some(("loudnorm_tp", v)) => match v.parse::<f64>() {
+                    Ok(tp) if (-9.0..=0.0).contains(&tp) => loudnorm_tp = Some(tp),
+                    _ => warnings.push(format!("预设选项无效: {}", option)),
+                },
+                _ => warnings.push(format!("未知的预设选项: {}", option)),
+            }
+        }
+
+        match (&mut preset.loudnorm, loudnorm_tp) {
+            (Some(target), Some(tp)) => target.true_peak = tp,
+            (None, Some(_)) => warnings.push(format!(
+                "loudnorm_tp 需要与 loudnorm 一起使用: {}",
+                preset.description
+            )),
+            _ => {}
+        }
+        if preset.loudnorm.is_some()
+            && (preset.audio_encoder() == Some("copy") || preset.args().any(|a| a == "-an"))
+        {
+            warnings.push(format!(
+                "预设不编码音频，忽略响度标准化: {}",
+                preset.description
+            ));
+            preset.loudnorm = None;
+        }
+
+        if preset.remux && (preset.target.is_some() || preset.quality_target.is_some()) {
+            warnings.push(format!(
+                "重新封装的预设不按目标大小、码率或画质转码，忽略这些选项: {}",
+                preset.description
+            ));
+            preset.target = None;
+            preset.quality_target = None;
+        }
+
+        if preset.quality_target.is_some() {
+            if preset.target.is_some() {
+                warnings.push(format!(
+                    "目标画质与目标大小不能同时使用，忽略目标画质: {}",
+                    preset.description
+                ));
+                preset.quality_target = None;
+            } else if preset.video_encoder().and_then(crf_range).is_none() {
+                warnings.push(format!(
+                    "预设的编码器不支持 CRF 搜索，忽略目标画质: {}",
+                    preset.description
+                ));
+                preset.quality_target = None;
+            }
+        }
+
+        presets.push(preset);
+    }
+
+    // 丢弃指向不存在预设的备用设置
+    let count = presets.len();
+    for preset in presets.iter_mut() {
+        if preset.fallback.is_some_and(|i| i >= count) {
+            warnings.push(format!("备用预设序号超出范围: {}", preset.description));
+            preset.fallback = None;
+        }
+    }
+
+    warnings
+}
@@ -0,0 +1,256 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::backend::Backend;
+use crate::benchmark::cut_segment;
+use crate::job::{Job, JobResult};
+use crate::media::MediaInfo;
+use crate::preset::Preset;
+use crate::quality::{QualityScores, measure};
+use crate::transcoder::{Control, Event};
+use crate::trim::sample_ranges;
+
+/// 画质指标
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Metric {
+    Ssim,
+    Psnr,
+    /// 需要 ffmpeg 带有 libvmaf 滤镜
+    Vmaf,
+}
+
+impl Metric {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Metric::Ssim => "SSIM",
+            Metric::Psnr => "PSNR",
+            Metric::Vmaf => "VMAF",
+        }
+    }
+
+    pub fn score_of(&self, scores: &QualityScores) -> Option<f64> {
+        match self {
+            Metric::Ssim => scores.ssim,
+            Metric::Psnr => scores.psnr,
+            Metric::Vmaf => scores.vmaf,
+        }
+    }
+}
+
+/// 目标画质，例如 SSIM 至少 0.98；CRF 搜索选择达到目标的最大 CRF
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct QualityTarget {
+    pub metric: Metric,
+    pub value: f64,
+}
+
+/// CRF 搜索的结果
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct CrfChoice {
+    pub crf: u32,
+    /// 样本在该 CRF 下的评分
+    pub score: f64,
+    /// 是否达到目标，搜索范围内最小的 CRF 也达不到时为 false
+    pub met: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum CrfSearchError {
+    /// 样本转码被调用方中止
+    Cancelled,
+    Failed(String),
+}
+
+/// CRF 搜索：截取几段样本，用不同的 CRF 转码并与原片比较画质，二分查找达到目标画质的最大 CRF
+pub struct CrfSearch {
+    pub ffprobe: PathBuf,
+    /// 截取的样本段数，均匀分布在视频中
+    pub segments: usize,
+    pub segment_length: Duration,
+    /// 存放样本和样本输出的临时目录，每次搜索结束后删除
+    pub dir: PathBuf,
+}
+
+/// 编码器的 CRF 搜索范围，不支持 CRF 的编码器为 None
+pub fn crf_range(encoder: &str) -> Option<(u32, u32)> {
+    match encoder {
+        "libx264" | "libx265" => Some((10, 40)),
+        "libsvtav1" | "libaom-av1" | "libvpx-vp9" => Some((10, 55)),
+        _ => None,
+    }
+}
+
+/// 把预设参数中的 -crf 改为 crf，没有 -crf 时加上
+pub fn with_crf(preset: &Preset, crf: u32) -> Preset {
+    let mut tokens: Vec<String> = preset.args().map(|s| s.to_string()).collect();
+    match tokens.iter().rposition(|t| t == "-crf") {
+        Some(i) if i + 1 < tokens.len() => tokens[i + 1] = crf.to_string(),
+        _ => tokens.extend(["-crf".to_string(), crf.to_string()]),
+    }
+
+    let mut preset = preset.clone();
+    preset.params = tokens.join(" ");
+    preset
+}
+
+/// 在 range 内二分查找 score_at 达到 target 的最大 CRF（CRF 越大画质越低）
+pub fn highest_passing_crf(
+    range: (u32, u32),
+    target: f64,
+    mut score_at: impl FnMut(u32) -> Result<f64, CrfSearchError>,
+) -> Result<CrfChoice, CrfSearchError> {
+    let (mut low, mut high) = range;
+    let mut best = None;
+    let mut lowest_crf_score = None;
+
+    while low <= high {
+        let crf = (low + high) / 2;
+        let score = score_at(crf)?;
+        if score >= target {
+            best = Some((crf, score));
+            low = crf + 1;
+        } else {
+            if crf == range.0 {
+                lowest_crf_score = Some(score);
+                break;
+            }
+            high = crf - 1;
+        }
+    }
+
+    match best {
+        Some((crf, score)) => Ok(CrfChoice {
+            crf,
+            score,
+            met: true,
+        }),
+        None => Ok(CrfChoice {
+            crf: range.0,
+            score: match lowest_crf_score {
+                Some(score) => score,
+                None => score_at(range.0)?,
+            },
+            met: false,
+        }),
+    }
+}
+
+impl CrfSearch {
+    pub fn new(ffprobe: impl Into<PathBuf>) -> Self {
+        CrfSearch {
+            ffprobe: ffprobe.into(),
+            segments: 3,
+            segment_length: Duration::from_secs(5),
+            dir: std::env::temp_dir()
+                .join(format!("ffmpegConvert_crfsearch_{}", std::process::id())),
+        }
+    }
+
+    /// 为 job 的输入搜索 CRF，样本用 backend 转码，事件转给 on_event。
+    /// filters 为转码时要添加的视频滤镜，截取样本时就加上；job 只转码一段时只在这一段中截取样本
+    pub fn run(
+        &self,
+        backend: &dyn Backend,
+        job: &Job,
+        target: QualityTarget,
+        filters: &[String],
+        on_event: &mut dyn FnMut(&Job, Event) -> Control,
+    ) -> Result<CrfChoice, CrfSearchError> {
+        std::fs::create_dir_all(&self.dir).map_err(|e| {
+            CrfSearchError::Failed(format!("无法创建临时目录 {}: {}", self.dir.display(), e))
+        })?;
+        let result = self.run_in_dir(backend, job, target, filters, on_event);
+        let _ = std::fs::remove_dir_all(&self.dir);
+        result
+    }
+
+    fn run_in_dir(
+        &self,
+        backend: &dyn Backend,
+        job: &Job,
+        target: QualityTarget,
+        filters: &[String],
+        on_event: &mut dyn FnMut(&Job, Event) -> Control,
+    ) -> Result<CrfChoice, CrfSearchError> {
+        let encoder = job.preset.video_encoder().unwrap_or("");
+        let Some(range) = crf_range(encoder) else {
+            return Err(CrfSearchError::Failed(format!(
+                "编码器 {} 不支持 CRF 搜索",
+                encoder
+            )));
+        };
+
+        let info = MediaInfo::probe(&self.ffprobe, &job.input).map_err(CrfSearchError::Failed)?;
+        let Some(duration) = info.duration else {
+            return Err(CrfSearchError::Failed("无法读取视频时长".to_string()));
+        };
+
+        let filter = (!filters.is_empty()).then(|| filters.join(","));
+        let mut segments = Vec::new();
+        for (i, (start, length)) in
+            sample_ranges(job.trim, duration, self.segments, self.segment_length)
+                .into_iter()
+                .enumerate()
+        {
+            let segment = self.dir.join(format!("sample{}.mkv", i + 1));
+            cut_segment(
+                &job.ffmpeg,
+                &job.input,
+                &segment,
+                start,
+                length,
+                filter.as_deref(),
+            )
+            .map_err(CrfSearchError::Failed)?;
+            segments.push(segment);
+        }
+
+        // 滤镜可能改变了分辨率，按样本的尺寸比较
+        let sample_info =
+            MediaInfo::probe(&self.ffprobe, &segments[0]).map_err(CrfSearchError::Failed)?;
+        let Some((width, height)) = sample_info
+            .video_stream()
+            .and_then(|v| Some((v.width?, v.height?)))
+        else {
+            return Err(CrfSearchError::Failed("无法读取视频分辨率".to_string()));
+        };
+
+        highest_passing_crf(range, target.value, |crf| {
+            let mut preset = with_crf(&job.preset, crf);
+            preset.target = None;
+
+            let mut scores = Vec::new();
+            for segment in segments.iter() {
+                let sample = Job::new(
+                    segment,
+                    segment.with_extension(format!("crf{}.mp4", crf)),
+                    preset.clone(),
+                    &job.ffmpeg,
+                );
+                match backend.run(&sample, &mut |event| on_event(&sample, event)) {
+                    JobResult::Success(_) => {}
+                    JobResult::Failed(failure) => {
+                        return Err(CrfSearchError::Failed(failure.reason));
+                    }
+                    JobResult::Cancelled => return Err(CrfSearchError::Cancelled),
+                }
+
+                let measured = measure(
+                    &job.ffmpeg,
+                    &sample.output,
+                    segment,
+                    width,
+                    height,
+                    target.metric == Metric::Vmaf,
+                );
+                let _ = std::fs::remove_file(&sample.output);
+                scores.push(measured.map_err(CrfSearchError::Failed)?);
+            }
+
+            target
+                .metric
+                .score_of(&QualityScores::average(&scores))
+                .ok_or_else(|| CrfSearchError::Failed(format!("无法计算 {}", target.metric.name())))
+        })
+    }
+}
@@ -0,0 +1,129 @@
+use std::path::{Path, PathBuf};
+
+/// 作为输入处理的视频文件扩展名
+pub const VIDEO_EXTS: [&str; 14] = [
+    "mp4", "mkv", "avi", "mov", "wmv", "flv", "webm", "m4v", "ts", "mpeg", "mpg", "3gp", "rm",
+    "rmvb",
+];
+
+/// 音频模式中额外作为输入处理的音频文件扩展名（视频文件则提取其中的音频）
+pub const AUDIO_EXTS: [&str; 12] = [
+    "flac", "wav", "mp3", "m4a", "aac", "ogg", "opus", "wma", "ape", "wv", "aiff", "aif",
+];
+
+/// 从命令行给出的文件和文件夹中找到的视频文件
+#[derive(Debug, Default)]
+pub struct Discovery {
+    /// 按自然顺序排列的视频文件绝对路径
+    pub files: Vec<PathBuf>,
+    /// 不存在的路径
+    pub missing: Vec<String>,
+    /// 直接给出但不是视频的文件
+    pub skipped: Vec<String>,
+}
+
+/// 收集视频文件，文件夹会递归查找，已转码过的 _h265/_av1/_sdr/_remux 等文件会被过滤掉。
+/// archive_dir 为替换原文件时的归档文件夹（相对路径相对于各文件夹），查找时跳过，以免再次转码归档的原文件
+pub fn collect_video_files(
+    paths: &[String],
+    exts: &[&str],
+    archive_dir: Option<&Path>,
+) -> Discovery {
+    let mut discovery = Discovery::default();
+
+    for arg in paths {
+        let path = Path::new(arg);
+
+        if !path.exists() {
+            discovery.missing.push(arg.clone());
+            continue;
+        }
+
+        if path.is_file() {
+            if is_video_file(path, exts) {
+                if let Ok(absolute_path) = path.canonicalize() {
+                    discovery.files.push(strip_verbatim_prefix(absolute_path));
+                }
+            } else {
+                discovery.skipped.push(arg.clone());
+            }
+        } else if path.is_dir() {
+            find_video_files(path, exts, archive_dir, &mut discovery.files);
+        }
+    }
+
+    // 过滤掉 _h265、_av1、转为 SDR 的 _sdr、重新封装的 _remux 和合并输出的 _concat 结尾的文件，以及音频预设输出的 _opus、_aac、_mp3、_flac 结尾的音频文件
+    discovery.files.retain(|p| {
+        if let Some(stem) = p.file_stem().and_then(|s| s.to_str()) {
+            let lower_stem = stem.to_lowercase();
+            let audio_output = is_video_file(p, &AUDIO_EXTS)
+                && ["_opus", "_aac", "_mp3", "_flac"]
+                    .iter()
+                    .any(|subfix| lower_stem.ends_with(subfix));
+            !(lower_stem.ends_with("_h265")
+                || lower_stem.ends_with("_av1")
+                || lower_stem.ends_with("_sdr")
+                || lower_stem.ends_with("_remux")
+                || lower_stem.ends_with("_concat")
+                || audio_output)
+        } else {
+            true
+        }
+    });
+
+    discovery.files.sort_by(|a, b| {
+        let a_str = a.to_string_lossy();
+        let b_str = b.to_string_lossy();
+        natural_sort_rs::natural_cmp(&a_str, &b_str)
+    });
+
+    discovery
+}
+
+pub fn is_video_file(path: &Path, exts: &[&str]) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .map(|ext| exts.iter().any(|&e| ext.eq_ignore_ascii_case(e)))
+        .unwrap_or(false)
+}
+
+fn find_video_files(
+    dir: &Path,
+    exts: &[&str],
+    archive_dir: Option<&Path>,
+    results: &mut Vec<PathBuf>,
+) {
+    if let Ok(entries) = std::fs::read_dir(dir) {
+        for entry in entries.flatten() {
+            let path = entry.path();
+            if path.is_dir() {
+                if !archive_dir.is_some_and(|archive| is_same_dir(&path, &dir.join(archive))) {
+                    find_video_files(&path, exts, archive_dir, results);
+                }
+            } else if is_video_file(&path, exts)
+                && let Ok(absolute_path) = path.canonicalize()
+            {
+                results.push(strip_verbatim_prefix(absolute_path));
+            }
+        }
+    }
+}
+
+// 归档文件夹为绝对路径时写法可能与查找到的路径不同，按实际位置比较
+fn is_same_dir(a: &Path, b: &Path) -> bool {
+    a == b || matches!((a.canonicalize(), b.canonicalize()), (Ok(a), Ok(b)) if a == b)
+}
+
+// Windows 上 canonicalize 得到的是 "\\?\C:\..." 或 "\\?\UNC\server\share" 形式，还原成普通路径
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    let Some(s) = path.to_str() else {
+        return path;
+    };
+    if let Some(unc) = s.strip_prefix(r"\\?\UNC\") {
+        PathBuf::from(format!(r"\\{}", unc))
+    } else if let Some(stripped) = s.strip_prefix(r"\\?\") {
+        PathBuf::from(stripped)
+    } else {
+        path
+    }
+}
@@ -0,0 +1,262 @@
+use std::collections::HashSet;
+use std::env;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+
+/// 未在配置文件中指定 min_ffmpeg_version 时要求的最低 ffmpeg 版本
+pub const MIN_FFMPEG_VERSION: &str = "4.4";
+
+/// 加上当前平台可执行文件的扩展名，例如 Windows 上 "ffmpeg" -> "ffmpeg.exe"
+pub fn executable_name(name: &str) -> String {
+    format!("{}{}", name, env::consts::EXE_SUFFIX)
+}
+
+/// 按命令行参数、环境变量、配置文件、本程序所在目录、PATH 的顺序确定程序位置
+pub fn resolve_program(
+    cli_value: Option<&Path>,
+    env_name: &str,
+    config_value: Option<&Path>,
+    file_name: &str,
+) -> Result<PathBuf, String> {
+    let env_value = env::var_os(env_name).map(PathBuf::from);
+    let specified = [
+        (cli_value.map(Path::to_path_buf), "命令行参数"),
+        (env_value, env_name),
+        (config_value.map(Path::to_path_buf), "配置文件"),
+    ];
+
+    // 明确指定了位置但文件不存在时直接报错，不再往后查找，以免悄悄用上另一个版本
+    if let Some((path, source)) = specified
+        .into_iter()
+        .find_map(|(path, source)| path.map(|p| (p, source)))
+    {
+        return if path.is_file() {
+            Ok(path)
+        } else {
+            Err(format!(
+                "{} 指定的 {} 不存在: {}",
+                source,
+                file_name,
+                path.display()
+            ))
+        };
+    }
+
+    let exe_dir = env::current_exe()
+        .ok()
+        .and_then(|p| p.parent().map(Path::to_path_buf));
+    let path_dirs = env::var_os("PATH")
+        .map(|paths| env::split_paths(&paths).collect::<Vec<_>>())
+        .unwrap_or_default();
+
+    exe_dir
+        .into_iter()
+        .chain(path_dirs)
+        .map(|dir| dir.join(file_name))
+        .find(|p| p.is_file())
+        .ok_or_else(|| {
+            format!(
+                "找不到 {}，请将其放到本程序同一目录下或添加到 PATH",
+                file_name
+            )
+        })
+}
+
+/// 取版本号开头的数字部分，如 "7.1-full_build-www.gyan.dev" -> [7, 1]，"n6.1.1" -> [6, 1, 1]
+pub fn parse_version(version: &str) -> Option<Vec<u32>> {
+    let version = version.strip_prefix('n').unwrap_or(version);
+    let numeric: String = version
+        .chars()
+        .take_while(|c| c.is_ascii_digit() || *c == '.')
+        .collect();
+    let parts: Vec<u32> = numeric.split('.').map_while(|p| p.parse().ok()).collect();
+    (!parts.is_empty()).then_some(parts)
+}
+
+pub fn check_version(version: &str, min_version: &str) -> Result<(), String> {
+    // 开发版（如 "N-113000-g..." 或 "2024-02-04-git-..."）及无法识别的版本号视为满足要求
+    if version.starts_with('N') || version.contains("-git-") {
+        return Ok(());
+    }
+    match (parse_version(version), parse_version(min_version)) {
+        (Some(v), Some(min)) if v < min => Err(format!(
+            "ffmpeg 版本 {} 低于要求的 {}",
+            version, min_version
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// ffmpeg 的版本及其支持的编码器和滤镜
+#[derive(Clone, Debug)]
+pub struct Capabilities {
+    pub version: String,
+    pub encoders: HashSet<String>,
+    pub filters: HashSet<String>,
+}
+
+impl Capabilities {
+    /// 预设参数中用到、但当前 ffmpeg 不支持的编码器和滤镜
+    pub fn missing_features(&self, params: &str) -> Vec<String> {
+        let tokens: Vec<&str> = params.split_whitespace().collect();
+        let mut missing = Vec::new();
+
+        for pair in tokens.windows(2) {
+            let (option, value) = (pair[0], pair[1]);
+
+            let is_codec_option = matches!(option, "-c" | "-codec" | "-vcodec" | "-acodec")
+                || option.starts_with("-c:")
+                || option.starts_with("-codec:");
+            if is_codec_option {
+                if value != "copy" && !self.encoders.contains(value) {
+                    missing.push(value.to_string());
+                }
+                continue;
+            }
+
+            let is_filter_option = matches!(option, "-vf" | "-af" | "-filter_complex" | "-lavfi")
+                || option.starts_with("-filter:");
+            if is_filter_option {
+                for filter in value.split([',', ';']) {
+                    // 去掉 [in] [out] 之类的标签和 = 后面的参数
+                    let mut name = filter.trim();
+                    while let Some(rest) = name.strip_prefix('[') {
+                        name = rest.split_once(']').map_or("", |(_, after)| after);
+                    }
+                    let name = name.split(['=', '[']).next().unwrap_or("").trim();
+                    if !name.is_empty() && !self.filters.contains(name) {
+                        missing.push(name.to_string());
+                    }
+                }
+            }
+        }
+
+        missing.sort();
+        missing.dedup();
+        missing
+    }
+}
+
+/// 读取 ffmpeg 的能力，结果按 ffmpeg 的路径、大小和修改时间缓存在 cache_path 中，换了 ffmpeg 才重新检测
+pub fn load_capabilities(ffmpeg: &Path, cache_path: &Path) -> Option<Capabilities> {
+    let metadata = std::fs::metadata(ffmpeg).ok()?;
+    let modified = metadata
+        .modified()
+        .ok()
+        .and_then(|t| t.duration_since(std::time::UNIX_EPOCH).ok())
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+    let key = format!("{}|{}|{}", ffmpeg.display(), metadata.len(), modified);
+
+    // 缓存文件中每个 ffmpeg 占一段: [key] 之后依次是 version、encoders、filters 三行
+    let cache = std::fs::read_to_string(cache_path).unwrap_or_default();
+    let mut sections: Vec<(String, Vec<String>)> = Vec::new();
+    for line in cache.lines() {
+        if let Some(section_key) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            sections.push((section_key.to_string(), Vec::new()));
+        } else if let Some((_, lines)) = sections.last_mut() {
+            lines.push(line.to_string());
+        }
+    }
+
+    if let Some((_, lines)) = sections.iter().find(|(k, _)| *k == key) {
+        let field = |name: &str| {
+            lines
+                .iter()
+                .find_map(|l| l.strip_prefix(name).and_then(|v| v.strip_prefix(' ')))
+                .unwrap_or("")
+                .to_string()
+        };
+        return Some(Capabilities {
+            version: field("version"),
+            encoders: field("encoders")
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+            filters: field("filters")
+                .split_whitespace()
+                .map(String::from)
+                .collect(),
+        });
+    }
+
+    let caps = probe_capabilities(ffmpeg)?;
+
+    let join = |set: &HashSet<String>| {
+        let mut names: Vec<&str> = set.iter().map(String::as_str).collect();
+        names.sort_unstable();
+        names.join(" ")
+    };
+    sections.retain(|(k, _)| !k.starts_with(&format!("{}|", ffmpeg.display())));
+    sections.push((
+        key,
+        vec![
+            format!("version {}", caps.version),
+            format!("encoders {}", join(&caps.encoders)),
+            format!("filters {}", join(&caps.filters)),
+        ],
+    ));
+
+    let mut content = String::new();
+    for (k, lines) in sections.iter() {
+        content.push_str(&format!("[{}]\n", k));
+        for line in lines {
+            content.push_str(line);
+            content.push('\n');
+        }
+    }
+    // 缓存写不进去只是下次启动要重新检测，不影响本次使用
+    let _ = std::fs::write(cache_path, content);
+
+    Some(caps)
+}
+
+/// 运行 ffmpeg -version、-encoders、-filters 检测其能力
+pub fn probe_capabilities(ffmpeg: &Path) -> Option<Capabilities> {
+    let run = |arg: &str| -> Option<String> {
+        let output = Command::new(ffmpeg)
+            .arg("-hide_banner")
+            .arg(arg)
+            .stdin(Stdio::null())
+            .stderr(Stdio::null())
+            .output()
+            .ok()?;
+        output
+            .status
+            .success()
+            .then(|| String::from_utf8_lossy(&output.stdout).into_owned())
+    };
+
+    // ffmpeg version 7.1-full_build-www.gyan.dev Copyright (c) ...
+    let version = run("-version")?
+        .lines()
+        .next()
+        .and_then(|l| l.split_whitespace().nth(2))
+        .unwrap_or("未知")
+        .to_string();
+
+    // " V....D libx265              libx265 H.265 / HEVC (codec hevc)"，分隔线之后才是列表
+    let encoders = run("-encoders")?
+        .lines()
+        .skip_while(|l| !l.trim_start().starts_with("------"))
+        .skip(1)
+        .filter_map(|l| l.split_whitespace().nth(1))
+        .map(String::from)
+        .collect();
+
+    // " TSC scale             V->V       Scale the input video size ..."
+    let filters = run("-filters")?
+        .lines()
+        .filter_map(|l| {
+            let mut fields = l.split_whitespace();
+            let (_flags, name, io) = (fields.next()?, fields.next()?, fields.next()?);
+            io.contains("->").then(|| name.to_string())
+        })
+        .collect();
+
+    Some(Capabilities {
+        version,
+        encoders,
+        filters,
+    })
+}
@@ -0,0 +1,300 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::media::StreamInfo;
+use crate::preset::Preset;
+
+/// 预设选项 tonemap= 可用的色调映射算法
+pub const TONEMAP_ALGORITHMS: [&str; 6] =
+    ["hable", "mobius", "reinhard", "clip", "linear", "gamma"];
+
+/// HDR 的传输特性
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HdrFormat {
+    /// PQ (SMPTE ST 2084)，HDR10
+    Pq,
+    /// HLG (ARIB STD-B67)
+    Hlg,
+}
+
+/// 母版显示器的色域和亮度，色度坐标和亮度都是实际值
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct MasteringDisplay {
+    pub red: (f64, f64),
+    pub green: (f64, f64),
+    pub blue: (f64, f64),
+    pub white_point: (f64, f64),
+    /// 最低和最高亮度 (cd/m²)
+    pub min_luminance: f64,
+    pub max_luminance: f64,
+}
+
+/// 内容亮度信息 (cd/m²)
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ContentLight {
+    /// MaxCLL
+    pub max_content: u32,
+    /// MaxFALL
+    pub max_average: u32,
+}
+
+/// 输入视频的 HDR 信息
+#[derive(Clone, Debug, PartialEq)]
+pub struct HdrInfo {
+    pub format: HdrFormat,
+    /// ffprobe 报告的色域和矩阵，如 "bt2020"、"bt2020nc"
+    pub primaries: String,
+    pub matrix: String,
+    pub mastering: Option<MasteringDisplay>,
+    pub content_light: Option<ContentLight>,
+}
+
+impl HdrInfo {
+    pub fn name(&self) -> &'static str {
+        match self.format {
+            HdrFormat::Pq => "HDR10",
+            HdrFormat::Hlg => "HLG",
+        }
+    }
+
+    /// 按视频流的传输特性判断是否为 HDR，side_data 为 ffprobe 的输出，从中读取母版显示器和内容亮度信息
+    pub fn detect(stream: &StreamInfo, side_data: &str) -> Option<HdrInfo> {
+        let format = match stream.color_transfer.as_str() {
+            "smpte2084" => HdrFormat::Pq,
+            "arib-std-b67" => HdrFormat::Hlg,
+            _ => return None,
+        };
+        let (mastering, content_light) = parse_side_data(side_data);
+        Some(HdrInfo {
+            format,
+            primaries: non_empty_or(&stream.color_primaries, "bt2020"),
+            matrix: non_empty_or(&stream.color_space, "bt2020nc"),
+            mastering,
+            content_light,
+        })
+    }
+}
+
+fn non_empty_or(value: &str, default: &str) -> String {
+    if value.is_empty() || value == "unknown" {
+        default.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// 用 ffprobe 读取视频流和第一帧的附加数据 (side data)，母版显示器信息可能在容器中，也可能在码流的 SEI 中
+pub fn probe_side_data(ffprobe: &Path, input: &Path) -> Result<String, String> {
+    let output = Command::new(ffprobe)
+        .args([
+            "-v",
+            "error",
+            "-select_streams",
+            "v:0",
+            "-show_streams",
+            "-show_frames",
+            "-read_intervals",
+            "%+#1",
+        ])
+        .arg(input)
+        .stdin(Stdio::null())
+        .output()
+        .map_err(|e| format!("无法启动 ffprobe {}: {}", ffprobe.display(), e))?;
+
+    if output.status.success() {
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    } else {
+        Err(format!(
+            "ffprobe 无法读取 HDR 信息: {}",
+            String::from_utf8_lossy(&output.stderr).trim()
+        ))
+    }
+}
+
+/// 解析 ffprobe 输出中的 [SIDE_DATA] 段，取第一个母版显示器信息和内容亮度信息
+pub fn parse_side_data(text: &str) -> (Option<MasteringDisplay>, Option<ContentLight>) {
+    let mut mastering = None;
+    let mut content_light = None;
+    let mut fields: Vec<(&str, &str)> = Vec::new();
+    let mut in_side_data = false;
+
+    for line in text.lines().map(str::trim) {
+        match line {
+            "[SIDE_DATA]" => {
+                in_side_data = true;
+                fields.clear();
+            }
+            "[/SIDE_DATA]" => {
+                in_side_data = false;
+                let field = |name: &str| {
+                    fields
+                        .iter()
+                        .find(|(k, _)| *k == name)
+                        .and_then(|(_, v)| parse_rational(v))
+                };
+                let side_data_type = fields
+                    .iter()
+                    .find(|(k, _)| *k == "side_data_type")
+                    .map(|(_, v)| *v);
+                match side_data_type {
+                    Some("Mastering display metadata") if mastering.is_none() => {
+                        mastering = (|| {
+                            Some(MasteringDisplay {
+                                red: (field("red_x")?, field("red_y")?),
+                                green: (field("green_x")?, field("green_y")?),
+                                blue: (field("blue_x")?, field("blue_y")?),
+                                white_point: (field("white_point_x")?, field("white_point_y")?),
+                                min_luminance: field("min_luminance")?,
+                                max_luminance: field("max_luminance")?,
+                            })
+                        })();
+                    }
+                    Some("Content light level metadata") if content_light.is_none() => {
+                        content_light = (|| {
+                            Some(ContentLight {
+                                max_content: field("max_content")? as u32,
+                                max_average: field("max_average")? as u32,
+                            })
+                        })();
+                    }
+                    _ => {}
+                }
+            }
+            _ if in_side_data => {
+                if let Some(field) = line.split_once('=') {
+                    fields.push(field);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    (mastering, content_light)
+}
+
+// "35400/50000" -> 0.708
+fn parse_rational(value: &str) -> Option<f64> {
+    let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (den != 0.0).then(|| num / den)
+}
+
+/// 该编码器是否支持写入 HDR 元数据
+pub fn supports_hdr(encoder: &str) -> bool {
+    matches!(encoder, "libx265" | "libsvtav1")
+}
+
+/// 在预设参数中加上保留 HDR 所需的色彩参数和元数据；未指定 -pix_fmt 时使用 10 位
+pub fn with_hdr(preset: &Preset, hdr: &HdrInfo) -> Preset {
+    let encoder = preset.video_encoder().unwrap_or("");
+    let transfer = match hdr.format {
+        HdrFormat::Pq => "smpte2084",
+        HdrFormat::Hlg => "arib-std-b67",
+    };
+
+    let mut tokens: Vec<String> = preset.args().map(|s| s.to_string()).collect();
+    if preset.option_value(&["-pix_fmt"]).is_none() {
+        tokens.extend(["-pix_fmt".to_string(), "yuv420p10le".to_string()]);
+    }
+    for (option, value) in [
+        ("-color_primaries", hdr.primaries.as_str()),
+        ("-color_trc", transfer),
+        ("-colorspace", hdr.matrix.as_str()),
+    ] {
+        tokens.extend([option.to_string(), value.to_string()]);
+    }
+
+    match encoder {
+        "libx265" => merge_params(&mut tokens, "-x265-params", &x265_hdr_params(hdr, transfer)),
+        "libsvtav1" => merge_params(&mut tokens, "-svtav1-params", &svtav1_hdr_params(hdr)),
+        _ => {}
+    }
+
+    let mut preset = preset.clone();
+    preset.params = tokens.join(" ");
+    preset
+}
+
+// x265 的 master-display 色度单位为 0.00002，亮度单位为 0.0001 cd/m²，顺序为 G B R WP L(max,min)
+fn x265_hdr_params(hdr: &HdrInfo, transfer: &str) -> String {
+    let mut params = vec![
+        "repeat-headers=1".to_string(),
+        format!("colorprim={}", hdr.primaries),
+        format!("transfer={}", transfer),
+        format!("colormatrix={}", hdr.matrix),
+    ];
+    if hdr.format == HdrFormat::Pq {
+        params.push("hdr10=1".to_string());
+        params.push("hdr10-opt=1".to_string());
+    }
+    if let Some(m) = &hdr.mastering {
+        let xy =
+            |(x, y): (f64, f64)| format!("({},{})", (x * 50000.0).round(), (y * 50000.0).round());
+        params.push(format!(
+            "master-display=G{}B{}R{}WP{}L({},{})",
+            xy(m.green),
+            xy(m.blue),
+            xy(m.red),
+            xy(m.white_point),
+            (m.max_luminance * 10000.0).round(),
+            (m.min_luminance * 10000.0).round()
+        ));
+    }
+    if let Some(cll) = &hdr.content_light {
+        params.push(format!("max-cll={},{}", cll.max_content, cll.max_average));
+    }
+    params.join(":")
+}
+
+// SVT-AV1 的色彩参数用 H.273 的编号，mastering-display 用实际值
+fn svtav1_hdr_params(hdr: &HdrInfo) -> String {
+    let mut params = vec![
+        "enable-hdr=1".to_string(),
+        "color-primaries=9".to_string(),
+        format!(
+            "transfer-characteristics={}",
+            match hdr.format {
+                HdrFormat::Pq => 16,
+                HdrFormat::Hlg => 18,
+            }
+        ),
+        "matrix-coefficients=9".to_string(),
+    ];
+    if let Some(m) = &hdr.mastering {
+        let xy = |(x, y): (f64, f64)| format!("({:.4},{:.4})", x, y);
+        params.push(format!(
+            "mastering-display=G{}B{}R{}WP{}L({},{})",
+            xy(m.green),
+            xy(m.blue),
+            xy(m.red),
+            xy(m.white_point),
+            m.max_luminance,
+            m.min_luminance
+        ));
+    }
+    if let Some(cll) = &hdr.content_light {
+        params.push(format!(
+            "content-light={},{}",
+            cll.max_content, cll.max_average
+        ));
+    }
+    params.join(":")
+}
+
+// 把 extra 追加到参数中已有的 option（如 -x265-params）之后，用 ':' 连接，没有时加上
+fn merge_params(tokens: &mut Vec<String>, option: &str, extra: &str) {
+    match tokens.iter().rposition(|t| t == option) {
+        Some(i) if i + 1 < tokens.len() => {
+            tokens[i + 1] = format!("{}:{}", tokens[i + 1], extra);
+        }
+        _ => tokens.extend([option.to_string(), extra.to_string()]),
+    }
+}
+
+/// HDR 转 SDR 的滤镜：转到线性光做色调映射，再转为 BT.709 的 8 位画面
+pub fn tonemap_filter(algorithm: &str) -> String {
+    format!(
+        "zscale=t=linear:npl=100,format=gbrpf32le,zscale=p=bt709,tonemap={}:desat=0,zscale=t=bt709:m=bt709:r=tv,format=yuv420p",
+        algorithm
+    )
+}
@@ -0,0 +1,248 @@
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::concat::Concat;
+use crate::preset::Preset;
+use crate::trim::Trim;
+
+/// 一个转码任务：用指定的 ffmpeg 和预设把输入文件转码为输出文件
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub input: PathBuf,
+    pub output: PathBuf,
+    pub preset: Preset,
+    pub ffmpeg: PathBuf,
+    /// 只转码输入中的一段，None 表示整个文件
+    pub trim: Option<Trim>,
+    /// 把多个文件合并转码为一个输出，input 为其中第一个文件；None 表示只转码 input
+    pub concat: Option<Concat>,
+}
+
+impl Job {
+    pub fn new(
+        input: impl Into<PathBuf>,
+        output: impl Into<PathBuf>,
+        preset: Preset,
+        ffmpeg: impl Into<PathBuf>,
+    ) -> Self {
+        Job {
+            input: input.into(),
+            output: output.into(),
+            preset,
+            ffmpeg: ffmpeg.into(),
+            trim: None,
+            concat: None,
+        }
+    }
+}
+
+/// 输出文件与输入文件同目录，文件名为输入文件名加上预设的后缀
+pub fn output_path_for(input: &Path, subfix: &str) -> PathBuf {
+    output_path_with_extension(input, subfix, "mp4")
+}
+
+/// 同 output_path_for，输出使用 extension 扩展名，例如音频预设的 "opus"
+pub fn output_path_with_extension(input: &Path, subfix: &str, extension: &str) -> PathBuf {
+    let mut p = input.to_path_buf();
+    let default_output_name = format!("output_{}", chrono::Local::now().format("%Y%m%d%H%M%S"));
+    let file_stem = p
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or(default_output_name.as_str());
+    let new_file_name = format!("{}{}.{}", file_stem, subfix, extension);
+    p.set_file_name(
+        new_file_name
+            .replace("_H264", "")
+            .replace("_h264", "")
+            .replace("_H265", "")
+            .replace("_h265", ""),
+    );
+    p
+}
+
+/// 是否为 MP4/MOV 类封装，只有这些封装接受 -movflags
+pub fn is_mp4_family(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(is_mp4_family_extension)
+}
+
+/// 按扩展名（不含点，不区分大小写）判断是否为 MP4/MOV 类封装
+pub fn is_mp4_family_extension(extension: &str) -> bool {
+    ["mp4", "m4a", "m4v", "mov", "3gp"]
+        .iter()
+        .any(|e| extension.eq_ignore_ascii_case(e))
+}
+
+/// 把 from 的修改时间和访问时间（Windows 和 macOS 上还有创建时间）复制到 to
+pub fn copy_file_times(from: &Path, to: &Path) -> std::io::Result<()> {
+    let metadata = std::fs::metadata(from)?;
+    let mut times = std::fs::FileTimes::new().set_modified(metadata.modified()?);
+    if let Ok(accessed) = metadata.accessed() {
+        times = times.set_accessed(accessed);
+    }
+
+    #[cfg(windows)]
+    if let Ok(created) = metadata.created() {
+        use std::os::windows::fs::FileTimesExt;
+        times = times.set_created(created);
+    }
+    #[cfg(target_os = "macos")]
+    if let Ok(created) = metadata.created() {
+        use std::os::macos::fs::FileTimesExt;
+        times = times.set_created(created);
+    }
+
+    std::fs::File::options()
+        .write(true)
+        .open(to)?
+        .set_times(times)
+}
+
+/// 一次转码的结果
+#[derive(Clone, Debug)]
+pub enum JobResult {
+    Success(JobStats),
+    Failed(JobFailure),
+    Cancelled,
+}
+
+/// 转码成功时的统计信息
+#[derive(Clone, Debug)]
+pub struct JobStats {
+    /// 视频时长，ffmpeg 未输出 Duration 时为 None
+    pub media_duration: Option<Duration>,
+    pub elapsed: Duration,
+}
+
+/// 转码失败的原因
+#[derive(Clone, Debug)]
+pub struct JobFailure {
+    pub reason: String,
+    /// 为 false 时用同一预设重试也无济于事（如编码参数无效）
+    pub retryable: bool,
+    /// 为 false 时改用备用预设也无济于事（如磁盘已满、输入文件损坏）
+    pub fallback: bool,
+    /// ffmpeg 输出（不含进度行）的最后若干行
+    pub stderr_tail: Vec<String>,
+}
+
+impl JobFailure {
+    pub fn new(reason: impl Into<String>) -> Self {
+        JobFailure {
+            reason: reason.into(),
+            retryable: true,
+            fallback: true,
+            stderr_tail: Vec::new(),
+        }
+    }
+
+    /// ffmpeg 以非零状态退出时，根据其输出的最后若干行判断失败原因
+    pub fn from_exit(exit_str: String, stderr_tail: Vec<String>) -> Self {
+        let error = FfmpegError::classify(&stderr_tail);
+        let reason = match error {
+            Some(e) => format!("{}, {}", e.explanation(), exit_str),
+            None => exit_str,
+        };
+
+        JobFailure {
+            reason,
+            retryable: error.is_none_or(|e| e.is_retryable()),
+            fallback: error.is_none_or(|e| e.allows_fallback()),
+            stderr_tail,
+        }
+    }
+}
+
+/// 能从 ffmpeg 输出中识别出的失败原因
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum FfmpegError {
+    EncoderUnavailable,
+    InvalidParameters,
+    InvalidData,
+    NoSpace,
+    PermissionDenied,
+    UnsupportedInContainer,
+}
+
+impl FfmpegError {
+    /// 按已知的错误信息对 ffmpeg 输出归类，越靠后的行越接近真正的失败原因
+    pub fn classify(lines: &[String]) -> Option<FfmpegError> {
+        const PATTERNS: [(&str, FfmpegError); 12] = [
+            ("Unknown encoder", FfmpegError::EncoderUnavailable),
+            ("Encoder not found", FfmpegError::EncoderUnavailable),
+            (
+                "DLL amfrt64.dll failed to open",
+                FfmpegError::EncoderUnavailable,
+            ),
+            ("Cannot load nvcuda.dll", FfmpegError::EncoderUnavailable),
+            ("No capable devices found", FfmpegError::EncoderUnavailable), // 显卡不支持 NVENC
+            (
+                "Invalid data found when processing input",
+                FfmpegError::InvalidData,
+            ),
+            ("moov atom not found", FfmpegError::InvalidData),
+            ("No space left on device", FfmpegError::NoSpace),
+            ("Permission denied", FfmpegError::PermissionDenied),
+            (
+                "not currently supported in container",
+                FfmpegError::UnsupportedInContainer,
+            ),
+            (
+                "Could not find tag for codec",
+                FfmpegError::UnsupportedInContainer,
+            ),
+            (
+                "codec not supported by the muxer",
+                FfmpegError::UnsupportedInContainer,
+            ),
+        ];
+
+        let specific = lines.iter().rev().find_map(|line| {
+            PATTERNS
+                .iter()
+                .find(|(pattern, _)| line.contains(pattern))
+                .map(|(_, error)| *error)
+        });
+        // 编码器初始化失败时 ffmpeg 最后都会输出这一行，没有更具体的原因时才认为是参数错误
+        specific.or_else(|| {
+            lines
+                .iter()
+                .any(|line| line.contains("Error while opening encoder"))
+                .then_some(FfmpegError::InvalidParameters)
+        })
+    }
+
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            FfmpegError::EncoderUnavailable => {
+                "编码器不可用: 当前 ffmpeg 不支持该编码器，或缺少对应的显卡/驱动"
+            }
+            FfmpegError::InvalidParameters => {
+                "编码参数无效: 编码器不接受预设中的参数，如码率、分辨率或像素格式"
+            }
+            FfmpegError::InvalidData => "输入文件数据无效: 文件可能已损坏或不完整",
+            FfmpegError::NoSpace => "磁盘空间不足: 输出位置所在磁盘已满",
+            FfmpegError::PermissionDenied => "没有权限: 无法读取输入文件或写入输出位置",
+            FfmpegError::UnsupportedInContainer => {
+                "封装格式不支持: 某个音视频或字幕流的编码无法放入输出容器"
+            }
+        }
+    }
+
+    /// 用同一预设重试是否可能成功
+    pub fn is_retryable(&self) -> bool {
+        matches!(
+            self,
+            FfmpegError::EncoderUnavailable | FfmpegError::UnsupportedInContainer
+        )
+    }
+
+    /// 改用备用预设是否可能成功：只有输入文件损坏、磁盘已满和没有权限与预设无关
+    pub fn allows_fallback(&self) -> bool {
+        !matches!(
+            self,
+            FfmpegError::InvalidData | FfmpegError::NoSpace | FfmpegError::PermissionDenied
+        )
+    }
+}
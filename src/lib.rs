@@ -0,0 +1,53 @@
+//! 使用 ffmpeg 批量转码视频的核心功能，命令行程序 ffmpegConvert 基于此实现。
+//!
+//! ```no_run
+//! use ffmpeg_convert::{Job, Transcoder, builtin_presets, output_path_for};
+//! use ffmpeg_convert::transcoder::{Control, Event};
+//! use std::path::Path;
+//!
+//! let input = Path::new("video.mkv");
+//! let preset = builtin_presets().remove(0);
+//! let job = Job::new(input, output_path_for(input, &preset.subfix), preset, "ffmpeg");
+//! let result = Transcoder::default().run(&job, &mut |event| {
+//!     if let Event::Progress(p) = event {
+//!         println!("{:.1}%", p.percentage().unwrap_or(0.0));
+//!     }
+//!     Control::Continue
+//! });
+//! ```
+
+pub mod analyze;
+pub mod audio;
+pub mod backend;
+pub mod batch;
+pub mod benchmark;
+pub mod concat;
+pub mod config;
+pub mod crfsearch;
+pub mod discover;
+pub mod ffmpeg;
+pub mod hdr;
+pub mod job;
+pub mod log;
+pub mod loudnorm;
+pub mod media;
+pub mod nogain;
+pub mod preset;
+pub mod progress;
+pub mod quality;
+pub mod remux;
+pub mod replace;
+pub mod selftest;
+pub mod target;
+pub mod transcoder;
+pub mod trim;
+pub mod verify;
+
+pub use backend::{Backend, FakeBackend, FakeRun};
+pub use batch::{Batch, BatchObserver, BatchReport, FileOutcome, FileReport, ShutdownStatus};
+pub use config::Settings;
+pub use job::{Job, JobFailure, JobResult, JobStats, output_path_for};
+pub use media::MediaInfo;
+pub use preset::{Preset, builtin_presets};
+pub use progress::Progress;
+pub use transcoder::Transcoder;
@@ -0,0 +1,57 @@
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+/// 追加写入的转码日志，每条记录以时间开头
+#[derive(Clone, Debug)]
+pub struct Logger {
+    path: Option<PathBuf>,
+}
+
+impl Logger {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Logger {
+            path: Some(path.into()),
+        }
+    }
+
+    /// 不写日志
+    pub fn disabled() -> Self {
+        Logger { path: None }
+    }
+
+    pub fn path(&self) -> Option<&Path> {
+        self.path.as_deref()
+    }
+
+    /// 写入一条记录，格式为 "[时间] 内容"
+    pub fn log(&self, content: &str) {
+        self.append(&format!("[{}] {}", timestamp(), content));
+    }
+
+    /// 原样写入一行（或多行）内容
+    pub fn append(&self, content: &str) {
+        let Some(path) = &self.path else {
+            return;
+        };
+
+        match std::fs::OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)
+        {
+            Ok(mut f) => {
+                let _ = writeln!(f, "{}", content);
+            }
+            Err(e) => {
+                eprintln!("无法打开日志文件 {}: {}", path.display(), e);
+            }
+        }
+    }
+}
+
+pub fn timestamp() -> String {
+    chrono::Local::now().format("%Y-%m-%d %H:%M:%S").to_string()
+}
+
+/// 多行日志记录中后续行的缩进，与 "[2025-01-01 00:00:00] " 对齐
+pub const CONTINUATION_INDENT: &str = "                      ";
@@ -0,0 +1,186 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+use crate::concat::{Concat, ConcatMethod};
+use crate::trim::Trim;
+
+/// 未指定 loudnorm_tp 时的真峰值上限 (dBTP)
+pub const DEFAULT_TRUE_PEAK: f64 = -1.5;
+/// 响度范围目标 (LU)，测得的范围更大时按测得的值，以便尽量使用线性调整
+const LOUDNESS_RANGE: f64 = 11.0;
+/// loudnorm 接受的最大响度范围
+const MAX_LOUDNESS_RANGE: f64 = 20.0;
+
+/// 响度标准化的目标 (EBU R128)
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct LoudnessTarget {
+    /// 整体响度 (LUFS)，例如 -16
+    pub integrated: f64,
+    /// 真峰值上限 (dBTP)
+    pub true_peak: f64,
+}
+
+/// 第一遍 loudnorm 测得的响度
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Loudness {
+    /// 整体响度 (LUFS)
+    pub integrated: f64,
+    /// 真峰值 (dBTP)
+    pub true_peak: f64,
+    /// 响度范围 (LU)
+    pub range: f64,
+    pub threshold: f64,
+    /// 达到目标还需的增益偏移 (LU)
+    pub target_offset: f64,
+}
+
+impl LoudnessTarget {
+    /// 测得的响度能否整体线性调整到目标：调整后峰值不超过上限，且响度范围不超过 loudnorm 的上限，否则 loudnorm 会改用动态调整
+    pub fn linear_possible(&self, measured: &Loudness) -> bool {
+        measured.true_peak + (self.integrated - measured.integrated) <= self.true_peak
+            && measured.range <= MAX_LOUDNESS_RANGE
+    }
+
+    fn range(&self, measured: &Loudness) -> f64 {
+        measured.range.clamp(LOUDNESS_RANGE, MAX_LOUDNESS_RANGE)
+    }
+}
+
+/// 第一遍：对第一条音轨运行 loudnorm 测量响度，返回测得的响度和音轨的采样率。
+/// audio_filter 为预设中已有的 -af，测量的是经过它处理后的声音；trim 不为 None 时只测量剪辑范围内的声音
+pub fn measure(
+    ffmpeg: &Path,
+    input: &Path,
+    trim: Option<Trim>,
+    audio_filter: Option<&str>,
+    target: LoudnessTarget,
+) -> Result<(Loudness, Option<u32>), String> {
+    let filter = measure_filter(audio_filter, target);
+    let trim = trim.unwrap_or_default();
+    let mut command = Command::new(ffmpeg);
+    command
+        .args(["-hide_banner", "-nostats"])
+        .args(trim.input_args())
+        .arg("-i")
+        .arg(input)
+        .args(trim.output_args())
+        .args([
+            "-map", "0:a:0?", "-vn", "-sn", "-dn", "-af", &filter, "-f", "null", "-",
+        ]);
+    run_measure(ffmpeg, command)
+}
+
+/// 测量合并后的响度：各文件的第一条音轨按转码时的方式拼接后测量。
+/// 用 concat 滤镜拼接时声音统一为 48 kHz，返回的采样率为 48000
+pub fn measure_concat(
+    ffmpeg: &Path,
+    concat: &Concat,
+    audio_filter: Option<&str>,
+    target: LoudnessTarget,
+) -> Result<(Loudness, Option<u32>), String> {
+    let filter = measure_filter(audio_filter, target);
+    let args = concat
+        .loudness_args(&filter)
+        .ok_or("有文件没有音轨，合并的输出不含音频")?;
+    let mut command = Command::new(ffmpeg);
+    command.args(["-hide_banner", "-nostats"]).args(args);
+    let (loudness, sample_rate) = run_measure(ffmpeg, command)?;
+    match concat.method {
+        ConcatMethod::Demuxer { .. } => Ok((loudness, sample_rate)),
+        ConcatMethod::Filter { .. } => Ok((loudness, Some(48000))),
+    }
+}
+
+// 测量用的滤镜：预设已有的 -af 之后接上 loudnorm
+fn measure_filter(audio_filter: Option<&str>, target: LoudnessTarget) -> String {
+    let loudnorm = format!(
+        "loudnorm=I={}:TP={}:LRA={}:print_format=json",
+        target.integrated, target.true_peak, LOUDNESS_RANGE
+    );
+    match audio_filter {
+        Some(existing) => format!("{},{}", existing, loudnorm),
+        None => loudnorm,
+    }
+}
+
+// 运行测量并读取 loudnorm 输出的 JSON
+fn run_measure(ffmpeg: &Path, mut command: Command) -> Result<(Loudness, Option<u32>), String> {
+    let output = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|e| format!("无法启动 ffmpeg {}: {}", ffmpeg.display(), e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        return Err(stderr.lines().last().unwrap_or("").trim().to_string());
+    }
+    let loudness = parse_loudnorm_json(&stderr).ok_or("没有音轨")?;
+    if !loudness.integrated.is_finite() || !loudness.true_peak.is_finite() {
+        return Err("音轨没有声音".to_string());
+    }
+    Ok((loudness, parse_sample_rate(&stderr)))
+}
+
+/// 解析 loudnorm 的 print_format=json 输出，例如
+///   "input_i" : "-27.61",
+pub fn parse_loudnorm_json(output: &str) -> Option<Loudness> {
+    let start = output.rfind("\"input_i\"")?;
+    let block = &output[start
+        ..output[start..]
+            .find('}')
+            .map_or(output.len(), |i| start + i)];
+    let value = |name: &str| -> Option<f64> {
+        block.lines().find_map(|line| {
+            let (key, value) = line.split_once(':')?;
+            (key.trim().trim_matches('"') == name).then(|| {
+                value
+                    .trim()
+                    .trim_end_matches(',')
+                    .trim_matches('"')
+                    .parse()
+                    .ok()
+            })?
+        })
+    };
+
+    Some(Loudness {
+        integrated: value("input_i")?,
+        true_peak: value("input_tp")?,
+        range: value("input_lra")?,
+        threshold: value("input_thresh")?,
+        target_offset: value("target_offset")?,
+    })
+}
+
+/// 从 ffmpeg 输出的音频流信息中读取采样率，例如
+///   Stream #0:1(und): Audio: aac (LC) (mp4a / 0x6134706D), 44100 Hz, stereo, fltp, 128 kb/s
+pub fn parse_sample_rate(output: &str) -> Option<u32> {
+    output.lines().find_map(|line| {
+        let (_, info) = line.split_once(": Audio: ")?;
+        info.split(',')
+            .find_map(|part| part.trim().strip_suffix(" Hz")?.parse().ok())
+    })
+}
+
+/// 第二遍的滤镜：用测得的值做标准化（能线性调整时线性调整）。loudnorm 内部以 192 kHz 处理，之后恢复原采样率
+pub fn normalize_filters(
+    target: LoudnessTarget,
+    measured: &Loudness,
+    sample_rate: Option<u32>,
+) -> Vec<String> {
+    vec![
+        format!(
+            "loudnorm=I={}:TP={}:LRA={}:measured_I={}:measured_TP={}:measured_LRA={}:measured_thresh={}:offset={}:linear=true",
+            target.integrated,
+            target.true_peak,
+            target.range(measured),
+            measured.integrated,
+            measured.true_peak,
+            measured.range,
+            measured.threshold,
+            measured.target_offset
+        ),
+        format!("aresample={}", sample_rate.unwrap_or(48000)),
+    ]
+}
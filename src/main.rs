@@ -1,88 +1,558 @@
+use std::collections::HashMap;
 use std::env;
-use std::ffi::OsStr;
 use std::fmt;
-use std::io::{BufReader, Read, Write};
-use std::os::windows::ffi::OsStrExt;
+use std::io::Write;
 use std::path::{Path, PathBuf};
-use std::process::{Command, Stdio};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
 use std::thread::sleep;
 use std::time::Duration;
-use winapi::shared::ntdef::HANDLE;
-use winapi::um::wincon::SetConsoleTitleW;
 
+use clap::Parser;
+use ffmpeg_convert::analyze::Analyzer;
+use ffmpeg_convert::batch::{
+    Batch, BatchObserver, FileOutcome, FileReport, ShutdownStatus, system_shutdown,
+};
+use ffmpeg_convert::benchmark::Benchmark;
+use ffmpeg_convert::concat::{Concatenator, group_by_folder};
+use ffmpeg_convert::config::{Settings, load_config};
+use ffmpeg_convert::crfsearch::{CrfSearch, Metric};
+use ffmpeg_convert::discover::{AUDIO_EXTS, VIDEO_EXTS, collect_video_files};
+use ffmpeg_convert::ffmpeg::{
+    Capabilities, check_version, executable_name, load_capabilities, resolve_program,
+};
+use ffmpeg_convert::hdr::supports_hdr;
+use ffmpeg_convert::job::{Job, JobFailure};
+use ffmpeg_convert::log::Logger;
+use ffmpeg_convert::nogain::{NoGainAction, NoGainPolicy};
+use ffmpeg_convert::preset::{Preset, builtin_audio_presets, builtin_presets, presets_for_mode};
+use ffmpeg_convert::progress::{format_duration, format_size};
+use ffmpeg_convert::remux::Remuxer;
+use ffmpeg_convert::replace::{ReplaceMode, ReplaceOriginal, records_in_log};
+use ffmpeg_convert::selftest::{CLIP_DURATION, SelfTest, SelfTestStatus};
+use ffmpeg_convert::target::{RateTarget, parse_bitrate, parse_size};
+use ffmpeg_convert::transcoder::{Control, Event, Transcoder};
+use ffmpeg_convert::trim::{Trim, parse_time};
+use ffmpeg_convert::verify::{Verifier, VerifyMode};
+
+#[cfg(windows)]
 pub fn set_console_title(title: &str) -> bool {
+    use std::ffi::OsStr;
+    use std::os::windows::ffi::OsStrExt;
+
     let wide: Vec<u16> = OsStr::new(title)
         .encode_wide()
         .chain(std::iter::once(0))
         .collect();
 
-    unsafe { SetConsoleTitleW(wide.as_ptr()) != 0 }
+    unsafe { winapi::um::wincon::SetConsoleTitleW(wide.as_ptr()) != 0 }
+}
+
+// 其他平台用 OSC 转义序列设置终端标题
+#[cfg(not(windows))]
+pub fn set_console_title(title: &str) -> bool {
+    print!("\x1b]0;{}\x07", title);
+    true
+}
+
+// 转码过程中收到的 Ctrl+C 次数，第一次询问如何处理，第二次强制退出
+static CTRL_C_COUNT: AtomicU32 = AtomicU32::new(0);
+// 选择了“完成当前文件后停止”
+static STOP_AFTER_CURRENT: AtomicBool = AtomicBool::new(false);
+// 正在处理文件（包括两次 ffmpeg 运行之间的分析、校验等），此时的 Ctrl+C 交给转码循环处理
+static FILE_IN_PROGRESS: AtomicBool = AtomicBool::new(false);
+// 正在运行的 ffmpeg 进程 ID 及其输出文件，供强制退出时清理；每次 ffmpeg 结束后清空
+static CURRENT_JOB: Mutex<Option<(u32, PathBuf)>> = Mutex::new(None);
+
+#[cfg(windows)]
+unsafe extern "system" fn ctrl_handler(ctrl_type: u32) -> i32 {
+    use winapi::shared::minwindef::{FALSE, TRUE};
+    use winapi::um::wincon::{CTRL_BREAK_EVENT, CTRL_C_EVENT};
+
+    if ctrl_type != CTRL_C_EVENT && ctrl_type != CTRL_BREAK_EVENT {
+        return FALSE;
+    }
+
+    // 没有正在处理的文件时，沿用系统默认处理（直接退出）
+    if !FILE_IN_PROGRESS.load(Ordering::SeqCst) {
+        return FALSE;
+    }
+
+    // 第一次 Ctrl+C 交给转码循环询问用户，在下一次 ffmpeg 运行时询问
+    if CTRL_C_COUNT.fetch_add(1, Ordering::SeqCst) == 0 {
+        return TRUE;
+    }
+
+    // 第二次 Ctrl+C: 结束正在运行的 ffmpeg，删除未完成的输出文件后强制退出；
+    // 两次运行之间没有 ffmpeg 在运行，已完成的输出保持不动
+    let current_job = match CURRENT_JOB.lock() {
+        Ok(job) => job.clone(),
+        Err(_) => None,
+    };
+    let Some((pid, output_path)) = current_job else {
+        Logger::new(exe_sidecar_path("log")).log("强制退出");
+        eprintln!("\n\n已强制退出");
+        std::process::exit(130);
+    };
+    unsafe {
+        let process = winapi::um::processthreadsapi::OpenProcess(
+            winapi::um::winnt::PROCESS_TERMINATE,
+            FALSE,
+            pid,
+        );
+        if !process.is_null() {
+            winapi::um::processthreadsapi::TerminateProcess(process, 1);
+            winapi::um::handleapi::CloseHandle(process);
+        }
+    }
+    sleep(Duration::from_millis(500)); // 等待 ffmpeg 释放输出文件
+    let _ = std::fs::remove_file(&output_path);
+    Logger::new(exe_sidecar_path("log")).log(&format!("强制退出: {}", output_path.display()));
+    eprintln!("\n\n已强制退出，未完成的输出文件已删除");
+    std::process::exit(130);
+}
+
+fn install_ctrl_handler() {
+    #[cfg(windows)]
+    unsafe {
+        winapi::um::consoleapi::SetConsoleCtrlHandler(
+            Some(ctrl_handler),
+            winapi::shared::minwindef::TRUE,
+        );
+    }
+}
+
+enum CtrlCAction {
+    FinishThenStop,
+    AbortNow,
+}
+
+fn ask_ctrl_c_action() -> CtrlCAction {
+    println!(
+        "\n\n收到 Ctrl+C，请选择: 1 完成当前文件后停止  2 立即中止当前文件 (再按一次 Ctrl+C 强制退出)"
+    );
+    loop {
+        print!("请输入序号: ");
+        std::io::stdout().flush().unwrap();
+
+        let mut input = String::new();
+        match std::io::stdin().read_line(&mut input) {
+            Ok(0) | Err(_) => return CtrlCAction::AbortNow,
+            Ok(_) => match input.trim() {
+                "1" => return CtrlCAction::FinishThenStop,
+                "2" => return CtrlCAction::AbortNow,
+                _ => {}
+            },
+        }
+    }
+}
+
+// 包装一个带有 Drop 的临时值，保证在 println 完成后恢复控制台颜色
+struct ColorF64 {
+    val: f64,
+    #[cfg(windows)]
+    handle: winapi::shared::ntdef::HANDLE,
+}
+
+impl ColorF64 {
+    // 体积变大显示红色，缩小超过 20% 显示绿色，其余缩小显示蓝色
+    fn new(val: f64) -> Self {
+        #[cfg(windows)]
+        {
+            let attr: u16 = if val > 0.0 {
+                0x0C // 明亮红色 (FOREGROUND_RED | FOREGROUND_INTENSITY)
+            } else if val < -20.0 {
+                0x0A // 明亮绿色 (FOREGROUND_GREEN | FOREGROUND_INTENSITY)
+            } else if val < 0.0 {
+                0x09 // 蓝色 (FOREGROUND_BLUE | FOREGROUND_INTENSITY)
+            } else {
+                0x07 // 默认
+            };
+
+            let handle = unsafe {
+                winapi::um::processenv::GetStdHandle(winapi::um::winbase::STD_OUTPUT_HANDLE)
+            };
+            unsafe {
+                let _ = winapi::um::wincon::SetConsoleTextAttribute(handle, attr);
+            }
+            ColorF64 { val, handle }
+        }
+
+        #[cfg(not(windows))]
+        ColorF64 { val }
+    }
+}
+
+impl fmt::Display for ColorF64 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        // 保证以一位小数输出（与原来 {:.1} 一致）
+        #[cfg(windows)]
+        return write!(f, "{:.1}", self.val);
+
+        // 其他平台用 ANSI 颜色
+        #[cfg(not(windows))]
+        {
+            let color = if self.val > 0.0 {
+                "91"
+            } else if self.val < -20.0 {
+                "92"
+            } else if self.val < 0.0 {
+                "94"
+            } else {
+                "0"
+            };
+            write!(f, "\x1b[{}m{:.1}\x1b[0m", color, self.val)
+        }
+    }
+}
+
+#[cfg(windows)]
+impl Drop for ColorF64 {
+    fn drop(&mut self) {
+        unsafe {
+            // 恢复默认颜色（白色）
+            let _ = winapi::um::wincon::SetConsoleTextAttribute(self.handle, 0x07);
+        }
+    }
+}
+
+#[derive(Parser)]
+#[clap(name = "ffmpegConvert", version, about = "使用 ffmpeg 给视频批量转码")]
+struct Cli {
+    /// 要转码的视频文件或文件夹
+    paths: Vec<String>,
+
+    /// 超过多少分钟没有进度则判定 ffmpeg 卡住并跳过该文件，0 表示不检测
+    #[clap(long, value_name = "MINUTES")]
+    stall_timeout: Option<u64>,
+
+    /// 单个文件的转码用时上限，为视频时长的倍数，0 表示不限制
+    #[clap(long, value_name = "RATIO")]
+    timeout_ratio: Option<f64>,
+
+    /// 转码失败后使用同一预设重试的次数，用完后再改用备用预设
+    #[clap(long, value_name = "COUNT")]
+    retries: Option<u32>,
+
+    /// ffmpeg 程序路径，优先于环境变量 FFMPEG_PATH 和配置文件
+    #[clap(long, value_name = "PATH")]
+    ffmpeg: Option<PathBuf>,
+
+    /// ffprobe 程序路径，优先于环境变量 FFPROBE_PATH 和配置文件
+    #[clap(long, value_name = "PATH")]
+    ffprobe: Option<PathBuf>,
+
+    /// 转码成功后校验输出: off 不校验，probe 比较时长和帧数，decode 另外完整解码一遍
+    #[clap(long, value_name = "MODE")]
+    verify: Option<String>,
+
+    /// 输出至少要比原文件小多少 (%) 才保留，否则按配置项 no_gain 处理（默认删除输出）
+    #[clap(long, value_name = "PERCENT")]
+    min_saving: Option<f64>,
+
+    /// 按目标文件大小转码（两遍编码），例如 25M、1.5G，取代预设中的 CRF/QP
+    #[clap(long, value_name = "SIZE")]
+    target_size: Option<String>,
+
+    /// 按目标视频码率转码（两遍编码），例如 2500k，取代预设中的 CRF/QP
+    #[clap(long, value_name = "BITRATE")]
+    target_bitrate: Option<String>,
+
+    /// 转码并校验成功后用输出替换原文件: archive 把原文件移到归档文件夹，trash 移到回收站
+    #[clap(long, value_name = "MODE")]
+    replace: Option<String>,
+
+    /// 检测视频中的黑边，转码时裁掉
+    #[clap(long)]
+    autocrop: bool,
+
+    /// 检测隔行扫描和胶片过带的视频，转码时自动去隔行
+    #[clap(long)]
+    deinterlace: bool,
+
+    /// 音频模式: 转码音频文件（FLAC、WAV、MP3 等），或提取视频文件中的音频，使用音频预设
+    #[clap(
+        long,
+        conflicts_with_all = &["self-test", "benchmark", "target-size", "target-bitrate"]
+    )]
+    audio: bool,
+
+    /// 只转码从此时间开始的一段，例如 90、1:30、01:02:03.5；输入文件旁的 `文件名.trim` 优先
+    #[clap(long, value_name = "TIME")]
+    start: Option<String>,
+
+    /// 只转码到此时间为止
+    #[clap(long, value_name = "TIME", conflicts_with = "duration")]
+    end: Option<String>,
+
+    /// 只转码这么长的一段，从 --start 开始
+    #[clap(long, value_name = "TIME")]
+    duration: Option<String>,
+
+    /// 合并模式: 把同一文件夹中的视频按文件名顺序合并为一个输出，例如行车记录仪和运动相机分段录制的视频
+    #[clap(
+        long,
+        conflicts_with_all = &["self-test", "benchmark", "audio", "start", "end", "duration"]
+    )]
+    concat: bool,
+
+    /// 撤销替换: 按日志把原文件放回原处，输出改回原来的文件名；指定了文件或文件夹时只撤销其中的文件
+    #[clap(long)]
+    undo: bool,
+
+    /// 自检: 生成测试片段，用每个预设转码并检查输出能否解码、时长和编码格式，最后列出各预设是否通过
+    #[clap(long)]
+    self_test: bool,
+
+    /// 预设对比: 从第一个视频文件中截取几段，用各预设转码，比较速度、体积和画质 (SSIM/PSNR，支持时包括 VMAF)
+    #[clap(long)]
+    benchmark: bool,
+
+    /// 预设对比中使用的预设序号，例如 1,3，默认为全部可用的预设
+    #[clap(long, value_name = "N,N", use_value_delimiter = true)]
+    benchmark_presets: Vec<usize>,
 }
 
-struct ConvertParameter {
-    params: &'static str,
-    subfix: &'static str,
-    description: &'static str,
+// 命令行参数优先于配置文件
+fn apply_cli(settings: &mut Settings, cli: &Cli) {
+    if let Some(v) = cli.stall_timeout {
+        settings.stall_timeout_minutes = v;
+    }
+    if let Some(v) = cli.timeout_ratio {
+        settings.timeout_ratio = v;
+    }
+    if let Some(v) = cli.retries {
+        settings.retries = v;
+    }
+    if let Some(v) = cli.min_saving {
+        settings.min_saving = Some(v);
+    }
+    if cli.autocrop {
+        settings.autocrop = true;
+    }
+    if cli.deinterlace {
+        settings.deinterlace = true;
+    }
+    if let Some(v) = &cli.verify
+        && let Err(e) = settings.set("verify", v)
+    {
+        eprintln!("{}", e);
+    }
+    if let Some(v) = &cli.replace
+        && let Err(e) = settings.set("replace", v)
+    {
+        eprintln!("{}", e);
+    }
 }
 
-fn log_file_path() -> PathBuf {
+// 与可执行文件同名但扩展名不同的旁侧文件，例如配置 .txt、日志 .log、能力缓存 .cache
+fn exe_sidecar_path(extension: &str) -> PathBuf {
     let mut p = env::current_exe().expect("无法获取可执行文件路径");
-    p.set_extension("log");
+    p.set_extension(extension);
     p
 }
 
-// 读取额外参数（从与可执行文件同名但扩展名为 .txt 的旁侧文件）
-fn load_params_from_sidecar(convert_params: &mut Vec<ConvertParameter>) {
-    if let Ok(mut sidecar) = env::current_exe() {
-        sidecar.set_extension("txt");
-        if !sidecar.exists() {
-            return;
+// 在控制台显示批量转码的进度，并处理 Ctrl+C
+struct ConsoleObserver {
+    title_prefix: String,
+    percent_int_last: i32,
+    attempt: u32,
+}
+
+impl ConsoleObserver {
+    // 第一次 Ctrl+C 后询问用户，返回是否立即中止当前文件
+    fn check_ctrl_c(&mut self) -> Control {
+        if CTRL_C_COUNT.load(Ordering::SeqCst) == 0 {
+            return Control::Continue;
         }
 
-        let content = match std::fs::read_to_string(&sidecar) {
-            Ok(s) => s,
-            Err(_) => return,
-        };
+        // 用户作出选择后才清零，选择之前再按一次 Ctrl+C 强制退出
+        let action = ask_ctrl_c_action();
+        CTRL_C_COUNT.store(0, Ordering::SeqCst);
+        match action {
+            CtrlCAction::FinishThenStop => {
+                STOP_AFTER_CURRENT.store(true, Ordering::SeqCst);
+                println!("当前文件完成后将停止批量转码\n");
+                Control::Continue
+            }
+            CtrlCAction::AbortNow => Control::Abort,
+        }
+    }
+}
+
+impl BatchObserver for ConsoleObserver {
+    fn file_started(&mut self, index: usize, total: usize, input: &Path) {
+        FILE_IN_PROGRESS.store(true, Ordering::SeqCst);
+        println!("[{}/{}] 处理中: {}", index, total, input.display());
+        self.title_prefix = format!("[{}/{}]", index, total);
+        self.percent_int_last = -1;
+        self.attempt = 1;
+    }
 
-        for line in content.lines() {
-            let line = line.trim();
-            if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
-                continue; // 跳过空行和注释行
+    fn event(&mut self, job: &Job, event: Event) -> Control {
+        let progress = match event {
+            Event::Started { pid } => {
+                *CURRENT_JOB.lock().unwrap() = Some((pid, job.output.clone()));
+                self.percent_int_last = -1;
+                return Control::Continue;
             }
+            Event::Exited => {
+                *CURRENT_JOB.lock().unwrap() = None;
+                return Control::Continue;
+            }
+            Event::Waiting => return self.check_ctrl_c(),
+            Event::Progress(progress) => progress,
+        };
 
-            // 按第一个 '#' 分割，前面是参数，中间是输出文件名称的附加后缀，后面是描述（可选）
-            let parts: Vec<&str> = line.split('#').map(|s| s.trim()).collect();
-            if parts.len() < 2 {
-                continue; // 至少需要参数和输出后缀
+        if self.check_ctrl_c() == Control::Abort {
+            return Control::Abort;
+        }
+
+        let (Some(total), Some(percentage)) = (progress.total, progress.percentage()) else {
+            return Control::Continue;
+        };
+
+        let remain_str = match progress.remaining() {
+            Some(remaining) if !remaining.is_zero() => {
+                format!("剩余:{}", format_duration(&remaining))
             }
+            _ => "已完成                ".to_string(),
+        };
+
+        // 两遍编码时显示当前是第几遍
+        let pass_str = match progress.pass {
+            Some((pass, count)) => format!("第{}/{}遍 ", pass, count),
+            None => String::new(),
+        };
+
+        // 在同一行更新进度
+        print!(
+            "\r    [{:3.1}%] {}{} / {} 速度:{} 用时:{} {}   ",
+            percentage,
+            pass_str,
+            format_duration(&progress.current_time),
+            format_duration(&total),
+            progress.speed_str,
+            format_duration(&Duration::from_millis(progress.elapsed.as_millis() as u64)),
+            remain_str
+        );
+        std::io::stdout().flush().unwrap();
+
+        let percen_int = percentage as i32;
+        if percen_int != self.percent_int_last {
+            self.percent_int_last = percen_int;
+
+            set_console_title(&format!(
+                "{} {}% {}",
+                self.title_prefix,
+                percen_int,
+                job.input
+                    .file_name()
+                    .map(|s| s.to_string_lossy())
+                    .unwrap_or_else(|| job.input.to_string_lossy())
+            ));
+        }
 
-            let params_part = parts[0];
-            let subfix_part = parts[1];
-            let desc_part = if parts.len() > 2 { parts[2] } else { parts[0] };
+        Control::Continue
+    }
 
-            // 过滤无意义行：参数部分不能为空且应包含 '-'（简单判断）
-            if params_part.is_empty() || !params_part.contains('-') {
-                continue;
+    fn attempt_failed(&mut self, failure: &JobFailure, next: Option<&Preset>) {
+        if !failure.stderr_tail.is_empty() {
+            eprintln!(
+                "\n\n    ffmpeg 输出的最后 {} 行:",
+                failure.stderr_tail.len()
+            );
+            for line in failure.stderr_tail.iter() {
+                eprintln!("    | {}", line);
             }
+        }
+
+        if let Some(next) = next {
+            eprintln!(
+                "\n第 {} 次尝试失败 ({})，重试使用预设: {}",
+                self.attempt, failure.reason, next.description
+            );
+            self.attempt += 1;
+        }
+    }
+
+    fn file_finished(&mut self, report: &FileReport) {
+        FILE_IN_PROGRESS.store(false, Ordering::SeqCst);
+
+        match &report.outcome {
+            FileOutcome::Converted { stats, .. } | FileOutcome::NoGain { stats, .. } => {
+                let elapsed_secs = stats.elapsed.as_secs().max(1);
+
+                // ffmpeg的进度输出可能达不到100%， 确保显示100%完成
+                if let Some(total) = stats.media_duration {
+                    print!(
+                        "\r    [100%] 视频时长:{} 速度:{:1.1}x 用时:{} 已完成                ",
+                        format_duration(&total),
+                        total.as_secs_f64() / (elapsed_secs as f64),
+                        format_duration(&Duration::from_secs(elapsed_secs))
+                    );
+                } else {
+                    print!("\r    [100%]  ");
+                }
+
+                // 再输出文件体积对比，例如: 795.46 MB -> 389.43 MB (-51%)
+                if let (Some((input_size, output_size)), Some(reduction)) =
+                    (report.outcome.sizes(), report.size_change_percent())
+                {
+                    println!();
+                    println!(
+                        "    {} -> {} ({}%)",
+                        format_size(input_size as f64),
+                        format_size(output_size as f64),
+                        ColorF64::new(reduction)
+                    );
+                }
+
+                if let FileOutcome::NoGain { action, kept, .. } = &report.outcome {
+                    if kept.is_none() && *action != NoGainAction::Delete {
+                        println!("    体积减少不足，已删除输出，改用原文件失败 (详见日志)");
+                    } else {
+                        println!("    体积减少不足，{}", action.description());
+                    }
+                }
 
-            // 将动态字符串泄漏为 'static，方便与现有 ConvertParameter<'static> 兼容
-            let params: &'static str = Box::leak(params_part.to_string().into_boxed_str());
-            let subfix: &'static str = Box::leak(subfix_part.to_string().into_boxed_str());
-            let description: &'static str = Box::leak(desc_part.to_string().into_boxed_str());
+                match &report.replaced {
+                    Some(Ok(replacement)) => println!(
+                        "    已替换原文件，原文件移到: {}",
+                        replacement
+                            .original
+                            .as_ref()
+                            .map_or("回收站".to_string(), |p| p.display().to_string())
+                    ),
+                    Some(Err(e)) => println!("    无法替换原文件 ({})，已保留输出", e),
+                    None => {}
+                }
 
-            convert_params.push(ConvertParameter {
-                params,
-                subfix,
-                description,
-            });
+                std::io::stdout().flush().unwrap();
+                println!(); // 换行，为下一个文件的处理做准备
+            }
+            FileOutcome::Failed(failure) => {
+                eprintln!(
+                    "\n处理失败: {} ({})",
+                    report.input.display(),
+                    failure.reason
+                );
+            }
+            FileOutcome::Cancelled => {
+                println!("\n已中止: {}", report.input.display());
+            }
         }
     }
+
+    fn stop_requested(&mut self) -> bool {
+        STOP_AFTER_CURRENT.load(Ordering::SeqCst)
+    }
 }
 
 fn main() {
-    let args: Vec<String> = env::args().skip(1).collect();
+    let cli = Cli::parse();
 
-    if args.is_empty() {
+    if cli.paths.is_empty() && !cli.self_test && !cli.undo {
         eprintln!(concat!(
             "请提供至少一个文件或文件夹路径作为参数\n\n",
             "本软件用于给视频批量转码，请把视频文件或文件夹拖到本软件图标上即可，支持多个一起拖拽\n\n",
@@ -94,43 +564,231 @@ fn main() {
         std::process::exit(1);
     }
 
-    let mut convert_params: Vec<ConvertParameter> = vec![
-        ConvertParameter {
-            params: "-c:a aac -c:v libx265 -crf 23 -preset slow",
-            subfix: "_H265",
-            description: "H265 (libx265)   CPU编码, 较慢",
-        },
-        ConvertParameter {
-            params: "-c:a aac -c:v hevc_amf -quality quality -rc cqp -qp_i 22 -qp_p 22",
-            subfix: "_H265",
-            description: "H265 (hevc_amf)  AMD GPU硬件加速编码, 速度快",
-        },
-        ConvertParameter {
-            params: "-c:a aac -c:v libsvtav1 -crf 28 -preset 4",
-            subfix: "_AV1",
-            description: "AV1  (libsvtav1) CPU编码, 非常慢",
-        },
-        ConvertParameter {
-            params: "-c:a aac -c:v libaom-av1 -crf 28 -cpu-used 8 -b:v 0 -row-mt 1",
-            subfix: "_AV1",
-            description: "AV1  (libaom-av1) CPU编码, 最慢",
-        },
-    ];
-
-    load_params_from_sidecar(&mut convert_params);
+    install_ctrl_handler();
+
+    // 读取额外参数和设置（从与可执行文件同名但扩展名为 .txt 的旁侧文件）
+    // 音频模式只使用音频预设，视频模式只使用视频预设
+    let mut presets = if cli.audio {
+        builtin_audio_presets()
+    } else {
+        builtin_presets()
+    };
+    let mut settings = Settings::default();
+    for warning in load_config(&exe_sidecar_path("txt"), &mut presets, &mut settings) {
+        eprintln!("{}", warning);
+    }
+    let mut presets = presets_for_mode(presets, cli.audio);
+    apply_cli(&mut settings, &cli);
+
+    // 命令行指定的目标大小或码率用于所有预设（包括备用预设），重新封装的预设除外
+    let target = match (&cli.target_size, &cli.target_bitrate) {
+        (Some(size), _) => Some(parse_size(size).map(RateTarget::Size).ok_or(size)),
+        (None, Some(bitrate)) => Some(
+            parse_bitrate(bitrate)
+                .map(RateTarget::Bitrate)
+                .ok_or(bitrate),
+        ),
+        (None, None) => None,
+    };
+    match target {
+        Some(Ok(target)) => {
+            for preset in presets.iter_mut().filter(|p| !p.remux) {
+                preset.target = Some(target);
+            }
+        }
+        Some(Err(value)) => {
+            eprintln!("目标大小或码率无效: {}", value);
+            std::process::exit(1);
+        }
+        None => {}
+    }
+
+    // 命令行指定的剪辑范围用于所有文件
+    let parse_cli_time = |value: &Option<String>| {
+        value.as_ref().map(|v| {
+            parse_time(v).unwrap_or_else(|| {
+                eprintln!("时间无效: {}", v);
+                std::process::exit(1);
+            })
+        })
+    };
+    let trim = match Trim::from_parts(
+        parse_cli_time(&cli.start),
+        parse_cli_time(&cli.end),
+        parse_cli_time(&cli.duration),
+    ) {
+        Ok(trim) => trim,
+        Err(e) => {
+            eprintln!("剪辑范围无效: {}", e);
+            std::process::exit(1);
+        }
+    };
+
+    if cli.undo {
+        std::process::exit(if run_undo(&cli.paths) { 0 } else { 1 });
+    }
+
+    let cache_path = exe_sidecar_path("cache");
+    let ffmpeg = resolve_program(
+        cli.ffmpeg.as_deref(),
+        "FFMPEG_PATH",
+        settings.ffmpeg.as_deref(),
+        &executable_name("ffmpeg"),
+    )
+    .unwrap_or_else(|e| exit_with_error(&e));
+    let Some(caps) = load_capabilities(&ffmpeg, &cache_path) else {
+        exit_with_error(&format!("无法运行 ffmpeg: {}", ffmpeg.display()));
+    };
+    if let Err(e) = check_version(&caps.version, &settings.min_ffmpeg_version) {
+        exit_with_error(&format!("{}: {}", e, ffmpeg.display()));
+    }
+    println!("ffmpeg 版本: {}  ({})", caps.version, ffmpeg.display());
+
+    // ffprobe 用于读取视频信息，找不到时只给出提示
+    let ffprobe = match resolve_program(
+        cli.ffprobe.as_deref(),
+        "FFPROBE_PATH",
+        settings.ffprobe.as_deref(),
+        &executable_name("ffprobe"),
+    ) {
+        Ok(ffprobe) => {
+            println!("ffprobe: {}\n", ffprobe.display());
+            Some(ffprobe)
+        }
+        Err(e) => {
+            eprintln!("警告: {}\n", e);
+            None
+        }
+    };
+
+    // 每个预设使用的 ffmpeg，以及不可用的原因（缺少编码器/滤镜、未配置或版本过低的 ffmpeg）
+    let mut caps_by_binary: HashMap<PathBuf, Option<Capabilities>> = HashMap::new();
+    let mut preset_ffmpeg: Vec<Option<PathBuf>> = Vec::new();
+    let mut unavailable: Vec<Option<String>> = Vec::new();
+    caps_by_binary.insert(ffmpeg.clone(), Some(caps));
+
+    for preset in presets.iter() {
+        let binary = match &preset.ffmpeg_build {
+            None => ffmpeg.clone(),
+            Some(name) => match settings.ffmpeg_builds.get(name) {
+                Some(path) => path.clone(),
+                None => {
+                    preset_ffmpeg.push(None);
+                    unavailable.push(Some(format!("未配置名为 {} 的 ffmpeg", name)));
+                    continue;
+                }
+            },
+        };
+
+        let caps = caps_by_binary
+            .entry(binary.clone())
+            .or_insert_with(|| load_capabilities(&binary, &cache_path));
+        let reason = match caps {
+            None => Some(format!("无法运行 {}", binary.display())),
+            Some(caps) => match check_version(&caps.version, &settings.min_ffmpeg_version) {
+                Err(e) => Some(e),
+                Ok(()) => {
+                    let mut missing = caps.missing_features(&preset.params);
+                    // 目标画质为 VMAF 时 CRF 搜索需要 libvmaf 滤镜
+                    if preset
+                        .quality_target
+                        .is_some_and(|t| t.metric == Metric::Vmaf)
+                        && !caps.filters.contains("libvmaf")
+                    {
+                        missing.push("libvmaf".to_string());
+                    }
+                    // HDR 转 SDR 需要 zscale 滤镜 (libzimg)
+                    if preset.tonemap.is_some() && !caps.filters.contains("zscale") {
+                        missing.push("zscale".to_string());
+                    }
+                    (!missing.is_empty())
+                        .then(|| format!("当前 ffmpeg 缺少 {}", missing.join(", ")))
+                }
+            },
+        };
+
+        preset_ffmpeg.push(reason.is_none().then_some(binary));
+        unavailable.push(reason);
+    }
+
+    if cli.self_test {
+        let Some(ffprobe) = ffprobe else {
+            eprintln!("自检需要 ffprobe 检查输出文件");
+            std::process::exit(1);
+        };
+        // 预设经过与批量转码相同的准备，只是不校验、不替换原文件
+        let batch = Batch {
+            presets,
+            preset_ffmpeg,
+            retries: 0,
+            backend: Box::new(Transcoder::from_settings(&settings)),
+            logger: Logger::new(exe_sidecar_path("log")),
+            verifier: None,
+            no_gain: None,
+            analyzer: Some(new_analyzer(&settings, &ffprobe, &caps_by_binary[&ffmpeg])),
+            crf_search: Some(CrfSearch::new(&ffprobe)),
+            remuxer: Some(Remuxer {
+                ffprobe: ffprobe.clone(),
+            }),
+            trim: None,
+            concat: None,
+            replace: None,
+            shutdown: None,
+        };
+        let self_test = SelfTest::new(&ffmpeg, ffprobe);
+        let passed = run_self_test(&self_test, &batch, &unavailable);
+        std::process::exit(if passed { 0 } else { 1 });
+    }
+
+    if cli.benchmark {
+        let Some(ffprobe) = ffprobe else {
+            eprintln!("预设对比需要 ffprobe 读取视频信息");
+            std::process::exit(1);
+        };
+        let Some(input) = collect_video_files(&cli.paths, &VIDEO_EXTS, Some(&settings.archive_dir))
+            .files
+            .into_iter()
+            .next()
+        else {
+            eprintln!("预设对比需要一个视频文件作为样本");
+            std::process::exit(1);
+        };
+
+        let mut benchmark = Benchmark::new(&ffmpeg, ffprobe);
+        benchmark.vmaf = caps_by_binary[&ffmpeg]
+            .as_ref()
+            .is_some_and(|caps| caps.filters.contains("libvmaf"));
+        let ok = run_benchmark(
+            &benchmark,
+            &settings,
+            &input,
+            &presets,
+            &preset_ffmpeg,
+            &cli.benchmark_presets,
+        );
+        std::process::exit(if ok { 0 } else { 1 });
+    }
 
     println!(
         "选择要转码的目标编码类型的序号，转码完成则正常退出程序。如果输入负数序号则转码完成后将自动关机 (30秒后关机)。\n"
     );
-    for (i, param) in convert_params.iter().enumerate() {
-        println!("  {:<2}: {}", i + 1, param.description);
+    for (i, preset) in presets.iter().enumerate() {
+        match &unavailable[i] {
+            None => println!("  {:<2}: {}", i + 1, preset.description),
+            Some(reason) => println!(
+                "  {:<2}: {}  [不可用: {}]",
+                i + 1,
+                preset.description,
+                reason
+            ),
+        }
     }
     println!();
 
     let mut select_index = 0;
     let mut shutdown_when_done = false;
 
-    while select_index <= 0 || select_index > (convert_params.len() as i32) {
+    while select_index <= 0 || select_index > (presets.len() as i32) {
         print!("请输入序号: ");
         std::io::stdout().flush().unwrap(); // 确保提示立即显示
 
@@ -142,6 +800,15 @@ fn main() {
                 select_index = -select_index;
                 shutdown_when_done = true;
             }
+
+            if select_index > 0
+                && select_index <= (presets.len() as i32)
+                && unavailable[(select_index - 1) as usize].is_some()
+            {
+                println!("该预设不可用，请选择其他预设");
+                select_index = 0;
+                shutdown_when_done = false;
+            }
         }
     }
 
@@ -149,508 +816,390 @@ fn main() {
         println!("提示: 转码完成后，将倒计时30秒关机。\n");
     }
 
-    let video_exts = [
-        "mp4", "mkv", "avi", "mov", "wmv", "flv", "webm", "m4v", "ts", "mpeg", "mpg", "3gp", "rm",
-        "rmvb",
-    ];
-    let mut video_files = Vec::new();
-
-    for arg in args {
-        let path = Path::new(&arg);
-
-        if !path.exists() {
-            eprintln!("路径不存在: {}", arg);
-            continue;
-        }
-
-        if path.is_file() {
-            if is_video_file(path, &video_exts) {
-                if let Ok(absolute_path) = path.canonicalize() {
-                    video_files.push(absolute_path);
-                }
-            } else {
-                eprintln!("跳过非视频文件: {}", arg);
-            }
-        } else if path.is_dir() {
-            find_video_files(path, &video_exts, &mut video_files);
-        }
+    // 音频模式也接受视频文件，提取其中的音频
+    let (exts, kind) = if cli.audio {
+        ([&AUDIO_EXTS[..], &VIDEO_EXTS[..]].concat(), "音视频")
+    } else {
+        (VIDEO_EXTS.to_vec(), "视频")
+    };
+    let discovery = collect_video_files(&cli.paths, &exts, Some(&settings.archive_dir));
+    for arg in discovery.missing.iter() {
+        eprintln!("路径不存在: {}", arg);
     }
+    for arg in discovery.skipped.iter() {
+        eprintln!("跳过非{}文件: {}", kind, arg);
+    }
+    let video_files = discovery.files;
 
-    // 过滤掉 _h265 和 _av1 结尾的文件
-    video_files.retain(|p| {
-        if let Some(stem) = p.file_stem().and_then(|s| s.to_str()) {
-            let lower_stem = stem.to_lowercase();
-            !(lower_stem.ends_with("_h265") || lower_stem.ends_with("_av1"))
-        } else {
-            true
-        }
-    });
-
-    println!("\n找到 {} 个视频文件需要处理", video_files.len());
+    println!("\n找到 {} 个{}文件需要处理", video_files.len(), kind);
     if video_files.is_empty() {
         sleep(Duration::from_secs(2)); // 2秒后自动关闭
         return;
     }
 
-    video_files.sort_by(|a, b| {
-        let a_str = a.to_string_lossy();
-        let b_str = b.to_string_lossy();
-        natural_sort_rs::natural_cmp(&a_str, &b_str)
-    });
-
-    let mut idx = 1;
-    for video_path in video_files.iter() {
-        println!(
-            "{:<2}: {}",
-            idx,
-            video_path.to_string_lossy()[4..].to_string()
-        );
-        idx += 1;
+    for (idx, video_path) in video_files.iter().enumerate() {
+        println!("{:<2}: {}", idx + 1, video_path.display());
     }
     println!();
 
-    let total_files = video_files.len();
-    let mut file_count = 1;
-
-    for video_path in video_files {
-        let video_path = video_path.to_string_lossy();
-        let video_path: &str = &video_path[4..]; // 去掉前面的 "\\?\" 之类的
-
-        println!("[{}/{}] 处理中: {}", file_count, total_files, video_path);
-
-        let output_path = {
-            let mut p = PathBuf::from(video_path);
-            let default_output_name =
-                format!("output_{}", chrono::Local::now().format("%Y%m%d%H%M%S"));
-            let file_stem = p
-                .file_stem()
-                .and_then(|s| s.to_str())
-                .unwrap_or(default_output_name.as_str());
-            let new_file_name = format!(
-                "{}{}.mp4",
-                file_stem,
-                convert_params[(select_index - 1) as usize].subfix
-            );
-            p.set_file_name(
-                new_file_name
-                    .replace("_H264", "")
-                    .replace("_h264", "")
-                    .replace("_H265", "")
-                    .replace("_h265", ""),
+    // 合并需要 ffprobe 比较各文件的流并计算总时长
+    let concat = match (cli.concat, &ffprobe) {
+        (false, _) => None,
+        (true, Some(ffprobe)) => {
+            println!(
+                "合并模式: 同一文件夹中的文件按以上顺序合并，共 {} 个输出\n",
+                group_by_folder(&video_files).len()
             );
-            p
-        };
-
-        // 执行转码并显示进度
-        if !transcode_with_progress(
-            &convert_params[(select_index - 1) as usize],
-            &video_path,
-            &output_path,
-            &format!("[{}/{}]", file_count, total_files),
-        ) {
-            eprintln!("\n处理失败: {}", video_path);
-        } else {
-            println!(); // 换行，为下一个文件的处理做准备
+            Some(Concatenator {
+                ffprobe: ffprobe.clone(),
+            })
         }
+        (true, None) => {
+            eprintln!("合并需要 ffprobe 读取各文件的音视频流");
+            sleep(Duration::from_secs(2));
+            std::process::exit(1);
+        }
+    };
 
-        file_count += 1;
-    }
-
-    if shutdown_when_done {
-        // shutdown.exe -s -t 30
-        Command::new("shutdown.exe")
-            .arg("-s")
-            .arg("-t")
-            .arg("30")
-            .spawn()
-            .expect("无法计划关机");
+    // 只替换校验通过的输出
+    if settings.replace != ReplaceMode::Off && settings.verify == VerifyMode::Off {
+        println!("替换原文件前需要校验输出，已启用 probe 校验\n");
+        settings.verify = VerifyMode::Probe;
     }
-}
 
-fn transcode_with_progress(
-    convert_params: &ConvertParameter,
-    input_path: &str,
-    output_path: &PathBuf,
-    title_prefix: &str,
-) -> bool {
-
-    // 输出日志
-    match std::fs::OpenOptions::new()
-        .create(true)
-        .append(true)
-        .open(log_file_path())
-    {
-        Ok(mut f) => {
-            let ts = chrono::Local::now().format("%Y-%m-%d %H:%M:%S");
-            let _ = writeln!(f, "[{}] 输入: {}", ts, input_path);
-        }
-        Err(e) => {
-            eprintln!("无法打开日志文件 {}: {}", log_file_path().display(), e);
+    // 校验输出文件需要 ffprobe
+    let verifier = match (settings.verify, &ffprobe) {
+        (VerifyMode::Off, _) => None,
+        (mode, Some(ffprobe)) => Some(Verifier {
+            ffprobe: ffprobe.clone(),
+            mode,
+            tolerance: Duration::from_secs_f64(settings.verify_tolerance),
+        }),
+        (_, None) => {
+            eprintln!("警告: 找不到 ffprobe，不校验输出文件\n");
+            None
         }
-    }
-
-    let mut child = Command::new("ffmpeg.exe")
-        .arg("-hide_banner")
-        .arg("-i")
-        .arg(&input_path)
-        .args(convert_params.params.split_whitespace())
-        .arg("-y") // 覆盖输出文件
-        .arg(&output_path)
-        .stderr(Stdio::piped())
-        .stdout(Stdio::null())
-        .stdin(Stdio::null())
-        .spawn()
-        .expect("无法启动 ffmpeg");
-
-    let stderr = child.stderr.take().expect("无法获取 stderr");
-    let reader = BufReader::new(stderr);
-
-    let mut total_duration: Option<Duration> = None;
-    let mut buffer = String::new();
-
-    //当前时间戳
-    let start_timestamp = std::time::Instant::now();
-
-    let mut percent_int_last = -1;
-
-    for byte in reader.bytes() {
-        if let Ok(b) = byte {
-            let ch = b as char;
+    };
 
-            if ch != '\r' && ch != '\n' {
-                buffer.push(ch);
-                continue;
+    // 按分辨率和帧率上限添加滤镜、检测黑边、隔行扫描和 HDR 需要 ffprobe 读取输入信息
+    let analyzer = match &ffprobe {
+        Some(ffprobe) => Some(new_analyzer(&settings, ffprobe, &caps_by_binary[&ffmpeg])),
+        None => {
+            if settings.autocrop
+                || settings.deinterlace
+                || presets.iter().any(|p| {
+                    p.max_res.is_some()
+                        || p.max_fps.is_some()
+                        || p.tonemap.is_some()
+                        || p.video_encoder().is_some_and(supports_hdr)
+                })
+            {
+                eprintln!(
+                    "警告: 找不到 ffprobe，不限制分辨率和帧率，不检测黑边、隔行扫描和 HDR（HDR 视频的输出会按 SDR 标记）\n"
+                );
             }
+            None
+        }
+    };
 
-            if buffer.is_empty() {
-                continue;
+    // CRF 搜索需要 ffprobe 读取视频时长和分辨率
+    let crf_search = match &ffprobe {
+        Some(ffprobe) => Some(CrfSearch::new(ffprobe)),
+        None => {
+            if presets.iter().any(|p| p.quality_target.is_some()) {
+                eprintln!("警告: 找不到 ffprobe，不搜索 CRF，使用预设中的参数\n");
             }
+            None
+        }
+    };
 
-            // 解析总时长
-            if total_duration.is_none() {
-                if let Some(duration) = parse_total_duration(&buffer) {
-                    total_duration = Some(duration);
-                }
+    // 重新封装需要 ffprobe 读取各个流的编码格式
+    let remuxer = match &ffprobe {
+        Some(ffprobe) => Some(Remuxer {
+            ffprobe: ffprobe.clone(),
+        }),
+        None => {
+            if presets.iter().any(|p| p.remux) {
+                eprintln!(
+                    "警告: 找不到 ffprobe，重新封装时不检查编码格式是否兼容，直接复制所有流\n"
+                );
             }
+            None
+        }
+    };
 
-            // 解析进度信息
-            if let Some(total) = total_duration {
-                if let Some(progress) = parse_progress(&buffer) {
-                    let percentage: f64 = if total.as_millis() > 0 {
-                        if progress.current_time == total {
-                            100.0
-                        } else {
-                            ((progress.current_time.as_millis() as f64) * 100.0)
-                                / (total.as_millis() as f64)
-                        }
-                    } else {
-                        0.0
-                    };
-
-                    let elapsed_millis =
-                        (std::time::Instant::now() - start_timestamp).as_millis() as u64;
-
-                    //根据已用时间和百分比计算估计剩余时间
-                    let estimated_remaining_millis = if elapsed_millis < 1000 {
-                        total.as_millis() as u64
-                    } else if percentage > 0.0 && percentage < 100.0 {
-                        let remain_millis =
-                            (100.0 - percentage) * (elapsed_millis as f64) / percentage;
-                        remain_millis as u64
-                    } else if percentage == 100.0 {
-                        0
-                    } else {
-                        total.as_millis() as u64
-                    };
-
-                    let remain_str = if estimated_remaining_millis > 0 {
-                        format!(
-                            "剩余:{}",
-                            format_duration(&Duration::from_millis(estimated_remaining_millis))
-                        )
-                    } else {
-                        "已完成                ".to_string()
-                    };
-
-                    // 在同一行更新进度
-                    print!(
-                        "\r    [{:3.1}%] {} / {} 速度:{} 用时:{} {}   ",
-                        percentage,
-                        format_duration(&progress.current_time),
-                        format_duration(&total),
-                        progress.speed_str,
-                        format_duration(&Duration::from_millis(elapsed_millis)),
-                        remain_str
-                    );
-                    std::io::stdout().flush().unwrap();
-
-                    let percen_int = percentage as i32;
-                    if percen_int != percent_int_last {
-                        percent_int_last = percen_int;
-
-                        set_console_title(&format!(
-                            "{} {}% {}",
-                            title_prefix,
-                            percen_int,
-                            Path::new(input_path)
-                                .file_name()
-                                .and_then(|s| s.to_str())
-                                .unwrap_or(input_path)
-                        ));
-                    }
-                }
-            }
-            buffer.clear();
+    let replace = match (settings.replace, &verifier) {
+        (ReplaceMode::Off, _) => None,
+        (_, None) => {
+            eprintln!("警告: 无法校验输出文件，不替换原文件\n");
+            None
         }
-    }
+        (ReplaceMode::Archive, Some(_)) => {
+            Some(ReplaceOriginal::Archive(settings.archive_dir.clone()))
+        }
+        (ReplaceMode::Trash, Some(_)) => Some(ReplaceOriginal::Trash),
+    };
 
-    let status = child.wait().expect("子进程执行失败");
-    let is_success = status.success();
+    let batch = Batch {
+        presets,
+        preset_ffmpeg,
+        retries: settings.retries,
+        backend: Box::new(Transcoder::from_settings(&settings)),
+        logger: Logger::new(exe_sidecar_path("log")),
+        verifier,
+        analyzer,
+        crf_search,
+        remuxer,
+        trim,
+        concat,
+        no_gain: settings.min_saving.map(|min_saving| NoGainPolicy {
+            min_saving,
+            action: settings.no_gain,
+        }),
+        replace,
+        shutdown: shutdown_when_done.then(|| Box::new(system_shutdown) as Box<_>),
+    };
+    let mut observer = ConsoleObserver {
+        title_prefix: String::new(),
+        percent_int_last: -1,
+        attempt: 1,
+    };
+    let report = batch.run(&video_files, (select_index - 1) as usize, &mut observer);
 
-    if is_success {
-        let mut log_content = format!(
-            "[{}] 输出: {}\n                      ",
-            chrono::Local::now().format("%Y-%m-%d %H:%M:%S"),
-            output_path.display()
+    if report.stopped && STOP_AFTER_CURRENT.load(Ordering::SeqCst) {
+        println!(
+            "已按要求在当前文件完成后停止，剩余 {} 个文件未处理",
+            report.remaining
         );
+    }
 
-        let mut elapsed_secs = (std::time::Instant::now() - start_timestamp).as_secs();
-        if elapsed_secs == 0 {
-            elapsed_secs = 1;
-        }
-
-        // ffmpeg的进度输出可能达不到100%， 确保显示100%完成
-        if let Some(total) = total_duration {
-            print!(
-                "\r    [100%] 视频时长:{} 速度:{:1.1}x 用时:{} 已完成                ",
-                format_duration(&total),
-                total.as_secs_f64() / (elapsed_secs as f64),
-                format_duration(&Duration::from_secs(elapsed_secs))
-            );
+    println!("\n{}", report.summary());
 
-            log_content.push_str(&format!(
-                "视频时长:{} 速度:{:1.1}x 用时:{}    ",
-                format_duration(&total),
-                total.as_secs_f64() / (elapsed_secs as f64),
-                format_duration(&Duration::from_secs(elapsed_secs))
-            ));
-        } else {
-            print!("\r    [100%]  ");
+    match report.shutdown {
+        ShutdownStatus::Cancelled => println!("批量转码已被中止，取消自动关机"),
+        ShutdownStatus::Failed(e) => eprintln!("无法计划关机: {}", e),
+        ShutdownStatus::NotRequested | ShutdownStatus::Scheduled => {}
+    }
+}
 
-            log_content.push_str(&format!(
-                "用时:{}    ",
-                format_duration(&Duration::from_secs(elapsed_secs))
-            ));
-        }
+// 按设置检测黑边和隔行扫描，caps 为主 ffmpeg 的功能
+fn new_analyzer(settings: &Settings, ffprobe: &Path, caps: &Option<Capabilities>) -> Analyzer {
+    Analyzer {
+        ffprobe: ffprobe.to_path_buf(),
+        autocrop: settings.autocrop,
+        // bwdif 画质更好，较旧的 ffmpeg 没有时用 yadif
+        deinterlacer: settings.deinterlace.then(|| {
+            let bwdif = caps
+                .as_ref()
+                .is_some_and(|caps| caps.filters.contains("bwdif"));
+            if bwdif { "bwdif" } else { "yadif" }.to_string()
+        }),
+    }
+}
 
-        // 再输出文件体积对比，例如: 795.46 MB -> 389.43 MB (-51%)
-        if let Ok(input_metadata) = std::fs::metadata(input_path) {
-            if let Ok(output_metadata) = std::fs::metadata(output_path) {
-                let input_size = input_metadata.len() as f64;
-                let output_size = output_metadata.len() as f64;
-                let reduction = if input_size > 0.0 {
-                    100.0 * (output_size - input_size) / input_size
-                } else {
-                    0.0
-                };
-
-                fn format_size(size: f64) -> String {
-                    if size >= 1_073_741_824.0 {
-                        format!("{:.2} GB", size / 1_073_741_824.0)
-                    } else if size >= 1_048_576.0 {
-                        format!("{:.2} MB", size / 1_048_576.0)
-                    } else if size >= 1024.0 {
-                        format!("{:.2} KB", size / 1024.0)
-                    } else {
-                        format!("{:.2} B", size)
-                    }
-                }
-                println!();
+// 执行自检并列出每个预设的结果，全部可用的预设都通过时返回 true
+fn run_self_test(self_test: &SelfTest, batch: &Batch, unavailable: &[Option<String>]) -> bool {
+    println!(
+        "自检: 生成 {} 秒的测试片段，依次用每个预设转码并检查输出\n",
+        CLIP_DURATION.as_secs()
+    );
 
-                log_content.push_str(&format!(
-                    "{} -> {} ({:.1}%)",
-                    format_size(input_size),
-                    format_size(output_size),
-                    reduction
-                ));
+    let mut observer = ConsoleObserver {
+        title_prefix: String::new(),
+        percent_int_last: -1,
+        attempt: 1,
+    };
+    let results = match self_test.run(batch, &mut observer) {
+        Ok(results) => results,
+        Err(e) => {
+            eprintln!("自检失败: {}", e);
+            return false;
+        }
+    };
 
+    println!("\n自检结果:\n");
+    let presets = &batch.presets;
+    let mut passed = true;
+    for result in results.iter() {
+        let preset = &presets[result.preset];
+        match &result.status {
+            SelfTestStatus::Passed => println!(
+                "  {:<2}: 通过  {}  {}",
+                result.preset + 1,
+                format_duration(&result.elapsed),
+                preset.description
+            ),
+            SelfTestStatus::Failed(reason) => {
+                passed = false;
                 println!(
-                    "    {} -> {} ({:.1}%)",
-                    format_size(input_size),
-                    format_size(output_size),
-                    {
-                        // 包装一个带有 Drop 的临时值，保证在 println 完成后恢复控制台颜色
-                        struct ColorF64 {
-                            val: f64,
-                            handle: HANDLE,
-                        }
-                        impl fmt::Display for ColorF64 {
-                            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-                                // 保证以一位小数输出（与原来 {:.1} 一致）
-                                write!(f, "{:.1}", self.val)
-                            }
-                        }
-                        impl Drop for ColorF64 {
-                            fn drop(&mut self) {
-                                unsafe {
-                                    // 恢复默认颜色（白色）
-                                    let _ = winapi::um::wincon::SetConsoleTextAttribute(
-                                        self.handle,
-                                        0x07,
-                                    );
-                                }
-                            }
-                        }
-
-                        let attr: u16 = if reduction > 0.0 {
-                            0x0C // 明亮红色 (FOREGROUND_RED | FOREGROUND_INTENSITY)
-                        } else if reduction < -20.0 {
-                            0x0A // 明亮绿色 (FOREGROUND_GREEN | FOREGROUND_INTENSITY)
-                        } else if reduction < 0.0 {
-                            0x09 // 蓝色 (FOREGROUND_BLUE | FOREGROUND_INTENSITY)
-                        } else {
-                            0x07 // 默认
-                        };
-
-                        let h: HANDLE = unsafe {
-                            winapi::um::processenv::GetStdHandle(
-                                winapi::um::winbase::STD_OUTPUT_HANDLE,
-                            )
-                        };
-                        unsafe {
-                            let _ = winapi::um::wincon::SetConsoleTextAttribute(h, attr);
-                        }
-
-                        ColorF64 {
-                            val: reduction,
-                            handle: h,
-                        }
-                    }
+                    "  {:<2}: 失败  {}  {}\n            {}",
+                    result.preset + 1,
+                    format_duration(&result.elapsed),
+                    preset.description,
+                    reason
                 );
             }
-        }
-
-        std::io::stdout().flush().unwrap();
-
-        // 记录日志
-        match std::fs::OpenOptions::new()
-            .create(true)
-            .append(true)
-            .open(log_file_path())
-        {
-            Ok(mut f) => {
-                let _ = writeln!(f, "{}", log_content);
-            }
-            Err(e) => {
-                eprintln!("无法打开日志文件 {}: {}", log_file_path().display(), e);
-            }
+            SelfTestStatus::Skipped => println!(
+                "  {:<2}: 跳过  {:8}  {}  [不可用: {}]",
+                result.preset + 1,
+                "",
+                preset.description,
+                unavailable[result.preset].as_deref().unwrap_or("")
+            ),
         }
     }
+    println!();
 
-    is_success
+    passed
 }
 
-fn parse_total_duration(line: &str) -> Option<Duration> {
-    if let Some(start) = line.find("Duration: ") {
-        let duration_str = &line[start + 10..];
-        if let Some(comma_pos) = duration_str.find(',') {
-            let time_str = &duration_str[..comma_pos];
-            return parse_time_to_duration(time_str);
+// 执行预设对比并列出结果，selected 为空时对比全部可用的预设
+fn run_benchmark(
+    benchmark: &Benchmark,
+    settings: &Settings,
+    input: &Path,
+    presets: &[Preset],
+    preset_ffmpeg: &[Option<PathBuf>],
+    selected: &[usize],
+) -> bool {
+    let mut candidates = Vec::new();
+    for (i, preset) in presets.iter().enumerate() {
+        if !selected.is_empty() && !selected.contains(&(i + 1)) {
+            continue;
+        }
+        // 重新封装和转为 SDR 的预设依赖对输入的分析，直接转码截取的片段得到的速度和体积不准，
+        // 转为 SDR 后的画面也无法与 HDR 片段比较画质
+        if preset.remux || preset.tonemap.is_some() {
+            if !selected.is_empty() {
+                eprintln!("预设 {} 为重新封装或转为 SDR，无法对比，跳过", i + 1);
+            }
+            continue;
+        }
+        match &preset_ffmpeg[i] {
+            Some(ffmpeg) => candidates.push((i, preset, ffmpeg.clone())),
+            None if !selected.is_empty() => eprintln!("预设 {} 不可用，跳过", i + 1),
+            None => {}
         }
     }
-    None
-}
+    if candidates.is_empty() {
+        eprintln!("没有可对比的预设");
+        return false;
+    }
 
-struct ProgressInfo {
-    current_time: Duration,
-    speed_str: String,
-}
+    println!(
+        "预设对比: 从 {} 中截取 {} 段，每段 {} 秒{}\n",
+        input.display(),
+        benchmark.segments,
+        benchmark.segment_length.as_secs(),
+        if benchmark.vmaf { "，计算 VMAF" } else { "" }
+    );
 
-fn parse_progress(line: &str) -> Option<ProgressInfo> {
-    // 查找 time= 字段
-    let time = if let Some(start) = line.find("time=") {
-        let time_str = &line[start + 5..];
-        if let Some(space_pos) = time_str.find(' ') {
-            parse_time_to_duration(&time_str[..space_pos])
-        } else {
-            None
-        }
-    } else {
-        None
+    let mut observer = ConsoleObserver {
+        title_prefix: String::new(),
+        percent_int_last: -1,
+        attempt: 1,
     };
-
-    // 提取 speed= 后到 x 字符（包含 x）
-    let speed_str = if let Some(start) = line.find("speed=") {
-        let speed_part = &line[start + 6..];
-        if let Some(x_pos) = speed_part.find('x') {
-            speed_part[..=x_pos].trim().to_string()
-        } else {
-            "0.0x  ".to_string()
+    let report = match benchmark.run(
+        &Transcoder::from_settings(settings),
+        input,
+        &candidates,
+        &mut observer,
+    ) {
+        Ok(report) => report,
+        Err(e) => {
+            eprintln!("预设对比失败: {}", e);
+            return false;
         }
-    } else {
-        "0.0x  ".to_string()
-    };
-
-    // 如果speed_str过短，补齐空格
-    let speed_str = if speed_str.len() < 6 {
-        format!("{:<6}", speed_str)
-    } else {
-        speed_str
     };
 
-    if let Some(time) = time {
-        Some(ProgressInfo {
-            current_time: time,
-            speed_str: speed_str,
-        })
-    } else {
-        None
-    }
-}
-
-fn parse_time_to_duration(time_str: &str) -> Option<Duration> {
-    let parts: Vec<&str> = time_str.split(':').collect();
-    if parts.len() == 3 {
-        let hours = parts[0].parse::<u64>().ok()?;
-        let minutes = parts[1].parse::<u64>().ok()?;
-        let seconds = parts[2].parse::<f64>().ok()?;
-
-        let total_seconds = hours * 3600 + minutes * 60 + seconds as u64;
-        let nanos = (seconds.fract() * 1_000_000_000.0) as u32;
+    println!(
+        "\n预设对比结果 (截取共 {}，原视频对应部分约 {}):\n",
+        format_duration(&report.media_time),
+        format_size(report.source_size as f64)
+    );
+    println!("  序号  速度     体积比   估计总大小   SSIM     PSNR    VMAF    预设");
 
-        Some(Duration::new(total_seconds, nanos))
-    } else {
-        None
+    let optional = |value: Option<f64>, precision: usize| match value {
+        Some(v) => format!("{:.*}", precision, v),
+        None => "-".to_string(),
+    };
+    let mut ok = true;
+    for result in report.results.iter() {
+        let preset = &presets[result.preset];
+        if let Some(error) = &result.error {
+            ok = false;
+            println!(
+                "  {:<4}  失败: {}  {}",
+                result.preset + 1,
+                error,
+                preset.description
+            );
+            continue;
+        }
+        println!(
+            "  {:<4}  {:<7}  {:<7}  {:<11}  {:<7}  {:<6}  {:<6}  {}",
+            result.preset + 1,
+            format!("{:.2}x", result.speed(report.media_time)),
+            report
+                .size_ratio(result)
+                .map_or("-".to_string(), |r| format!("{:.1}%", r)),
+            format_size(report.estimated_size(result) as f64),
+            optional(result.scores.ssim, 4),
+            optional(result.scores.psnr, 2),
+            optional(result.scores.vmaf, 2),
+            preset.description
+        );
     }
-}
-
-fn format_duration(duration: &Duration) -> String {
-    let total_seconds = duration.as_secs();
-    let hours = total_seconds / 3600;
-    let minutes = (total_seconds % 3600) / 60;
-    let seconds = total_seconds % 60;
+    println!();
 
-    format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+    ok
 }
 
-fn is_video_file(path: &Path, exts: &[&str]) -> bool {
-    path.extension()
-        .and_then(|ext| ext.to_str())
-        .map(|ext| exts.iter().any(|&e| ext.eq_ignore_ascii_case(e)))
-        .unwrap_or(false)
-}
+// 按日志撤销替换，从最近的记录开始；paths 不为空时只撤销其中的原文件
+fn run_undo(paths: &[String]) -> bool {
+    let log_path = exe_sidecar_path("log");
+    let content = std::fs::read_to_string(&log_path).unwrap_or_default();
+    let filters: Vec<PathBuf> = paths
+        .iter()
+        .filter_map(|p| std::path::absolute(p).ok())
+        .collect();
+    let records: Vec<_> = records_in_log(&content)
+        .into_iter()
+        .filter(|r| filters.is_empty() || filters.iter().any(|f| r.original.starts_with(f)))
+        .collect();
+    if records.is_empty() {
+        println!("日志中没有可撤销的替换记录: {}", log_path.display());
+        return true;
+    }
 
-fn find_video_files(dir: &Path, exts: &[&str], results: &mut Vec<PathBuf>) {
-    if let Ok(entries) = std::fs::read_dir(dir) {
-        for entry in entries.flatten() {
-            let path = entry.path();
-            if path.is_dir() {
-                find_video_files(&path, exts, results);
-            } else if is_video_file(&path, exts) {
-                if let Ok(absolute_path) = path.canonicalize() {
-                    results.push(absolute_path);
-                }
+    let logger = Logger::new(&log_path);
+    let mut restored = 0;
+    let mut ok = true;
+    for record in records.iter().rev() {
+        match record.undo() {
+            Ok(true) => {
+                restored += 1;
+                println!("已还原: {}", record.original.display());
+                logger.log(&format!("撤销替换: {}", record.original.display()));
+            }
+            Ok(false) => {}
+            Err(e) => {
+                ok = false;
+                eprintln!("无法还原 {}: {}", record.original.display(), e);
             }
         }
     }
+    println!("\n已还原 {} 个原文件", restored);
+
+    ok
+}
+
+fn exit_with_error(message: &str) -> ! {
+    eprintln!(
+        "{}\n\n{} 下载地址: https://www.gyan.dev/ffmpeg/builds/\n\n可用 --ffmpeg 参数、FFMPEG_PATH 环境变量或配置文件中的 `ffmpeg = 路径` 指定 ffmpeg 的位置",
+        message,
+        executable_name("ffmpeg")
+    );
+    sleep(Duration::from_secs(600)); // 10分钟后自动关闭
+    std::process::exit(1);
 }
@@ -0,0 +1,185 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::Duration;
+
+/// ffprobe 读取到的媒体文件信息
+#[derive(Clone, Debug, Default)]
+pub struct MediaInfo {
+    /// 封装格式，例如 "mov,mp4,m4a,3gp,3g2,mj2"
+    pub format_name: String,
+    pub duration: Option<Duration>,
+    /// 总码率 (bit/s)
+    pub bit_rate: Option<u64>,
+    pub streams: Vec<StreamInfo>,
+}
+
+/// 媒体文件中的一个音视频或字幕流
+#[derive(Clone, Debug, Default)]
+pub struct StreamInfo {
+    pub index: usize,
+    /// "video"、"audio"、"subtitle" 等
+    pub codec_type: String,
+    pub codec_name: String,
+    pub width: Option<u32>,
+    pub height: Option<u32>,
+    /// 平均帧率
+    pub frame_rate: Option<f64>,
+    /// 帧数（视频流），来自 -count_packets 的计数或容器中记录的 nb_frames
+    pub frames: Option<u64>,
+    /// 传输特性、色域和矩阵，例如 "smpte2084"、"bt2020"、"bt2020nc"，未知时为空
+    pub color_transfer: String,
+    pub color_primaries: String,
+    pub color_space: String,
+    /// 封面图片（音频文件和 MKV 中的附加图片），不是真正的视频
+    pub attached_pic: bool,
+    /// 像素格式（视频流），例如 "yuv420p"
+    pub pix_fmt: String,
+    /// 采样率和声道数（音频流）
+    pub sample_rate: Option<u32>,
+    pub channels: Option<u32>,
+}
+
+impl MediaInfo {
+    /// 用 ffprobe 读取媒体文件信息
+    pub fn probe(ffprobe: &Path, path: &Path) -> Result<MediaInfo, String> {
+        MediaInfo::probe_with(ffprobe, path, &[])
+    }
+
+    /// 同 probe，并逐个读取数据包得到准确的帧数（需要读完整个文件）
+    pub fn probe_counting_frames(ffprobe: &Path, path: &Path) -> Result<MediaInfo, String> {
+        MediaInfo::probe_with(ffprobe, path, &["-count_packets"])
+    }
+
+    fn probe_with(ffprobe: &Path, path: &Path, extra_args: &[&str]) -> Result<MediaInfo, String> {
+        let output = Command::new(ffprobe)
+            .args(["-v", "error", "-show_format", "-show_streams"])
+            .args(extra_args)
+            .arg(path)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("无法启动 ffprobe {}: {}", ffprobe.display(), e))?;
+
+        if !output.status.success() {
+            return Err(format!(
+                "ffprobe 无法读取 {}: {}",
+                path.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            ));
+        }
+
+        Ok(MediaInfo::parse(&String::from_utf8_lossy(&output.stdout)))
+    }
+
+    /// 解析 ffprobe 默认格式的输出: [STREAM] ... [/STREAM] [FORMAT] ... [/FORMAT]，段内每行一个 key=value
+    pub fn parse(text: &str) -> MediaInfo {
+        let mut info = MediaInfo::default();
+        let mut section = "";
+
+        for line in text.lines() {
+            let line = line.trim();
+            match line {
+                "[STREAM]" => {
+                    section = "STREAM";
+                    info.streams.push(StreamInfo::default());
+                    continue;
+                }
+                "[FORMAT]" => {
+                    section = "FORMAT";
+                    continue;
+                }
+                // 流中的附加数据 (side data) 段，结束后回到所在的流
+                "[SIDE_DATA]" if section == "STREAM" => {
+                    section = "STREAM_SIDE_DATA";
+                    continue;
+                }
+                "[/SIDE_DATA]" if section == "STREAM_SIDE_DATA" => {
+                    section = "STREAM";
+                    continue;
+                }
+                _ if line.starts_with("[/") => {
+                    section = "";
+                    continue;
+                }
+                _ => {}
+            }
+
+            let Some((key, value)) = line.split_once('=') else {
+                continue;
+            };
+
+            match section {
+                "FORMAT" => match key {
+                    "format_name" => info.format_name = value.to_string(),
+                    "duration" => {
+                        info.duration = value.parse().ok().map(Duration::from_secs_f64);
+                    }
+                    "bit_rate" => info.bit_rate = value.parse().ok(),
+                    _ => {}
+                },
+                "STREAM" => {
+                    let Some(stream) = info.streams.last_mut() else {
+                        continue;
+                    };
+                    match key {
+                        "index" => stream.index = value.parse().unwrap_or(0),
+                        "codec_type" => stream.codec_type = value.to_string(),
+                        "codec_name" => stream.codec_name = value.to_string(),
+                        "width" => stream.width = value.parse().ok(),
+                        "height" => stream.height = value.parse().ok(),
+                        "avg_frame_rate" => stream.frame_rate = parse_rational(value),
+                        "nb_read_packets" => stream.frames = value.parse().ok(),
+                        "nb_frames" if stream.frames.is_none() => {
+                            stream.frames = value.parse().ok();
+                        }
+                        "color_transfer" => stream.color_transfer = value.to_string(),
+                        "color_primaries" => stream.color_primaries = value.to_string(),
+                        "color_space" => stream.color_space = value.to_string(),
+                        "DISPOSITION:attached_pic" => stream.attached_pic = value == "1",
+                        "pix_fmt" => stream.pix_fmt = value.to_string(),
+                        "sample_rate" => stream.sample_rate = value.parse().ok(),
+                        "channels" => stream.channels = value.parse().ok(),
+                        _ => {}
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        info
+    }
+
+    pub fn video_stream(&self) -> Option<&StreamInfo> {
+        self.streams.iter().find(|s| s.codec_type == "video")
+    }
+
+    pub fn audio_streams(&self) -> impl Iterator<Item = &StreamInfo> {
+        self.streams.iter().filter(|s| s.codec_type == "audio")
+    }
+}
+
+/// 用 ffmpeg 完整解码一遍，检查文件是否损坏
+pub fn full_decode(ffmpeg: &Path, path: &Path) -> Result<(), String> {
+    let output = Command::new(ffmpeg)
+        .args(["-v", "error", "-xerror", "-i"])
+        .arg(path)
+        .args(["-f", "null", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|e| format!("无法启动 ffmpeg {}: {}", ffmpeg.display(), e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let first_error = stderr.lines().map(str::trim).find(|l| !l.is_empty());
+    match (output.status.success(), first_error) {
+        (true, None) => Ok(()),
+        (_, Some(error)) => Err(format!("解码出错: {}", error)),
+        (false, None) => Err(format!("解码失败: ffmpeg {}", output.status)),
+    }
+}
+
+// "30000/1001" -> 29.97，分母为 0 (如 "0/0") 时无意义
+fn parse_rational(value: &str) -> Option<f64> {
+    let (num, den) = value.split_once('/').unwrap_or((value, "1"));
+    let (num, den): (f64, f64) = (num.parse().ok()?, den.parse().ok()?);
+    (den != 0.0 && num != 0.0).then(|| num / den)
+}
@@ -0,0 +1,106 @@
+use std::path::PathBuf;
+use std::process::{Command, Stdio};
+
+use crate::job::{Job, is_mp4_family};
+
+/// 输出体积减少不足时的处理方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum NoGainAction {
+    /// 删除输出文件
+    #[default]
+    Delete,
+    /// 删除输出文件，把原文件复制为输出文件名（保留原扩展名）
+    Copy,
+    /// 删除输出文件，把原文件不经转码重新封装为输出文件
+    Remux,
+}
+
+impl NoGainAction {
+    pub fn parse(value: &str) -> Option<NoGainAction> {
+        match value {
+            "delete" => Some(NoGainAction::Delete),
+            "copy" => Some(NoGainAction::Copy),
+            "remux" => Some(NoGainAction::Remux),
+            _ => None,
+        }
+    }
+
+    pub fn description(&self) -> &'static str {
+        match self {
+            NoGainAction::Delete => "已删除输出文件",
+            NoGainAction::Copy => "已改为复制原文件",
+            NoGainAction::Remux => "已改为重新封装原文件",
+        }
+    }
+}
+
+/// 只保留比原文件小至少 min_saving% 的输出
+#[derive(Clone, Copy, Debug)]
+pub struct NoGainPolicy {
+    pub min_saving: f64,
+    pub action: NoGainAction,
+}
+
+impl NoGainPolicy {
+    /// 体积变化（负数表示变小）是否达不到要求
+    pub fn is_no_gain(&self, size_change_percent: f64) -> bool {
+        -size_change_percent < self.min_saving
+    }
+
+    /// 按 action 处理没有收益的输出，返回最终保留在输出位置的文件（Delete 为 None）
+    pub fn apply(&self, job: &Job) -> Result<Option<PathBuf>, String> {
+        std::fs::remove_file(&job.output)
+            .map_err(|e| format!("无法删除 {}: {}", job.output.display(), e))?;
+
+        match self.action {
+            NoGainAction::Delete => Ok(None),
+            NoGainAction::Copy => {
+                let target = match job.input.extension() {
+                    Some(ext) => job.output.with_extension(ext),
+                    None => job.output.clone(),
+                };
+                // 输出文件名与原文件相同时原文件就在原处，无需复制
+                if target != job.input {
+                    std::fs::copy(&job.input, &target)
+                        .map_err(|e| format!("无法复制原文件到 {}: {}", target.display(), e))?;
+                }
+                Ok(Some(target))
+            }
+            NoGainAction::Remux => {
+                if job.output == job.input {
+                    return Ok(Some(job.input.clone()));
+                }
+                // 音频预设的输出只要音轨
+                let streams: &[&str] = if job.preset.audio {
+                    &["-map", "0:a"]
+                } else {
+                    &["-map", "0:v", "-map", "0:a?"]
+                };
+                let mut command = Command::new(&job.ffmpeg);
+                command
+                    .args(["-hide_banner", "-v", "error", "-i"])
+                    .arg(&job.input)
+                    .args(streams)
+                    .args(["-c", "copy", "-map_metadata", "0"]);
+                if is_mp4_family(&job.output) {
+                    command.args(["-movflags", "+use_metadata_tags"]);
+                }
+                let output = command
+                    .arg("-y")
+                    .arg(&job.output)
+                    .stdin(Stdio::null())
+                    .output()
+                    .map_err(|e| format!("无法启动 ffmpeg {}: {}", job.ffmpeg.display(), e))?;
+                if output.status.success() {
+                    Ok(Some(job.output.clone()))
+                } else {
+                    let _ = std::fs::remove_file(&job.output);
+                    Err(format!(
+                        "重新封装失败: {}",
+                        String::from_utf8_lossy(&output.stderr).trim()
+                    ))
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,217 @@
+use crate::crfsearch::QualityTarget;
+use crate::loudnorm::LoudnessTarget;
+use crate::target::RateTarget;
+
+/// 一个转码预设：ffmpeg 编码参数及输出文件的附加后缀
+#[derive(Clone, Debug)]
+pub struct Preset {
+    /// ffmpeg 编码参数，以空格分隔
+    pub params: String,
+    /// 输出文件名称的附加后缀，例如 "_H265"
+    pub subfix: String,
+    pub description: String,
+    /// 本预设失败后改用的备用预设（在预设列表中的下标）
+    pub fallback: Option<usize>,
+    /// 使用配置文件中 `ffmpeg.名称` 指定的 ffmpeg，None 则使用默认的 ffmpeg
+    pub ffmpeg_build: Option<String>,
+    /// 按目标大小或码率转码，取代参数中的 CRF/QP，None 则按参数转码
+    pub target: Option<RateTarget>,
+    /// 目标画质，转码前先搜索达到该画质的最大 CRF，None 则使用参数中的 CRF
+    pub quality_target: Option<QualityTarget>,
+    /// 分辨率上限，如 1080 表示画面不超出 1920x1080（竖屏为 1080x1920），超出时等比缩小到其中
+    pub max_res: Option<u32>,
+    /// 帧率上限，输入超过时降低帧率
+    pub max_fps: Option<f64>,
+    /// HDR 输入转为 SDR 使用的色调映射算法，None 则保留 HDR（编码器支持时）
+    pub tonemap: Option<String>,
+    /// 响度标准化的目标，转码前先测量响度，None 则不调整音量
+    pub loudnorm: Option<LoudnessTarget>,
+    /// 音频预设，只在音频模式中显示，输出只含音频（和封面）
+    pub audio: bool,
+    /// 输出文件的扩展名，None 为 mp4
+    pub extension: Option<String>,
+    /// 只重新封装：能复制的流直接复制，输出封装不支持的流才用参数中的编码器转码
+    pub remux: bool,
+    /// 放在 -i 之前的输入参数，例如 "-fflags +genpts"
+    pub input_params: String,
+}
+
+impl Preset {
+    pub fn new(params: &str, subfix: &str, description: &str) -> Self {
+        Preset {
+            params: params.to_string(),
+            subfix: subfix.to_string(),
+            description: description.to_string(),
+            fallback: None,
+            ffmpeg_build: None,
+            target: None,
+            quality_target: None,
+            max_res: None,
+            max_fps: None,
+            tonemap: None,
+            loudnorm: None,
+            audio: false,
+            extension: None,
+            remux: false,
+            input_params: String::new(),
+        }
+    }
+
+    /// 音频预设，输出使用 extension 扩展名
+    pub fn audio(params: &str, subfix: &str, extension: &str, description: &str) -> Self {
+        let mut preset = Preset::new(params, subfix, description);
+        preset.audio = true;
+        preset.extension = Some(extension.to_string());
+        preset
+    }
+
+    /// 输出文件的扩展名，例如 "mp4"、"opus"
+    pub fn extension(&self) -> &str {
+        self.extension.as_deref().unwrap_or("mp4")
+    }
+
+    pub fn args(&self) -> impl Iterator<Item = &str> {
+        self.params.split_whitespace()
+    }
+
+    pub fn input_args(&self) -> impl Iterator<Item = &str> {
+        self.input_params.split_whitespace()
+    }
+
+    /// 参数中指定的视频编码器，例如 "libx265"
+    pub fn video_encoder(&self) -> Option<&str> {
+        self.option_value(&["-c:v", "-codec:v", "-vcodec"])
+    }
+
+    /// 参数中指定的音频编码器，例如 "aac"
+    pub fn audio_encoder(&self) -> Option<&str> {
+        self.option_value(&["-c:a", "-codec:a", "-acodec"])
+    }
+
+    /// 参数中最后一次出现的选项的值，例如 option_value(&["-b:a"])
+    pub fn option_value(&self, names: &[&str]) -> Option<&str> {
+        let tokens: Vec<&str> = self.args().collect();
+        tokens
+            .windows(2)
+            .rev()
+            .find(|pair| names.contains(&pair[0]))
+            .map(|pair| pair[1])
+    }
+}
+
+/// 编码器输出的编码格式（ffprobe 中的 codec_name），例如 "libx265" -> "hevc"，无法判断时为 None
+pub fn codec_of_encoder(encoder: &str) -> Option<&str> {
+    let codec = match encoder {
+        "libx265" => "hevc",
+        "libx264" => "h264",
+        "libsvtav1" | "libaom-av1" | "librav1e" => "av1",
+        "libvpx" => "vp8",
+        "libvpx-vp9" => "vp9",
+        "libfdk_aac" => "aac",
+        "libopus" => "opus",
+        "libmp3lame" => "mp3",
+        "libvorbis" => "vorbis",
+        // 硬件编码器，如 hevc_amf、h264_nvenc、av1_qsv
+        _ => match encoder.split_once('_') {
+            Some((codec, _)) if ["h264", "hevc", "av1", "vp9", "mjpeg"].contains(&codec) => codec,
+            Some(_) => return None,
+            // ffmpeg 自带的编码器多与编码格式同名，如 aac、flac、mpeg4
+            None if encoder.starts_with("lib") || encoder == "copy" => return None,
+            None => encoder,
+        },
+    };
+    Some(codec)
+}
+
+/// 内置的转码预设
+pub fn builtin_presets() -> Vec<Preset> {
+    let mut hevc_amf = Preset::new(
+        "-c:a aac -c:v hevc_amf -quality quality -rc cqp -qp_i 22 -qp_p 22",
+        "_H265",
+        "H265 (hevc_amf)  AMD GPU硬件加速编码, 速度快",
+    );
+    hevc_amf.fallback = Some(0); // 没有 AMD 显卡驱动等情况下改用 libx265
+
+    // 播放设备不支持 HDR 时使用，SDR 输入不做处理
+    let mut sdr = Preset::new(
+        "-c:a aac -c:v libx265 -crf 23 -preset slow",
+        "_SDR",
+        "H265 (libx265)   HDR 转 SDR, 较慢",
+    );
+    sdr.tonemap = Some("hable".to_string());
+
+    // 编码格式 MP4 不支持的流才转码，参数只用于这些流
+    let mut remux = Preset::new(
+        "-c:v libx264 -crf 20 -preset medium -c:a aac -b:a 192k",
+        "_REMUX",
+        "MP4  (重新封装)   不转码, 很快",
+    );
+    remux.remux = true;
+
+    vec![
+        Preset::new(
+            "-c:a aac -c:v libx265 -crf 23 -preset slow",
+            "_H265",
+            "H265 (libx265)   CPU编码, 较慢",
+        ),
+        hevc_amf,
+        Preset::new(
+            "-c:a aac -c:v libsvtav1 -crf 28 -preset 4",
+            "_AV1",
+            "AV1  (libsvtav1) CPU编码, 非常慢",
+        ),
+        Preset::new(
+            "-c:a aac -c:v libaom-av1 -crf 28 -cpu-used 8 -b:v 0 -row-mt 1",
+            "_AV1",
+            "AV1  (libaom-av1) CPU编码, 最慢",
+        ),
+        sdr,
+        remux,
+    ]
+}
+
+/// 音频模式的内置预设
+pub fn builtin_audio_presets() -> Vec<Preset> {
+    vec![
+        Preset::audio(
+            "-c:a libopus -b:a 128k",
+            "_OPUS",
+            "opus",
+            "Opus 128k        体积小，适合音乐和播客",
+        ),
+        Preset::audio(
+            "-c:a aac -b:a 256k",
+            "_AAC",
+            "m4a",
+            "AAC  256k (.m4a) 兼容性好",
+        ),
+        Preset::audio(
+            "-c:a libmp3lame -q:a 0",
+            "_MP3",
+            "mp3",
+            "MP3  VBR V0      兼容性最好",
+        ),
+        Preset::audio("-c:a flac", "_FLAC", "flac", "FLAC             无损压缩"),
+    ]
+}
+
+/// 只保留视频模式或音频模式的预设，备用预设的下标随之调整，指向被去掉的预设的备用设置会被丢弃
+pub fn presets_for_mode(presets: Vec<Preset>, audio: bool) -> Vec<Preset> {
+    let mut new_index = Vec::new();
+    let mut count = 0;
+    for preset in presets.iter() {
+        new_index.push((preset.audio == audio).then(|| {
+            count += 1;
+            count - 1
+        }));
+    }
+
+    presets
+        .into_iter()
+        .filter(|p| p.audio == audio)
+        .map(|mut p| {
+            p.fallback = p.fallback.and_then(|i| new_index.get(i).copied().flatten());
+            p
+        })
+        .collect()
+}
@@ -0,0 +1,155 @@
+use std::time::Duration;
+
+/// 转码过程中的一次进度
+#[derive(Clone, Debug)]
+pub struct Progress {
+    /// 已转码到的视频时间点
+    pub current_time: Duration,
+    /// 视频总时长，ffmpeg 尚未输出 Duration 时为 None
+    pub total: Option<Duration>,
+    /// ffmpeg 输出的速度，例如 "2.35x "
+    pub speed_str: String,
+    /// 本次转码已用时间，两遍编码时包括第一遍
+    pub elapsed: Duration,
+    /// 两遍编码时为 (当前第几遍, 总遍数)，百分比按两遍合计
+    pub pass: Option<(u32, u32)>,
+}
+
+impl Progress {
+    /// 完成百分比 (0 ~ 100)
+    pub fn percentage(&self) -> Option<f64> {
+        let percentage = self.pass_percentage()?;
+        Some(match self.pass {
+            Some((pass, count)) if count > 0 => {
+                ((pass - 1) as f64 * 100.0 + percentage) / count as f64
+            }
+            _ => percentage,
+        })
+    }
+
+    // 当前这一遍的完成百分比
+    fn pass_percentage(&self) -> Option<f64> {
+        let total = self.total?;
+        Some(if total.as_millis() > 0 {
+            if self.current_time == total {
+                100.0
+            } else {
+                ((self.current_time.as_millis() as f64) * 100.0) / (total.as_millis() as f64)
+            }
+        } else {
+            0.0
+        })
+    }
+
+    /// 根据已用时间和百分比估计剩余时间
+    pub fn remaining(&self) -> Option<Duration> {
+        let total = self.total?;
+        let percentage = self.percentage()?;
+        let elapsed_millis = self.elapsed.as_millis() as u64;
+
+        let estimated_remaining_millis = if elapsed_millis < 1000 {
+            total.as_millis() as u64
+        } else if percentage > 0.0 && percentage < 100.0 {
+            let remain_millis = (100.0 - percentage) * (elapsed_millis as f64) / percentage;
+            remain_millis as u64
+        } else if percentage == 100.0 {
+            0
+        } else {
+            total.as_millis() as u64
+        };
+
+        Some(Duration::from_millis(estimated_remaining_millis))
+    }
+}
+
+pub(crate) fn parse_total_duration(line: &str) -> Option<Duration> {
+    if let Some(start) = line.find("Duration: ") {
+        let duration_str = &line[start + 10..];
+        if let Some(comma_pos) = duration_str.find(',') {
+            let time_str = &duration_str[..comma_pos];
+            return parse_time_to_duration(time_str);
+        }
+    }
+    None
+}
+
+pub(crate) struct ProgressInfo {
+    pub(crate) current_time: Duration,
+    pub(crate) speed_str: String,
+}
+
+pub(crate) fn parse_progress(line: &str) -> Option<ProgressInfo> {
+    // 查找 time= 字段
+    let time = if let Some(start) = line.find("time=") {
+        let time_str = &line[start + 5..];
+        if let Some(space_pos) = time_str.find(' ') {
+            parse_time_to_duration(&time_str[..space_pos])
+        } else {
+            None
+        }
+    } else {
+        None
+    };
+
+    // 提取 speed= 后到 x 字符（包含 x）
+    let speed_str = if let Some(start) = line.find("speed=") {
+        let speed_part = &line[start + 6..];
+        if let Some(x_pos) = speed_part.find('x') {
+            speed_part[..=x_pos].trim().to_string()
+        } else {
+            "0.0x  ".to_string()
+        }
+    } else {
+        "0.0x  ".to_string()
+    };
+
+    // 如果speed_str过短，补齐空格
+    let speed_str = if speed_str.len() < 6 {
+        format!("{:<6}", speed_str)
+    } else {
+        speed_str
+    };
+
+    time.map(|time| ProgressInfo {
+        current_time: time,
+        speed_str,
+    })
+}
+
+/// 解析 ffmpeg 的时间格式，例如 "01:02:03.45"
+pub fn parse_time_to_duration(time_str: &str) -> Option<Duration> {
+    let parts: Vec<&str> = time_str.split(':').collect();
+    if parts.len() == 3 {
+        let hours = parts[0].parse::<u64>().ok()?;
+        let minutes = parts[1].parse::<u64>().ok()?;
+        let seconds = parts[2].parse::<f64>().ok()?;
+
+        let total_seconds = hours * 3600 + minutes * 60 + seconds as u64;
+        let nanos = (seconds.fract() * 1_000_000_000.0) as u32;
+
+        Some(Duration::new(total_seconds, nanos))
+    } else {
+        None
+    }
+}
+
+pub fn format_duration(duration: &Duration) -> String {
+    let total_seconds = duration.as_secs();
+    let hours = total_seconds / 3600;
+    let minutes = (total_seconds % 3600) / 60;
+    let seconds = total_seconds % 60;
+
+    format!("{:02}:{:02}:{:02}", hours, minutes, seconds)
+}
+
+pub fn format_size(size: f64) -> String {
+    if size >= 1_073_741_824.0 {
+        format!("{:.2} GB", size / 1_073_741_824.0)
+    } else if size >= 1_048_576.0 {
+        format!("{:.2} MB", size / 1_048_576.0)
+    } else if size >= 1024.0 {
+        format!("{:.2} KB", size / 1024.0)
+    } else {
+        format!("{:.2} B", size)
+    }
+}
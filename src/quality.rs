@@ -0,0 +1,112 @@
+use std::path::Path;
+use std::process::{Command, Stdio};
+
+/// 画质评分，未计算的项为 None
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct QualityScores {
+    /// SSIM (0 ~ 1)，越接近 1 越好
+    pub ssim: Option<f64>,
+    /// PSNR (dB)，越高越好
+    pub psnr: Option<f64>,
+    /// VMAF (0 ~ 100)，越高越好，需要 ffmpeg 带有 libvmaf 滤镜
+    pub vmaf: Option<f64>,
+}
+
+impl QualityScores {
+    /// 多段评分的平均值，某项在任一段中缺失则结果中也缺失
+    pub fn average(scores: &[QualityScores]) -> QualityScores {
+        fn mean(values: impl Iterator<Item = Option<f64>>) -> Option<f64> {
+            let values: Option<Vec<f64>> = values.collect();
+            let values = values.filter(|v| !v.is_empty())?;
+            Some(values.iter().sum::<f64>() / values.len() as f64)
+        }
+
+        QualityScores {
+            ssim: mean(scores.iter().map(|s| s.ssim)),
+            psnr: mean(scores.iter().map(|s| s.psnr)),
+            vmaf: mean(scores.iter().map(|s| s.vmaf)),
+        }
+    }
+}
+
+/// 用 ffmpeg 的 ssim、psnr（以及 libvmaf）滤镜比较转码后的视频与参考视频。
+/// 转码后的视频会先缩放到参考视频的分辨率 (width x height)
+pub fn measure(
+    ffmpeg: &Path,
+    distorted: &Path,
+    reference: &Path,
+    width: u32,
+    height: u32,
+    vmaf: bool,
+) -> Result<QualityScores, String> {
+    let metrics: &[&str] = if vmaf {
+        &["ssim", "psnr", "libvmaf"]
+    } else {
+        &["ssim", "psnr"]
+    };
+    let count = metrics.len();
+
+    // [0:v] 为转码后的视频，[1:v] 为参考视频，时间戳都从 0 开始以便逐帧对齐
+    let mut filter = format!(
+        "[0:v]scale={}:{}:flags=bicubic,setpts=PTS-STARTPTS,split={}{};[1:v]setpts=PTS-STARTPTS,split={}{}",
+        width,
+        height,
+        count,
+        (0..count).map(|i| format!("[d{}]", i)).collect::<String>(),
+        count,
+        (0..count).map(|i| format!("[r{}]", i)).collect::<String>(),
+    );
+    for (i, metric) in metrics.iter().enumerate() {
+        filter.push_str(&format!(";[d{}][r{}]{}", i, i, metric));
+    }
+
+    let output = Command::new(ffmpeg)
+        .args(["-hide_banner", "-nostats", "-i"])
+        .arg(distorted)
+        .arg("-i")
+        .arg(reference)
+        .args(["-lavfi", &filter, "-f", "null", "-"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::null())
+        .output()
+        .map_err(|e| format!("无法启动 ffmpeg {}: {}", ffmpeg.display(), e))?;
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    if !output.status.success() {
+        let last_line = stderr.lines().last().unwrap_or("").trim();
+        return Err(format!("画质计算失败: {}", last_line));
+    }
+
+    let scores = parse_scores(&stderr);
+    if scores.ssim.is_none() && scores.psnr.is_none() {
+        return Err("画质计算失败: ffmpeg 没有输出 SSIM/PSNR".to_string());
+    }
+    Ok(scores)
+}
+
+/// 从 ffmpeg 输出中读取评分，例如:
+///   [Parsed_ssim_4 @ 0x...] SSIM Y:0.990 (20.0) U:0.993 (21.5) V:0.992 (21.1) All:0.991 (20.4)
+///   [Parsed_psnr_5 @ 0x...] PSNR y:45.1 u:48.2 v:48.0 average:46.0 min:43.2 max:49.9
+///   [Parsed_libvmaf_6 @ 0x...] VMAF score: 95.123456
+pub fn parse_scores(output: &str) -> QualityScores {
+    let mut scores = QualityScores::default();
+
+    for line in output.lines() {
+        if line.contains("SSIM Y:") {
+            scores.ssim = value_after(line, "All:");
+        } else if line.contains("PSNR y:") {
+            scores.psnr = value_after(line, "average:");
+        } else if line.contains("VMAF score") {
+            scores.vmaf = value_after(line, "VMAF score:").or(value_after(line, "VMAF score ="));
+        }
+    }
+
+    scores
+}
+
+// 取 key 之后的数字，如 "average:46.0 min" 中的 46.0；无损时 PSNR 为 "inf"
+fn value_after(line: &str, key: &str) -> Option<f64> {
+    let start = line.find(key)? + key.len();
+    let value = line[start..].split_whitespace().next()?;
+    value.parse().ok()
+}
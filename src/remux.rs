@@ -0,0 +1,270 @@
+use std::path::{Path, PathBuf};
+
+use crate::job::is_mp4_family_extension;
+use crate::media::{MediaInfo, StreamInfo};
+use crate::preset::Preset;
+
+/// MP4 能直接复制的视频和音频编码格式
+const MP4_VIDEO_CODECS: [&str; 6] = ["h264", "hevc", "av1", "vp9", "mpeg4", "mpeg2video"];
+const MP4_AUDIO_CODECS: [&str; 8] = ["aac", "mp3", "mp2", "ac3", "eac3", "opus", "flac", "alac"];
+/// 文本字幕，可以转为输出封装支持的字幕格式；图形字幕（如 PGS、DVD）无法转换
+const TEXT_SUBTITLE_CODECS: [&str; 6] = ["subrip", "ass", "ssa", "webvtt", "text", "mov_text"];
+/// 时间戳常有缺失或不连续的封装（ffprobe 的 format_name），复制时重新生成时间戳
+const GENPTS_FORMATS: [&str; 6] = ["mpegts", "flv", "avi", "mpeg", "h264", "hevc"];
+
+/// 重新封装时对一个输入流的处理
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum StreamAction {
+    Copy,
+    /// 输出封装不支持该编码格式，用此编码器转码
+    Encode(String),
+    /// 输出封装不支持且无法转码，例如 MP4 中的图形字幕和字体附件
+    Drop,
+}
+
+/// 重新封装的计划：每个输入流的处理方式，以及是否重新生成时间戳
+#[derive(Clone, Debug)]
+pub struct RemuxPlan {
+    pub streams: Vec<(StreamInfo, StreamAction)>,
+    pub genpts: bool,
+}
+
+/// 为重新封装预设读取输入的流信息并生成计划
+#[derive(Clone, Debug)]
+pub struct Remuxer {
+    pub ffprobe: PathBuf,
+}
+
+impl Remuxer {
+    pub fn plan(&self, preset: &Preset, input: &Path) -> Result<RemuxPlan, String> {
+        let info = MediaInfo::probe(&self.ffprobe, input)?;
+        if info.streams.is_empty() {
+            return Err("没有音视频流".to_string());
+        }
+        Ok(plan_remux(preset, &info))
+    }
+}
+
+/// 输出封装（按扩展名）能否直接容纳该流。MP4/MOV 只接受部分编码格式，
+/// MKV 几乎都能接受，其他封装无法判断，只保留音视频和字幕流交给 ffmpeg 处理
+pub fn container_supports(extension: &str, stream: &StreamInfo) -> bool {
+    let codec = stream.codec_name.as_str();
+    match (extension, stream.codec_type.as_str()) {
+        ("mp4" | "m4v" | "mov", "video") if stream.attached_pic => {
+            matches!(codec, "mjpeg" | "png")
+        }
+        ("mp4" | "m4v", "video") => MP4_VIDEO_CODECS.contains(&codec),
+        ("mov", "video") => {
+            MP4_VIDEO_CODECS.contains(&codec) || matches!(codec, "prores" | "mjpeg" | "dnxhd")
+        }
+        ("mp4" | "m4v", "audio") => MP4_AUDIO_CODECS.contains(&codec),
+        ("mov", "audio") => MP4_AUDIO_CODECS.contains(&codec) || codec.starts_with("pcm_"),
+        ("mp4" | "m4v" | "mov", "subtitle") => codec == "mov_text",
+        ("mp4" | "m4v" | "mov", _) => false,
+        ("mkv", "subtitle") => codec != "mov_text",
+        ("mkv", "video" | "audio" | "attachment") => true,
+        (_, "video" | "audio" | "subtitle") => true,
+        _ => false,
+    }
+}
+
+// 文本字幕在输出封装中使用的格式
+fn subtitle_encoder(extension: &str) -> Option<&'static str> {
+    match extension {
+        "mp4" | "m4v" | "mov" => Some("mov_text"),
+        "mkv" => Some("srt"),
+        _ => None,
+    }
+}
+
+/// 按输出封装决定每个流复制、转码还是丢弃。不兼容的音视频流用预设参数中的编码器转码，
+/// 参数中没有指定（或为 copy）时视频用 libx264，音频用 aac；封面图片不转码
+pub fn plan_remux(preset: &Preset, info: &MediaInfo) -> RemuxPlan {
+    let extension = preset.extension();
+    let video_encoder = preset
+        .video_encoder()
+        .filter(|e| *e != "copy")
+        .unwrap_or("libx264");
+    let audio_encoder = preset
+        .audio_encoder()
+        .filter(|e| *e != "copy")
+        .unwrap_or("aac");
+
+    let streams = info
+        .streams
+        .iter()
+        .map(|stream| {
+            let action = if container_supports(extension, stream) {
+                StreamAction::Copy
+            } else {
+                match stream.codec_type.as_str() {
+                    "video" if !stream.attached_pic => {
+                        StreamAction::Encode(video_encoder.to_string())
+                    }
+                    "audio" => StreamAction::Encode(audio_encoder.to_string()),
+                    "subtitle" if TEXT_SUBTITLE_CODECS.contains(&stream.codec_name.as_str()) => {
+                        match subtitle_encoder(extension) {
+                            Some(encoder) => StreamAction::Encode(encoder.to_string()),
+                            None => StreamAction::Drop,
+                        }
+                    }
+                    _ => StreamAction::Drop,
+                }
+            };
+            (stream.clone(), action)
+        })
+        .collect();
+
+    RemuxPlan {
+        streams,
+        genpts: info
+            .format_name
+            .split(',')
+            .any(|name| GENPTS_FORMATS.contains(&name)),
+    }
+}
+
+impl RemuxPlan {
+    /// 生成重新封装的预设：逐个流映射并指定复制或编码器，预设中的其他参数（如 -crf）只对转码的流有效。
+    /// MP4 把索引移到文件开头 (faststart)，便于网页和播放器边下边播
+    pub fn apply(&self, preset: &Preset) -> Preset {
+        let extension = preset.extension();
+        let mut tokens: Vec<String> = Vec::new();
+        let mut output_index = 0;
+        for (stream, action) in self.streams.iter() {
+            let codec = match action {
+                StreamAction::Copy => "copy",
+                StreamAction::Encode(encoder) => encoder.as_str(),
+                StreamAction::Drop => continue,
+            };
+            tokens.extend([
+                "-map".to_string(),
+                format!("0:{}", stream.index),
+                format!("-c:{}", output_index),
+                codec.to_string(),
+            ]);
+            // 苹果设备只播放标记为 hvc1 的 H.265
+            if *action == StreamAction::Copy
+                && stream.codec_name == "hevc"
+                && is_mp4_family_extension(extension)
+            {
+                tokens.extend([format!("-tag:{}", output_index), "hvc1".to_string()]);
+            }
+            output_index += 1;
+        }
+
+        tokens.extend(without_stream_selection(preset));
+        if is_mp4_family_extension(extension) {
+            // 同一选项只有最后一次有效，这里要带上输出参数中已有的 use_metadata_tags
+            tokens.extend([
+                "-movflags".to_string(),
+                "+faststart+use_metadata_tags".to_string(),
+            ]);
+        }
+
+        let mut preset = preset.clone();
+        preset.params = tokens.join(" ");
+        if self.genpts {
+            preset.input_params = format!("{} -fflags +genpts", preset.input_params)
+                .trim()
+                .to_string();
+        }
+        preset
+    }
+
+    /// 计划的简要说明，例如 "复制 2 个流，转码 1 个流 (dts -> aac)，丢弃 1 个流 (hdmv_pgs_subtitle)，重新生成时间戳"
+    pub fn summary(&self) -> String {
+        let copied = self
+            .streams
+            .iter()
+            .filter(|(_, action)| *action == StreamAction::Copy)
+            .count();
+        let mut parts = vec![format!("复制 {} 个流", copied)];
+
+        let encoded: Vec<String> = self
+            .streams
+            .iter()
+            .filter_map(|(stream, action)| match action {
+                StreamAction::Encode(encoder) => {
+                    Some(format!("{} -> {}", stream.codec_name, encoder))
+                }
+                _ => None,
+            })
+            .collect();
+        if !encoded.is_empty() {
+            parts.push(format!(
+                "转码 {} 个流 ({})",
+                encoded.len(),
+                encoded.join(", ")
+            ));
+        }
+
+        let dropped: Vec<&str> = self
+            .streams
+            .iter()
+            .filter(|(_, action)| *action == StreamAction::Drop)
+            .map(|(stream, _)| stream.codec_name.as_str())
+            .collect();
+        if !dropped.is_empty() {
+            parts.push(format!(
+                "丢弃 {} 个流 ({})",
+                dropped.len(),
+                dropped.join(", ")
+            ));
+        }
+
+        if self.genpts {
+            parts.push("重新生成时间戳".to_string());
+        }
+        parts.join("，")
+    }
+}
+
+/// 读取不到流信息时的重新封装：复制所有流，不检查兼容性
+pub fn copy_all_streams(preset: &Preset) -> Preset {
+    let mut tokens = vec![
+        "-map".to_string(),
+        "0".to_string(),
+        "-c".to_string(),
+        "copy".to_string(),
+    ];
+    tokens.extend(without_stream_selection(preset));
+    if is_mp4_family_extension(preset.extension()) {
+        tokens.extend([
+            "-movflags".to_string(),
+            "+faststart+use_metadata_tags".to_string(),
+        ]);
+    }
+    let mut preset = preset.clone();
+    preset.params = tokens.join(" ");
+    preset
+}
+
+// 去掉预设参数中的流映射、编码器和 -movflags 选项，这些由重新封装的计划指定
+fn without_stream_selection(preset: &Preset) -> Vec<String> {
+    const OPTIONS: [&str; 13] = [
+        "-map",
+        "-c",
+        "-codec",
+        "-c:v",
+        "-codec:v",
+        "-vcodec",
+        "-c:a",
+        "-codec:a",
+        "-acodec",
+        "-c:s",
+        "-codec:s",
+        "-scodec",
+        "-movflags",
+    ];
+    let mut tokens = Vec::new();
+    let mut args = preset.args();
+    while let Some(arg) = args.next() {
+        if OPTIONS.contains(&arg) {
+            args.next();
+        } else {
+            tokens.push(arg.to_string());
+        }
+    }
+    tokens
+}
@@ -0,0 +1,389 @@
+use std::path::{Path, PathBuf};
+
+use crate::log::CONTINUATION_INDENT;
+
+/// 转码成功后是否用输出替换原文件
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum ReplaceMode {
+    /// 不替换，保留原文件和输出文件（默认）
+    #[default]
+    Off,
+    /// 把原文件移到归档文件夹
+    Archive,
+    /// 把原文件移到回收站
+    Trash,
+}
+
+impl ReplaceMode {
+    pub fn parse(value: &str) -> Option<ReplaceMode> {
+        match value {
+            "off" | "0" => Some(ReplaceMode::Off),
+            "archive" => Some(ReplaceMode::Archive),
+            "trash" => Some(ReplaceMode::Trash),
+            _ => None,
+        }
+    }
+}
+
+/// 用输出替换原文件：原文件移走后，输出文件改用原文件的文件名（扩展名为输出的扩展名）
+#[derive(Clone, Debug)]
+pub enum ReplaceOriginal {
+    /// 原文件移到此文件夹，相对路径相对于原文件所在的文件夹
+    Archive(PathBuf),
+    Trash,
+}
+
+/// 一次替换的结果
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Replacement {
+    /// 替换后的输出文件
+    pub output: PathBuf,
+    /// 原文件被移到的位置，Windows 回收站中的位置无法得知，为 None
+    pub original: Option<PathBuf>,
+}
+
+impl ReplaceOriginal {
+    /// 移走原文件 input，把输出 output 改名为原文件名。失败时尽量恢复原状
+    pub fn replace(&self, input: &Path, output: &Path) -> Result<Replacement, String> {
+        let input = std::path::absolute(input).map_err(|e| e.to_string())?;
+        let output = std::path::absolute(output).map_err(|e| e.to_string())?;
+        let target = match output.extension() {
+            Some(ext) => input.with_extension(ext),
+            None => input.with_extension(""),
+        };
+        if target != input && target.exists() {
+            return Err(format!("{} 已存在", target.display()));
+        }
+
+        let original = match self {
+            ReplaceOriginal::Archive(dir) => {
+                let dir = match input.parent() {
+                    Some(parent) => parent.join(dir),
+                    None => dir.clone(),
+                };
+                std::fs::create_dir_all(&dir)
+                    .map_err(|e| format!("无法创建归档文件夹 {}: {}", dir.display(), e))?;
+                let archived = unique_path(&dir.join(input.file_name().unwrap_or_default()));
+                move_file(&input, &archived)
+                    .map_err(|e| format!("无法归档原文件到 {}: {}", archived.display(), e))?;
+                Some(archived)
+            }
+            ReplaceOriginal::Trash => {
+                move_to_trash(&input).map_err(|e| format!("无法把原文件移到回收站: {}", e))?
+            }
+        };
+
+        if let Err(e) = std::fs::rename(&output, &target) {
+            // 输出改名失败，原文件放回原处（回收站中的无法放回）
+            if let Some(original) = &original {
+                let _ = restore_file(original, &input);
+            }
+            return Err(format!("无法把输出改名为 {}: {}", target.display(), e));
+        }
+
+        Ok(Replacement {
+            output: target,
+            original,
+        })
+    }
+}
+
+/// 日志中的替换记录，写入和读取见 `log_record` 与 `records_in_log`
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ReplaceRecord {
+    pub original: PathBuf,
+    /// 原文件被移到的位置，None 表示移到了 Windows 回收站
+    pub archived: Option<PathBuf>,
+    /// 替换前的输出文件名
+    pub output: PathBuf,
+    /// 替换后的输出文件名
+    pub replaced_by: PathBuf,
+}
+
+// 日志中表示原文件在 Windows 回收站
+const RECYCLE_BIN: &str = "回收站";
+
+/// 替换记录在日志中的内容，例如:
+///   替换原文件: D:\a.mkv
+///                         原文件移到: D:\原文件\a.mkv
+///                         原输出: D:\a_AV1.mp4
+///                         替换为: D:\a.mp4
+pub fn log_record(input: &Path, output: &Path, replacement: &Replacement) -> String {
+    let original = std::path::absolute(input).unwrap_or_else(|_| input.to_path_buf());
+    let output = std::path::absolute(output).unwrap_or_else(|_| output.to_path_buf());
+    format!(
+        "替换原文件: {}\n{indent}原文件移到: {}\n{indent}原输出: {}\n{indent}替换为: {}",
+        original.display(),
+        replacement
+            .original
+            .as_ref()
+            .map_or(RECYCLE_BIN.to_string(), |p| p.display().to_string()),
+        output.display(),
+        replacement.output.display(),
+        indent = CONTINUATION_INDENT
+    )
+}
+
+/// 读取日志中的全部替换记录，按写入顺序排列
+pub fn records_in_log(content: &str) -> Vec<ReplaceRecord> {
+    let mut records = Vec::new();
+    let mut lines = content.lines();
+
+    while let Some(line) = lines.next() {
+        let Some((_, original)) = line.split_once("] 替换原文件: ") else {
+            continue;
+        };
+        let mut field = |key: &str| {
+            lines
+                .next()
+                .and_then(|l| l.trim_start().strip_prefix(key))
+                .map(|v| v.to_string())
+        };
+        let (Some(archived), Some(output), Some(replaced_by)) =
+            (field("原文件移到: "), field("原输出: "), field("替换为: "))
+        else {
+            continue;
+        };
+
+        records.push(ReplaceRecord {
+            original: PathBuf::from(original),
+            archived: (archived != RECYCLE_BIN).then(|| PathBuf::from(archived)),
+            output: PathBuf::from(output),
+            replaced_by: PathBuf::from(replaced_by),
+        });
+    }
+
+    records
+}
+
+impl ReplaceRecord {
+    /// 撤销替换：输出改回原来的文件名，原文件放回原处。
+    /// 原文件已不在记录的位置或已在原处（如已撤销过）时返回 Ok(false)
+    pub fn undo(&self) -> Result<bool, String> {
+        let Some(archived) = &self.archived else {
+            return self.undo_from_recycle_bin();
+        };
+        if !archived.exists() {
+            return Ok(false);
+        }
+
+        let replaced = self.replaced_by.exists();
+        if replaced && self.output.exists() {
+            return Err(format!("{} 已存在", self.output.display()));
+        }
+        if self.original.exists() && !(replaced && self.original == self.replaced_by) {
+            return Err(format!("{} 已存在", self.original.display()));
+        }
+
+        if replaced {
+            std::fs::rename(&self.replaced_by, &self.output)
+                .map_err(|e| format!("无法把输出改回 {}: {}", self.output.display(), e))?;
+        }
+        if let Err(e) = restore_file(archived, &self.original) {
+            if replaced {
+                let _ = std::fs::rename(&self.output, &self.replaced_by);
+            }
+            return Err(format!(
+                "无法把原文件放回 {}: {}",
+                self.original.display(),
+                e
+            ));
+        }
+
+        Ok(true)
+    }
+
+    // Windows 回收站中的原文件无法自动放回：输出还占着替换后的文件名时先改回原来的文件名，
+    // 以免输出与原文件同名（如 b.mp4）时从回收站还原发生冲突，再提示从回收站还原
+    fn undo_from_recycle_bin(&self) -> Result<bool, String> {
+        let renamed = self.replaced_by.exists() && !self.output.exists();
+        if renamed {
+            std::fs::rename(&self.replaced_by, &self.output)
+                .map_err(|e| format!("无法把输出改回 {}: {}", self.output.display(), e))?;
+        }
+        if self.original.exists() {
+            return Ok(renamed);
+        }
+
+        let mut message = format!(
+            "原文件在回收站中，请从回收站还原: {}",
+            self.original.display()
+        );
+        if renamed {
+            message = format!("输出已改回 {}，{}", self.output.display(), message);
+        }
+        Err(message)
+    }
+}
+
+// 同名文件已存在时依次尝试 "名称 (2).扩展名"、"名称 (3).扩展名"……
+fn unique_path(path: &Path) -> PathBuf {
+    if !path.exists() {
+        return path.to_path_buf();
+    }
+
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let ext = path
+        .extension()
+        .map(|e| format!(".{}", e.to_string_lossy()))
+        .unwrap_or_default();
+    (2..)
+        .map(|n| path.with_file_name(format!("{} ({}){}", stem, n, ext)))
+        .find(|p| !p.exists())
+        .unwrap()
+}
+
+// 移动文件，不在同一个分区时改为复制后删除
+fn move_file(from: &Path, to: &Path) -> std::io::Result<()> {
+    if std::fs::rename(from, to).is_ok() {
+        return Ok(());
+    }
+
+    if let Err(e) = std::fs::copy(from, to) {
+        let _ = std::fs::remove_file(to);
+        return Err(e);
+    }
+    std::fs::remove_file(from)
+}
+
+// 把归档或回收站中的原文件放回原处，从 freedesktop 回收站放回时同时删除其 .trashinfo
+fn restore_file(archived: &Path, original: &Path) -> std::io::Result<()> {
+    move_file(archived, original)?;
+
+    if let (Some(files), Some(name)) = (archived.parent(), archived.file_name())
+        && files.file_name().is_some_and(|n| n == "files")
+        && let Some(trash) = files.parent()
+    {
+        let mut info_name = name.to_os_string();
+        info_name.push(".trashinfo");
+        let _ = std::fs::remove_file(trash.join("info").join(info_name));
+    }
+    Ok(())
+}
+
+// 用 SHFileOperationW 删除到回收站，回收站中的位置无法得知。
+// 没有回收站的磁盘（网络共享、部分移动硬盘）上删除会直接永久删除，先查询；
+// 文件超出回收站容量时 FOF_WANTNUKEWARNING 让系统先询问，用户拒绝则保留原文件
+#[cfg(windows)]
+fn move_to_trash(path: &Path) -> Result<Option<PathBuf>, String> {
+    use std::os::windows::ffi::OsStrExt;
+    use winapi::um::shellapi::{
+        FO_DELETE, FOF_ALLOWUNDO, FOF_NOCONFIRMATION, FOF_NOERRORUI, FOF_SILENT,
+        FOF_WANTNUKEWARNING, SHFILEOPSTRUCTW, SHFileOperationW, SHQUERYRBINFO, SHQueryRecycleBinW,
+    };
+
+    let path_wide: Vec<u16> = path.as_os_str().encode_wide().chain([0]).collect();
+    let mut info = SHQUERYRBINFO {
+        cbSize: std::mem::size_of::<SHQUERYRBINFO>() as u32,
+        i64Size: 0,
+        i64NumItems: 0,
+    };
+    let result = unsafe { SHQueryRecycleBinW(path_wide.as_ptr(), &mut info) };
+    if result < 0 {
+        return Err(format!(
+            "所在磁盘没有回收站 (错误码 {:#x})，保留原文件",
+            result as u32
+        ));
+    }
+
+    // pFrom 是以两个 0 结尾的路径列表
+    let from: Vec<u16> = path.as_os_str().encode_wide().chain([0, 0]).collect();
+    let mut operation = SHFILEOPSTRUCTW {
+        hwnd: std::ptr::null_mut(),
+        wFunc: FO_DELETE,
+        pFrom: from.as_ptr(),
+        pTo: std::ptr::null(),
+        fFlags: FOF_ALLOWUNDO
+            | FOF_NOCONFIRMATION
+            | FOF_NOERRORUI
+            | FOF_SILENT
+            | FOF_WANTNUKEWARNING,
+        fAnyOperationsAborted: 0,
+        hNameMappings: std::ptr::null_mut(),
+        lpszProgressTitle: std::ptr::null(),
+    };
+
+    let result = unsafe { SHFileOperationW(&mut operation) };
+    if result != 0 || operation.fAnyOperationsAborted != 0 {
+        return Err(format!("SHFileOperation 错误码 {}", result));
+    }
+    Ok(None)
+}
+
+// 按 freedesktop 回收站规范移到 $XDG_DATA_HOME/Trash，同时写入 .trashinfo
+#[cfg(not(windows))]
+fn move_to_trash(path: &Path) -> Result<Option<PathBuf>, String> {
+    let data_home = match std::env::var_os("XDG_DATA_HOME").filter(|v| !v.is_empty()) {
+        Some(dir) => PathBuf::from(dir),
+        None => match std::env::var_os("HOME") {
+            Some(home) => PathBuf::from(home).join(".local/share"),
+            None => return Err("找不到用户主目录".to_string()),
+        },
+    };
+    let trash = data_home.join("Trash");
+    let files = trash.join("files");
+    let info = trash.join("info");
+    for dir in [&files, &info] {
+        std::fs::create_dir_all(dir).map_err(|e| format!("{}: {}", dir.display(), e))?;
+    }
+
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    let info_content = format!(
+        "[Trash Info]\nPath={}\nDeletionDate={}\n",
+        percent_encode(&path.to_string_lossy()),
+        chrono::Local::now().format("%Y-%m-%dT%H:%M:%S")
+    );
+
+    // 先独占创建 .trashinfo 占住文件名，再移动文件
+    for n in 1.. {
+        let trashed_name = match n {
+            1 => name.to_string(),
+            _ => unique_name(&name, n),
+        };
+        let info_path = info.join(format!("{}.trashinfo", trashed_name));
+        let trashed = files.join(&trashed_name);
+        if trashed.exists() {
+            continue;
+        }
+        match std::fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&info_path)
+        {
+            Ok(mut f) => {
+                use std::io::Write;
+                let written = f.write_all(info_content.as_bytes());
+                let moved = written.and_then(|_| move_file(path, &trashed));
+                if let Err(e) = moved {
+                    let _ = std::fs::remove_file(&info_path);
+                    return Err(e.to_string());
+                }
+                return Ok(Some(trashed));
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(format!("{}: {}", info_path.display(), e)),
+        }
+    }
+    unreachable!()
+}
+
+#[cfg(not(windows))]
+fn unique_name(name: &str, n: usize) -> String {
+    match name.rsplit_once('.') {
+        Some((stem, ext)) if !stem.is_empty() => format!("{} ({}).{}", stem, n, ext),
+        _ => format!("{} ({})", name, n),
+    }
+}
+
+// .trashinfo 中的路径按 URL 规则转义，保留 '/' 和不需要转义的字符
+#[cfg(not(windows))]
+fn percent_encode(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'/' | b'-' | b'_' | b'.' | b'~' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
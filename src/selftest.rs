@@ -0,0 +1,255 @@
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+use crate::batch::{Batch, BatchObserver, FileOutcome, FileReport};
+use crate::job::{Job, JobResult, output_path_with_extension};
+use crate::media::{MediaInfo, full_decode};
+use crate::preset::{Preset, codec_of_encoder};
+
+/// 自检用测试片段的时长
+pub const CLIP_DURATION: Duration = Duration::from_secs(3);
+
+// 输出时长与测试片段时长允许的误差
+const DURATION_TOLERANCE: Duration = Duration::from_millis(500);
+
+/// 自检：用 lavfi 生成测试片段，按批量转码的流程依次用每个预设转码，检查输出能否解码以及时长和编码格式是否正确
+pub struct SelfTest {
+    /// 用于生成测试片段和解码检查的 ffmpeg
+    pub ffmpeg: PathBuf,
+    pub ffprobe: PathBuf,
+    /// 存放测试片段和输出文件的临时目录，结束后删除
+    pub dir: PathBuf,
+}
+
+/// 一个预设的自检结果
+#[derive(Clone, Debug)]
+pub struct SelfTestResult {
+    pub preset: usize,
+    pub status: SelfTestStatus,
+    pub elapsed: Duration,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SelfTestStatus {
+    Passed,
+    Failed(String),
+    /// 预设不可用，没有测试
+    Skipped,
+}
+
+impl SelfTest {
+    pub fn new(ffmpeg: impl Into<PathBuf>, ffprobe: impl Into<PathBuf>) -> Self {
+        SelfTest {
+            ffmpeg: ffmpeg.into(),
+            ffprobe: ffprobe.into(),
+            dir: std::env::temp_dir()
+                .join(format!("ffmpegConvert_selftest_{}", std::process::id())),
+        }
+    }
+
+    /// 依次测试 batch 中的每个预设，不可用的预设跳过。预设经过与批量转码相同的准备（分析输入、加滤镜、
+    /// 重新封装计划等），转 SDR 的预设使用 HDR 测试片段。无法生成测试片段时返回错误
+    pub fn run(
+        &self,
+        batch: &Batch,
+        observer: &mut dyn BatchObserver,
+    ) -> Result<Vec<SelfTestResult>, String> {
+        std::fs::create_dir_all(&self.dir)
+            .map_err(|e| format!("无法创建临时目录 {}: {}", self.dir.display(), e))?;
+        let result = self.run_in_dir(batch, observer);
+        let _ = std::fs::remove_dir_all(&self.dir);
+        result
+    }
+
+    fn run_in_dir(
+        &self,
+        batch: &Batch,
+        observer: &mut dyn BatchObserver,
+    ) -> Result<Vec<SelfTestResult>, String> {
+        let presets = &batch.presets;
+        let sdr_clip = self.generate_clip(false)?;
+        let hdr_clip = if presets.iter().any(|p| p.tonemap.is_some()) {
+            Some(self.generate_clip(true)?)
+        } else {
+            None
+        };
+        let mut results = Vec::new();
+
+        for (i, preset) in presets.iter().enumerate() {
+            let Some(ffmpeg) = &batch.preset_ffmpeg[i] else {
+                results.push(SelfTestResult {
+                    preset: i,
+                    status: SelfTestStatus::Skipped,
+                    elapsed: Duration::ZERO,
+                });
+                continue;
+            };
+
+            let clip = match (&hdr_clip, &preset.tonemap) {
+                (Some(hdr_clip), Some(_)) => hdr_clip,
+                _ => &sdr_clip,
+            };
+            observer.file_started(i + 1, presets.len(), clip);
+            let start = Instant::now();
+            let mut job = Job::new(
+                clip,
+                output_path_with_extension(clip, &preset.subfix, preset.extension()),
+                preset.clone(),
+                ffmpeg,
+            );
+            let outcome = match batch.prepare(&job, observer) {
+                Some(prepared) => {
+                    job.preset = prepared;
+                    match batch
+                        .backend
+                        .run(&job, &mut |event| observer.event(&job, event))
+                    {
+                        JobResult::Success(stats) => FileOutcome::Converted {
+                            stats,
+                            sizes: std::fs::metadata(clip)
+                                .and_then(|i| {
+                                    std::fs::metadata(&job.output).map(|o| (i.len(), o.len()))
+                                })
+                                .ok(),
+                        },
+                        JobResult::Failed(failure) => FileOutcome::Failed(failure),
+                        JobResult::Cancelled => FileOutcome::Cancelled,
+                    }
+                }
+                None => FileOutcome::Cancelled,
+            };
+            let status = match &outcome {
+                FileOutcome::Converted { .. } | FileOutcome::NoGain { .. } => {
+                    match self.check_output(&job.output, &job.preset) {
+                        Ok(()) => SelfTestStatus::Passed,
+                        Err(e) => SelfTestStatus::Failed(e),
+                    }
+                }
+                FileOutcome::Failed(failure) => SelfTestStatus::Failed(failure.reason.clone()),
+                FileOutcome::Cancelled => SelfTestStatus::Failed("已取消".to_string()),
+            };
+            let elapsed = start.elapsed();
+
+            observer.file_finished(&FileReport {
+                input: clip.clone(),
+                output: job.output.clone(),
+                preset: i,
+                attempts: 1,
+                outcome,
+                replaced: None,
+            });
+            let _ = std::fs::remove_file(&job.output);
+
+            results.push(SelfTestResult {
+                preset: i,
+                status,
+                elapsed,
+            });
+        }
+
+        Ok(results)
+    }
+
+    // 生成带音频的测试片段：testsrc2 测试画面 + 1kHz 正弦波，使用 ffmpeg 自带的编码器。
+    // hdr 为 true 时画面为 10 位并标记为 HDR10 (PQ)，用于测试转 SDR 的预设
+    fn generate_clip(&self, hdr: bool) -> Result<PathBuf, String> {
+        let clip = self.dir.join(if hdr {
+            "selftest_hdr.mkv"
+        } else {
+            "selftest.mkv"
+        });
+        let duration = CLIP_DURATION.as_secs().to_string();
+        let video_args: &[&str] = if hdr {
+            &[
+                "-vf",
+                "format=yuv420p10le",
+                "-c:v",
+                "ffv1",
+                "-color_primaries",
+                "bt2020",
+                "-color_trc",
+                "smpte2084",
+                "-colorspace",
+                "bt2020nc",
+            ]
+        } else {
+            &["-c:v", "mpeg4", "-q:v", "3"]
+        };
+        let output = Command::new(&self.ffmpeg)
+            .args(["-hide_banner", "-v", "error", "-f", "lavfi", "-i"])
+            .arg(format!(
+                "testsrc2=duration={}:size=640x360:rate=25",
+                duration
+            ))
+            .args(["-f", "lavfi", "-i"])
+            .arg(format!("sine=frequency=1000:duration={}", duration))
+            .args(video_args)
+            .args(["-c:a", "aac", "-shortest", "-y"])
+            .arg(&clip)
+            .stdin(Stdio::null())
+            .output()
+            .map_err(|e| format!("无法启动 ffmpeg {}: {}", self.ffmpeg.display(), e))?;
+
+        if output.status.success() {
+            Ok(clip)
+        } else {
+            Err(format!(
+                "无法生成测试片段: {}",
+                String::from_utf8_lossy(&output.stderr).trim()
+            ))
+        }
+    }
+
+    // 输出应能完整解码，时长与测试片段一致，音视频编码格式与预设一致，转 SDR 的输出不再标记为 HDR
+    fn check_output(&self, output: &Path, preset: &Preset) -> Result<(), String> {
+        let info = MediaInfo::probe(&self.ffprobe, output)?;
+
+        // 音频预设从测试片段中只提取音频
+        if !preset.audio {
+            let Some(video) = info.video_stream() else {
+                return Err("输出中没有视频流".to_string());
+            };
+            if let Some(expected) = preset.video_encoder().and_then(codec_of_encoder)
+                && video.codec_name != expected
+            {
+                return Err(format!(
+                    "视频编码为 {}，应为 {}",
+                    video.codec_name, expected
+                ));
+            }
+            if preset.tonemap.is_some()
+                && matches!(video.color_transfer.as_str(), "smpte2084" | "arib-std-b67")
+            {
+                return Err(format!("输出仍标记为 HDR ({})", video.color_transfer));
+            }
+        }
+
+        if let Some(expected) = preset.audio_encoder().and_then(codec_of_encoder) {
+            match info.audio_streams().next() {
+                None => return Err("输出中没有音频流".to_string()),
+                Some(audio) if audio.codec_name != expected => {
+                    return Err(format!(
+                        "音频编码为 {}，应为 {}",
+                        audio.codec_name, expected
+                    ));
+                }
+                Some(_) => {}
+            }
+        }
+
+        match info.duration {
+            None => return Err("无法读取输出时长".to_string()),
+            Some(d) if d.abs_diff(CLIP_DURATION) > DURATION_TOLERANCE => {
+                return Err(format!(
+                    "输出时长 {:.2} 秒，应为 {} 秒",
+                    d.as_secs_f64(),
+                    CLIP_DURATION.as_secs()
+                ));
+            }
+            Some(_) => {}
+        }
+
+        full_decode(&self.ffmpeg, output)
+    }
+}
@@ -0,0 +1,149 @@
+use std::time::Duration;
+
+/// 按目标大小或码率转码，取代预设中的 CRF/QP 等码率控制参数
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RateTarget {
+    /// 输出文件大小（字节）
+    Size(u64),
+    /// 视频码率 (bit/s)
+    Bitrate(u64),
+}
+
+// 预设中会被目标码率取代的码率控制参数，均带一个值
+const RATE_CONTROL_OPTIONS: [&str; 11] = [
+    "-crf",
+    "-qp",
+    "-q:v",
+    "-qscale:v",
+    "-cq",
+    "-rc",
+    "-qp_i",
+    "-qp_p",
+    "-qp_b",
+    "-b:v",
+    "-maxrate",
+];
+
+// 封装格式本身占用的体积，按总码率的 2% 预留
+const CONTAINER_OVERHEAD: f64 = 0.02;
+
+// 计算出的视频码率低于此值时认为目标大小无法达到 (bit/s)
+const MIN_VIDEO_BITRATE: u64 = 50_000;
+
+// 预设未指定音频码率时 ffmpeg aac 编码器的默认码率 (bit/s)
+pub const DEFAULT_AUDIO_BITRATE: u64 = 128_000;
+
+// 两遍编码的统计文件名，相对于 ffmpeg 的工作目录（x265-params 中的路径不能含 ':'）
+const PASS_LOG: &str = "ffmpegConvert2pass";
+
+/// 支持两遍编码的编码器，其他编码器只按目标码率编码一遍
+pub fn supports_two_pass(encoder: &str) -> bool {
+    matches!(
+        encoder,
+        "libx264" | "libx265" | "libaom-av1" | "libvpx" | "libvpx-vp9"
+    )
+}
+
+/// 解析大小，例如 "25M"、"1.5G"、"700MB"，单位按 1024 换算，没有单位为字节
+pub fn parse_size(value: &str) -> Option<u64> {
+    parse_with_unit(value.trim_end_matches(['B', 'b']), 1024.0)
+}
+
+/// 解析码率，例如 "2500k"、"8M"，单位按 1000 换算，没有单位为 bit/s
+pub fn parse_bitrate(value: &str) -> Option<u64> {
+    parse_with_unit(value, 1000.0)
+}
+
+fn parse_with_unit(value: &str, base: f64) -> Option<u64> {
+    let value = value.trim();
+    let (number, scale) = match value.chars().last()? {
+        'k' | 'K' => (&value[..value.len() - 1], base),
+        'm' | 'M' => (&value[..value.len() - 1], base * base),
+        'g' | 'G' => (&value[..value.len() - 1], base * base * base),
+        _ => (value, 1.0),
+    };
+    let number: f64 = number.parse().ok()?;
+    (number > 0.0).then_some((number * scale) as u64)
+}
+
+/// 根据目标和视频时长计算视频码率 (bit/s)，扣除音频码率和封装开销
+pub fn video_bitrate(
+    target: RateTarget,
+    duration: Duration,
+    audio_bitrate: u64,
+) -> Result<u64, String> {
+    let size = match target {
+        RateTarget::Bitrate(bitrate) => return Ok(bitrate),
+        RateTarget::Size(size) => size,
+    };
+
+    let seconds = duration.as_secs_f64();
+    if seconds <= 0.0 {
+        return Err("无法读取视频时长，不能按目标大小计算码率".to_string());
+    }
+    let total = size as f64 * 8.0 / seconds * (1.0 - CONTAINER_OVERHEAD);
+    let video = total - audio_bitrate as f64;
+    if video < MIN_VIDEO_BITRATE as f64 {
+        return Err(format!(
+            "目标大小过小，视频码率只有 {:.0} kbit/s",
+            video.max(0.0) / 1000.0
+        ));
+    }
+    Ok(video as u64)
+}
+
+/// 把预设参数改为按 video_bitrate 编码：去掉码率控制参数，加上 -b:v 和两遍编码的参数。
+/// pass 为 Some(1) 或 Some(2) 时生成对应一遍的参数，统计文件位于 ffmpeg 的工作目录
+pub fn rate_args(
+    args: &[&str],
+    encoder: &str,
+    video_bitrate: u64,
+    pass: Option<u32>,
+) -> Vec<String> {
+    let mut result = Vec::new();
+    let mut x265_params = None;
+
+    let mut tokens = args.iter();
+    while let Some(token) = tokens.next() {
+        if RATE_CONTROL_OPTIONS.contains(token) {
+            tokens.next();
+        } else if *token == "-x265-params" {
+            x265_params = tokens.next().map(|v| v.to_string());
+        } else {
+            result.push(token.to_string());
+        }
+    }
+
+    result.push("-b:v".to_string());
+    result.push(format!("{}k", video_bitrate / 1000));
+
+    match pass {
+        Some(pass) if encoder == "libx265" => {
+            let pass_params = format!("pass={}:stats={}.log", pass, PASS_LOG);
+            x265_params = Some(match x265_params {
+                Some(params) => format!("{}:{}", params, pass_params),
+                None => pass_params,
+            });
+        }
+        Some(pass) => {
+            result.extend(["-pass".to_string(), pass.to_string()]);
+            result.extend(["-passlogfile".to_string(), PASS_LOG.to_string()]);
+        }
+        None => {}
+    }
+    if let Some(params) = x265_params {
+        result.extend(["-x265-params".to_string(), params]);
+    }
+
+    result
+}
+
+/// 从 ffmpeg 输出的音频流信息中读取码率 (bit/s)，例如
+///   Stream #0:1(und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s (default)
+pub fn parse_audio_bitrate(line: &str) -> Option<u64> {
+    let (_, info) = line.split_once(": Audio: ")?;
+    info.split(',').find_map(|part| {
+        let kbps: f64 = part.trim().split_once(" kb/s")?.0.parse().ok()?;
+        Some((kbps * 1000.0) as u64)
+    })
+}
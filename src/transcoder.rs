@@ -0,0 +1,450 @@
+use std::collections::VecDeque;
+use std::ffi::OsString;
+use std::io::{BufReader, Read};
+use std::path::Path;
+use std::process::{Child, Command, Stdio};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::time::{Duration, Instant};
+
+use crate::backend::Backend;
+use crate::config::Settings;
+use crate::job::{Job, JobFailure, JobResult, JobStats, is_mp4_family};
+use crate::progress::{Progress, parse_progress, parse_total_duration};
+use crate::target::{
+    DEFAULT_AUDIO_BITRATE, RateTarget, parse_audio_bitrate, parse_bitrate, rate_args,
+    supports_two_pass, video_bitrate,
+};
+use crate::trim::Trim;
+
+// 失败时保留的 ffmpeg 输出（不含进度行）的最后行数
+const STDERR_TAIL_LINES: usize = 20;
+
+/// 转码过程中报告给调用方的事件
+pub enum Event<'a> {
+    /// ffmpeg 已启动
+    Started { pid: u32 },
+    /// ffmpeg 输出了新的进度
+    Progress(&'a Progress),
+    /// 一段时间内没有新的进度，调用方可借此检查用户操作（如 Ctrl+C）
+    Waiting,
+    /// 本次 ffmpeg 已结束（包括没能启动的情况），Started 报告的进程 ID 不再有效
+    Exited,
+}
+
+/// 调用方对事件的回应
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Control {
+    Continue,
+    /// 结束 ffmpeg 并删除未完成的输出文件
+    Abort,
+}
+
+/// 驱动 ffmpeg 执行转码任务，通过回调报告进度，是默认的转码后端
+#[derive(Clone, Debug, Default)]
+pub struct Transcoder {
+    /// 超过这么久没有进度则判定 ffmpeg 卡住，None 表示不检测
+    pub stall_timeout: Option<Duration>,
+    /// 单个任务的用时上限，为视频时长的倍数，None 表示不限制
+    pub timeout_ratio: Option<f64>,
+}
+
+impl Transcoder {
+    pub fn from_settings(settings: &Settings) -> Self {
+        Transcoder {
+            stall_timeout: (settings.stall_timeout_minutes > 0)
+                .then(|| Duration::from_secs(settings.stall_timeout_minutes * 60)),
+            timeout_ratio: (settings.timeout_ratio > 0.0).then_some(settings.timeout_ratio),
+        }
+    }
+
+    /// 执行一个转码任务，卡住、超时或被调用方中止时结束 ffmpeg 并删除未完成的输出文件
+    pub fn run(&self, job: &Job, on_event: &mut dyn FnMut(Event) -> Control) -> JobResult {
+        Backend::run(self, job, on_event)
+    }
+}
+
+impl Backend for Transcoder {
+    fn run(&self, job: &Job, on_event: &mut dyn FnMut(Event) -> Control) -> JobResult {
+        let start = Instant::now();
+        match job.preset.target {
+            None => {
+                let args = output_args(job, job.preset.args().map(OsString::from));
+                self.run_ffmpeg(job, args, None, None, start, on_event)
+            }
+            Some(target) => self.run_with_target(job, target, start, on_event),
+        }
+    }
+}
+
+impl Transcoder {
+    // 按目标大小或码率转码，支持的编码器先分析一遍再编码，进度按两遍合计
+    fn run_with_target(
+        &self,
+        job: &Job,
+        target: RateTarget,
+        start: Instant,
+        on_event: &mut dyn FnMut(Event) -> Control,
+    ) -> JobResult {
+        let preset_args: Vec<&str> = job.preset.args().collect();
+        let (duration, input_audio_bitrate) = probe_input(&job.ffmpeg, &job.input);
+        let duration = match (duration, &job.trim, &job.concat) {
+            (_, _, Some(concat)) => concat.duration,
+            (Some(duration), Some(trim), None) => Some(trim.duration_within(duration)),
+            (duration, _, _) => duration,
+        };
+        let audio_bitrate = if preset_args.contains(&"-an") {
+            0
+        } else if job.preset.audio_encoder() == Some("copy") {
+            input_audio_bitrate.unwrap_or(DEFAULT_AUDIO_BITRATE)
+        } else {
+            job.preset
+                .option_value(&["-b:a"])
+                .and_then(parse_bitrate)
+                .unwrap_or(DEFAULT_AUDIO_BITRATE)
+        };
+        let video_bitrate = match video_bitrate(target, duration.unwrap_or_default(), audio_bitrate)
+        {
+            Ok(bitrate) => bitrate,
+            Err(reason) => {
+                return JobResult::Failed(JobFailure {
+                    reason,
+                    retryable: false,
+                    fallback: true,
+                    stderr_tail: Vec::new(),
+                });
+            }
+        };
+
+        let encoder = job.preset.video_encoder().unwrap_or("");
+        if !supports_two_pass(encoder) {
+            let args = output_args(job, rate_args(&preset_args, encoder, video_bitrate, None));
+            return self.run_ffmpeg(job, args, None, None, start, on_event);
+        }
+
+        // 两遍编码的统计文件放在临时工作目录中，ffmpeg 的工作目录改变后路径都要用绝对路径
+        let absolute = |p: &Path| std::path::absolute(p).unwrap_or_else(|_| p.to_path_buf());
+        let job = Job {
+            input: absolute(&job.input),
+            output: absolute(&job.output),
+            ffmpeg: match job.ffmpeg.parent() {
+                Some(parent) if !parent.as_os_str().is_empty() => absolute(&job.ffmpeg),
+                _ => job.ffmpeg.clone(),
+            },
+            preset: job.preset.clone(),
+            trim: job.trim,
+            concat: job.concat.clone(),
+        };
+        let work_dir =
+            std::env::temp_dir().join(format!("ffmpegConvert_2pass_{}", std::process::id()));
+        if let Err(e) = std::fs::create_dir_all(&work_dir) {
+            return JobResult::Failed(JobFailure::new(format!(
+                "无法创建临时目录 {}: {}",
+                work_dir.display(),
+                e
+            )));
+        }
+
+        // 第一遍只分析视频，不输出文件
+        let mut first_pass: Vec<OsString> = vec!["-hide_banner".into()];
+        first_pass.extend(source_args(&job));
+        first_pass.extend(
+            rate_args(&preset_args, encoder, video_bitrate, Some(1))
+                .into_iter()
+                .map(OsString::from),
+        );
+        first_pass.extend(["-an", "-f", "null", "-"].map(OsString::from));
+
+        let result = match self.run_ffmpeg(
+            &job,
+            first_pass,
+            Some(&work_dir),
+            Some((1, 2)),
+            start,
+            on_event,
+        ) {
+            JobResult::Success(_) => {
+                let args = output_args(
+                    &job,
+                    rate_args(&preset_args, encoder, video_bitrate, Some(2)),
+                );
+                self.run_ffmpeg(&job, args, Some(&work_dir), Some((2, 2)), start, on_event)
+            }
+            result => result,
+        };
+        let _ = std::fs::remove_dir_all(&work_dir);
+        result
+    }
+
+    // 运行一次 ffmpeg，pass 为两遍编码中的第几遍；start 为整个任务的开始时间。结束后报告 Exited
+    fn run_ffmpeg(
+        &self,
+        job: &Job,
+        args: Vec<OsString>,
+        work_dir: Option<&Path>,
+        pass: Option<(u32, u32)>,
+        start: Instant,
+        on_event: &mut dyn FnMut(Event) -> Control,
+    ) -> JobResult {
+        let result = self.watch_ffmpeg(job, args, work_dir, pass, start, on_event);
+        on_event(Event::Exited);
+        result
+    }
+
+    // 启动 ffmpeg 并读取其输出，直到结束、卡住、超时或被调用方中止
+    fn watch_ffmpeg(
+        &self,
+        job: &Job,
+        args: Vec<OsString>,
+        work_dir: Option<&Path>,
+        pass: Option<(u32, u32)>,
+        start: Instant,
+        on_event: &mut dyn FnMut(Event) -> Control,
+    ) -> JobResult {
+        let mut command = Command::new(&job.ffmpeg);
+        command
+            .args(args)
+            .stderr(Stdio::piped())
+            .stdout(Stdio::null())
+            .stdin(Stdio::null());
+        if let Some(dir) = work_dir {
+            command.current_dir(dir);
+        }
+
+        // 独立进程组，控制台的 Ctrl+C 不会直接传给 ffmpeg，由调用方决定如何处理
+        #[cfg(windows)]
+        {
+            use std::os::windows::process::CommandExt;
+            command.creation_flags(winapi::um::winbase::CREATE_NEW_PROCESS_GROUP);
+        }
+
+        let mut child = match command.spawn() {
+            Ok(child) => child,
+            Err(e) => {
+                return JobResult::Failed(JobFailure::new(format!(
+                    "无法启动 ffmpeg {}: {}",
+                    job.ffmpeg.display(),
+                    e
+                )));
+            }
+        };
+
+        if on_event(Event::Started { pid: child.id() }) == Control::Abort {
+            abort(&mut child, &job.output);
+            return JobResult::Cancelled;
+        }
+
+        let stderr = child.stderr.take().expect("无法获取 stderr");
+        let reader = BufReader::new(stderr);
+
+        // 在单独线程中按行读取 ffmpeg 输出，主循环即使没有新输出也能定时检查卡住、超时和用户操作
+        let (line_tx, line_rx) = mpsc::channel::<String>();
+        std::thread::spawn(move || {
+            let mut buffer = Vec::new();
+            for b in reader.bytes().map_while(Result::ok) {
+                if b != b'\r' && b != b'\n' {
+                    buffer.push(b);
+                    continue;
+                }
+
+                if buffer.is_empty() {
+                    continue;
+                }
+
+                let line = String::from_utf8_lossy(&buffer).into_owned();
+                buffer.clear();
+                if line_tx.send(line).is_err() {
+                    break;
+                }
+            }
+        });
+
+        // 合并多个文件时 ffmpeg 输出每个文件的 Duration，总时长用各文件时长之和
+        let mut total_duration: Option<Duration> = job.concat.as_ref().and_then(|c| c.duration);
+
+        // 本次运行的开始时间，用于超时检测
+        let start_timestamp = Instant::now();
+
+        // 最近一次 time= 前进的时间点，用于卡住检测
+        let mut last_progress_time = Duration::ZERO;
+        let mut last_progress_instant = start_timestamp;
+
+        // 保留最后若干行非进度输出，失败时用于分析原因
+        let mut stderr_tail: VecDeque<String> = VecDeque::with_capacity(STDERR_TAIL_LINES);
+
+        loop {
+            let line = match line_rx.recv_timeout(Duration::from_millis(500)) {
+                Ok(line) => line,
+                Err(RecvTimeoutError::Timeout) => {
+                    if on_event(Event::Waiting) == Control::Abort {
+                        abort(&mut child, &job.output);
+                        return JobResult::Cancelled;
+                    }
+                    String::new()
+                }
+                Err(RecvTimeoutError::Disconnected) => break,
+            };
+
+            // 卡住检测和单个任务超时，任一触发都结束本次转码
+            let timeout_reason = if let Some(stall_timeout) = self.stall_timeout
+                && last_progress_instant.elapsed() > stall_timeout
+            {
+                Some(format!(
+                    "超过 {} 分钟没有进度",
+                    stall_timeout.as_secs() / 60
+                ))
+            } else if let Some(ratio) = self.timeout_ratio
+                && let Some(total) = total_duration
+                && start_timestamp.elapsed().as_secs_f64() > total.as_secs_f64() * ratio
+            {
+                Some(format!("用时超过视频时长的 {} 倍", ratio))
+            } else {
+                None
+            };
+
+            if let Some(reason) = timeout_reason {
+                abort(&mut child, &job.output);
+                return JobResult::Failed(JobFailure {
+                    reason,
+                    retryable: true,
+                    fallback: true,
+                    stderr_tail: stderr_tail.into(),
+                });
+            }
+
+            if line.is_empty() {
+                continue;
+            }
+
+            // 解析总时长，剪辑时进度按剪辑后的时长计算
+            if total_duration.is_none()
+                && let Some(duration) = parse_total_duration(&line)
+            {
+                total_duration = Some(match &job.trim {
+                    Some(trim) => trim.duration_within(duration),
+                    None => duration,
+                });
+            }
+
+            // 解析进度信息
+            let Some(info) = parse_progress(&line) else {
+                if stderr_tail.len() == STDERR_TAIL_LINES {
+                    stderr_tail.pop_front();
+                }
+                stderr_tail.push_back(line);
+                continue;
+            };
+
+            if info.current_time > last_progress_time {
+                last_progress_time = info.current_time;
+                last_progress_instant = Instant::now();
+            }
+
+            let progress = Progress {
+                current_time: info.current_time,
+                total: total_duration,
+                speed_str: info.speed_str,
+                elapsed: start.elapsed(),
+                pass,
+            };
+            if on_event(Event::Progress(&progress)) == Control::Abort {
+                abort(&mut child, &job.output);
+                return JobResult::Cancelled;
+            }
+        }
+
+        let status = match child.wait() {
+            Ok(status) => status,
+            Err(e) => {
+                let _ = std::fs::remove_file(&job.output);
+                return JobResult::Failed(JobFailure::new(format!("ffmpeg 执行失败: {}", e)));
+            }
+        };
+
+        if status.success() {
+            return JobResult::Success(JobStats {
+                media_duration: total_duration,
+                elapsed: start.elapsed(),
+            });
+        }
+
+        let exit_str = match status.code() {
+            Some(code) => format!("ffmpeg 退出码 {}", code),
+            None => "ffmpeg 异常退出".to_string(),
+        };
+        let _ = std::fs::remove_file(&job.output); // 失败的输出文件不完整，直接删除
+
+        JobResult::Failed(JobFailure::from_exit(exit_str, stderr_tail.into()))
+    }
+}
+
+// 转码参数：输入、复制元数据、编码参数和输出文件
+fn output_args<S: Into<OsString>>(
+    job: &Job,
+    codec_args: impl IntoIterator<Item = S>,
+) -> Vec<OsString> {
+    let mut args: Vec<OsString> = vec!["-hide_banner".into()];
+    args.extend(source_args(job));
+    // 复制容器级的元数据（拍摄时间、GPS、设备型号、音乐标签等），放在预设参数之前以便预设覆盖
+    args.extend(["-map_metadata", "0"].map(OsString::from));
+    if is_mp4_family(&job.output) {
+        args.extend(["-movflags", "+use_metadata_tags"].map(OsString::from));
+    }
+    args.extend(codec_args.into_iter().map(Into::into));
+    args.push("-y".into()); // 覆盖输出文件
+    args.push(job.output.clone().into());
+    args
+}
+
+// 输入部分的参数：预设的输入参数、剪辑的开始时间、-i 输入文件和剪辑的时长；合并多个文件时为合并的参数
+fn source_args(job: &Job) -> Vec<OsString> {
+    if let Some(concat) = &job.concat {
+        return concat
+            .input_args()
+            .into_iter()
+            .map(OsString::from)
+            .collect();
+    }
+
+    let mut args: Vec<OsString> = job.preset.input_args().map(OsString::from).collect();
+    args.extend(
+        job.trim
+            .iter()
+            .flat_map(Trim::input_args)
+            .map(OsString::from),
+    );
+    args.push("-i".into());
+    args.push(job.input.clone().into());
+    args.extend(
+        job.trim
+            .iter()
+            .flat_map(Trim::output_args)
+            .map(OsString::from),
+    );
+    args
+}
+
+// 用 ffmpeg -i 读取输入的时长和音频码率（不需要 ffprobe）
+fn probe_input(ffmpeg: &Path, input: &Path) -> (Option<Duration>, Option<u64>) {
+    let Ok(output) = Command::new(ffmpeg)
+        .arg("-hide_banner")
+        .arg("-i")
+        .arg(input)
+        .stdin(Stdio::null())
+        .output()
+    else {
+        return (None, None);
+    };
+
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    let duration = stderr.lines().find_map(parse_total_duration);
+    let audio_bitrates: Vec<u64> = stderr.lines().filter_map(parse_audio_bitrate).collect();
+    let audio_bitrate = (!audio_bitrates.is_empty()).then(|| audio_bitrates.iter().sum());
+    (duration, audio_bitrate)
+}
+
+// 结束 ffmpeg 并删除未完成的输出文件
+fn abort(child: &mut Child, output_path: &Path) {
+    let _ = child.kill();
+    let _ = child.wait();
+    let _ = std::fs::remove_file(output_path);
+}
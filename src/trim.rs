@@ -0,0 +1,171 @@
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::benchmark::segment_ranges;
+use crate::progress::format_duration;
+
+/// 只转码文件中的一段，时间都相对文件开头
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Trim {
+    pub start: Duration,
+    /// 结束时间，None 表示到文件末尾
+    pub end: Option<Duration>,
+}
+
+impl Trim {
+    /// 由开始时间和结束时间（或时长）组成剪辑范围，都没有指定时返回 None
+    pub fn from_parts(
+        start: Option<Duration>,
+        end: Option<Duration>,
+        length: Option<Duration>,
+    ) -> Result<Option<Trim>, String> {
+        let end = match (end, length) {
+            (Some(_), Some(_)) => return Err("结束时间和时长只能指定一个".to_string()),
+            (Some(end), None) => Some(end),
+            (None, Some(length)) if length.is_zero() => {
+                return Err("时长应大于 0".to_string());
+            }
+            (None, Some(length)) => Some(start.unwrap_or_default() + length),
+            (None, None) => None,
+        };
+        if start.is_none() && end.is_none() {
+            return Ok(None);
+        }
+
+        let trim = Trim {
+            start: start.unwrap_or_default(),
+            end,
+        };
+        if trim.end.is_some_and(|end| end <= trim.start) {
+            return Err("结束时间应晚于开始时间".to_string());
+        }
+        Ok(Some(trim))
+    }
+
+    /// 剪辑后的时长，total 为整个文件的时长
+    pub fn duration_within(&self, total: Duration) -> Duration {
+        self.end
+            .map_or(total, |end| end.min(total))
+            .saturating_sub(self.start)
+    }
+
+    /// 放在 -i 之前的参数：从开始时间处读取输入。转码时 ffmpeg 会解码到准确的位置，
+    /// 直接复制的流只能从开始时间之前的关键帧开始
+    pub fn input_args(&self) -> Vec<String> {
+        if self.start.is_zero() {
+            Vec::new()
+        } else {
+            vec!["-ss".to_string(), seconds(self.start)]
+        }
+    }
+
+    /// 输出参数：限制输出时长
+    pub fn output_args(&self) -> Vec<String> {
+        match self.end {
+            Some(end) => vec!["-t".to_string(), seconds(end - self.start)],
+            None => Vec::new(),
+        }
+    }
+}
+
+// 例如 "00:01:30 - 00:05:00"、"00:01:30 - 结尾"
+impl fmt::Display for Trim {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.end {
+            Some(end) => write!(
+                f,
+                "{} - {}",
+                format_duration(&self.start),
+                format_duration(&end)
+            ),
+            None => write!(f, "{} - 结尾", format_duration(&self.start)),
+        }
+    }
+}
+
+/// 在要转码的范围内均匀截取 count 段样本（用于分析输入和 CRF 搜索），返回相对文件开头的 (开始时间, 时长)。
+/// total 为整个文件的时长，trim 为 None 时在整个文件中截取
+pub fn sample_ranges(
+    trim: Option<Trim>,
+    total: Duration,
+    count: usize,
+    length: Duration,
+) -> Vec<(Duration, Duration)> {
+    let (offset, duration) = match trim {
+        Some(trim) => (trim.start, trim.duration_within(total)),
+        None => (Duration::ZERO, total),
+    };
+    segment_ranges(duration, count, length)
+        .into_iter()
+        .map(|(start, length)| (offset + start, length))
+        .collect()
+}
+
+fn seconds(duration: Duration) -> String {
+    format!("{:.3}", duration.as_secs_f64())
+}
+
+/// 解析时间，可以是秒数 ("90"、"90.5")，也可以是 "1:30"、"01:02:03.5"
+pub fn parse_time(value: &str) -> Option<Duration> {
+    let mut parts = value.trim().rsplit(':');
+    let seconds: f64 = parts.next()?.parse().ok()?;
+    if !seconds.is_finite() || seconds < 0.0 {
+        return None;
+    }
+
+    let mut total = seconds;
+    for (i, part) in parts.enumerate() {
+        // 最多到小时
+        if i >= 2 {
+            return None;
+        }
+        let n: u64 = part.parse().ok()?;
+        total += n as f64 * if i == 0 { 60.0 } else { 3600.0 };
+    }
+    Some(Duration::from_secs_f64(total))
+}
+
+/// 输入文件旁的剪辑设置文件，例如 `a.mp4` 的 `a.mp4.trim`
+pub fn sidecar_path(input: &Path) -> PathBuf {
+    let mut name = input.as_os_str().to_owned();
+    name.push(".trim");
+    PathBuf::from(name)
+}
+
+/// 读取输入文件旁的剪辑设置文件，没有该文件时返回 None
+pub fn read_sidecar(input: &Path) -> Result<Option<Trim>, String> {
+    let path = sidecar_path(input);
+    if !path.is_file() {
+        return Ok(None);
+    }
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("无法读取 {}: {}", path.display(), e))?;
+    parse_trim(&text).map_err(|e| format!("{}: {}", path.display(), e))
+}
+
+/// 解析剪辑设置，每行一个 `start = 00:01:30`、`end = 00:05:00` 或 `duration = 90`，
+/// 以 `//` 或 `#` 开头的行为注释
+pub fn parse_trim(text: &str) -> Result<Option<Trim>, String> {
+    let mut start = None;
+    let mut end = None;
+    let mut length = None;
+
+    for line in text.lines().map(str::trim) {
+        if line.is_empty() || line.starts_with("//") || line.starts_with('#') {
+            continue;
+        }
+        let Some((key, value)) = line.split_once('=') else {
+            return Err(format!("无法识别: {}", line));
+        };
+        let time = parse_time(value).ok_or_else(|| format!("时间无效: {}", line))?;
+        match key.trim() {
+            "start" => start = Some(time),
+            "end" => end = Some(time),
+            "duration" => length = Some(time),
+            _ => return Err(format!("未知的剪辑项: {}", line)),
+        }
+    }
+
+    Trim::from_parts(start, end, length)
+}
@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::concat::combined_info;
+use crate::job::Job;
+use crate::media::{MediaInfo, full_decode};
+
+/// 转码成功后对输出文件的校验方式
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum VerifyMode {
+    /// 不校验，ffmpeg 正常退出即视为成功
+    #[default]
+    Off,
+    /// 用 ffprobe 比较输出与输入的时长和帧数
+    Probe,
+    /// 在 Probe 的基础上完整解码一遍输出文件，检查是否损坏
+    Decode,
+}
+
+impl VerifyMode {
+    pub fn parse(value: &str) -> Option<VerifyMode> {
+        match value {
+            "off" | "0" => Some(VerifyMode::Off),
+            "probe" | "1" => Some(VerifyMode::Probe),
+            "decode" | "2" => Some(VerifyMode::Decode),
+            _ => None,
+        }
+    }
+}
+
+/// 校验输出文件：时长和帧数与输入相差不超过容差，可选完整解码
+#[derive(Clone, Debug)]
+pub struct Verifier {
+    pub ffprobe: PathBuf,
+    pub mode: VerifyMode,
+    /// 时长允许的误差，帧数的误差按输出帧率换算
+    pub tolerance: Duration,
+}
+
+impl Verifier {
+    /// 校验通过返回 Ok，否则返回原因
+    pub fn verify(&self, job: &Job) -> Result<(), String> {
+        if self.mode == VerifyMode::Off {
+            return Ok(());
+        }
+
+        let mut input = match &job.concat {
+            Some(concat) => combined_info(
+                &concat
+                    .inputs
+                    .iter()
+                    .map(|input| MediaInfo::probe_counting_frames(&self.ffprobe, input))
+                    .collect::<Result<Vec<_>, _>>()?,
+            ),
+            None => MediaInfo::probe_counting_frames(&self.ffprobe, &job.input)?,
+        };
+        // 音频预设的输出只含音频（和封面），不比较视频帧数
+        if job.preset.audio {
+            input.streams.retain(|s| s.codec_type != "video");
+        }
+        // 只转码了一段时，按剪辑后的时长比较，帧数按比例换算
+        if let (Some(trim), Some(full)) = (job.trim, input.duration) {
+            let trimmed = trim.duration_within(full);
+            input.duration = Some(trimmed);
+            for stream in input.streams.iter_mut() {
+                stream.frames = stream.frames.filter(|_| !full.is_zero()).map(|frames| {
+                    (frames as f64 * trimmed.as_secs_f64() / full.as_secs_f64()).round() as u64
+                });
+            }
+        }
+        let output = MediaInfo::probe_counting_frames(&self.ffprobe, &job.output)
+            .map_err(|e| format!("校验失败: {}", e))?;
+        compare_media(&input, &output, self.tolerance).map_err(|e| format!("校验失败: {}", e))?;
+
+        if self.mode == VerifyMode::Decode {
+            full_decode(&job.ffmpeg, &job.output).map_err(|e| format!("校验失败: {}", e))?;
+        }
+
+        Ok(())
+    }
+}
+
+/// 比较输入和输出的时长与视频帧数，输入中读不到的项不比较
+pub fn compare_media(
+    input: &MediaInfo,
+    output: &MediaInfo,
+    tolerance: Duration,
+) -> Result<(), String> {
+    if let Some(expected) = input.duration {
+        let Some(actual) = output.duration else {
+            return Err("无法读取输出时长".to_string());
+        };
+        if actual.abs_diff(expected) > tolerance {
+            return Err(format!(
+                "输出时长 {:.2} 秒，输入为 {:.2} 秒",
+                actual.as_secs_f64(),
+                expected.as_secs_f64()
+            ));
+        }
+    }
+
+    let Some(input_video) = input.video_stream() else {
+        return Ok(());
+    };
+    let Some(output_video) = output.video_stream() else {
+        return Err("输出中没有视频流".to_string());
+    };
+
+    if let (Some(input_frames), Some(input_fps)) = (input_video.frames, input_video.frame_rate) {
+        let Some(output_frames) = output_video.frames else {
+            return Err("无法读取输出帧数".to_string());
+        };
+
+        // 输出帧率可能与输入不同（如限制了帧率），按输出帧率换算期望的帧数
+        let output_fps = output_video.frame_rate.unwrap_or(input_fps);
+        let expected = input_frames as f64 * output_fps / input_fps;
+        let allowed = tolerance.as_secs_f64() * output_fps + 1.0;
+        if (output_frames as f64 - expected).abs() > allowed {
+            return Err(format!(
+                "输出 {} 帧，应约为 {:.0} 帧",
+                output_frames, expected
+            ));
+        }
+    }
+
+    Ok(())
+}
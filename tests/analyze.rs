@@ -0,0 +1,220 @@
+// 按输入文件信息生成的视频滤镜、黑边和隔行扫描检测
+
+use ffmpeg_convert::analyze::{
+    Analysis, Crop, IdetCounts, Scan, classify_scan, deinterlace_filter, parse_cropdetect,
+    parse_idet, stable_crop, video_filters, with_video_filters,
+};
+use ffmpeg_convert::media::MediaInfo;
+use ffmpeg_convert::preset::Preset;
+
+fn analysis(width: u32, height: u32, frame_rate: &str) -> Analysis {
+    Analysis {
+        info: MediaInfo::parse(&format!(
+            "[STREAM]\nindex=0\ncodec_type=video\ncodec_name=h264\nwidth={}\nheight={}\navg_frame_rate={}\n[/STREAM]\n",
+            width, height, frame_rate
+        )),
+        ..Default::default()
+    }
+}
+
+fn capped_preset() -> Preset {
+    let mut preset = Preset::new("-c:v libx265 -crf 23", "_H265", "");
+    preset.max_res = Some(1080);
+    preset.max_fps = Some(30.0);
+    preset
+}
+
+#[test]
+fn caps_only_inputs_above_the_limits() {
+    let preset = capped_preset();
+    assert_eq!(
+        video_filters(&analysis(3840, 2160, "60/1"), &preset),
+        [
+            "scale=w='if(gte(iw,ih),1920,1080)':h='if(gte(iw,ih),1080,1920)':force_original_aspect_ratio=decrease:force_divisible_by=2",
+            "fps=30"
+        ]
+    );
+    // 竖屏 4K 限制在 1080x1920 之内
+    assert_eq!(
+        video_filters(&analysis(2160, 3840, "30000/1001"), &preset).len(),
+        1
+    );
+    assert!(video_filters(&analysis(1920, 1080, "30/1"), &preset).is_empty());
+    assert!(video_filters(&analysis(1280, 720, "25/1"), &preset).is_empty());
+
+    // 宽银幕的短边不到 1080，但宽度超过 1920
+    assert_eq!(
+        video_filters(&analysis(2560, 1072, "24/1"), &preset).len(),
+        1
+    );
+    let mut hd = preset.clone();
+    hd.max_res = Some(720);
+    assert!(
+        video_filters(&analysis(3840, 2160, "24/1"), &hd)[0]
+            .starts_with("scale=w='if(gte(iw,ih),1280,720)'")
+    );
+}
+
+#[test]
+fn merges_filters_into_existing_vf() {
+    let filters = ["fps=30".to_string()];
+    let preset = Preset::new("-c:v libx265 -vf hqdn3d -crf 23", "_H265", "");
+    assert_eq!(
+        with_video_filters(&preset, &filters).unwrap().params,
+        "-c:v libx265 -vf fps=30,hqdn3d -crf 23"
+    );
+
+    let preset = Preset::new("-c:v libx265", "_H265", "");
+    assert_eq!(
+        with_video_filters(&preset, &filters).unwrap().params,
+        "-c:v libx265 -vf fps=30"
+    );
+
+    let preset = Preset::new("-filter_complex [0:v]null -c:v libx265", "_H265", "");
+    assert!(with_video_filters(&preset, &filters).is_err());
+}
+
+fn crop(width: u32, height: u32, x: u32, y: u32) -> Crop {
+    Crop {
+        width,
+        height,
+        x,
+        y,
+    }
+}
+
+#[test]
+fn parses_last_cropdetect_line() {
+    let output = "\
+[Parsed_cropdetect_0 @ 0x5581] x1:0 x2:1919 y1:142 y2:937 w:1920 h:796 x:0 y:142 pts:48 t:0.048 limit:0.094 crop=1920:796:0:142
+[Parsed_cropdetect_0 @ 0x5581] x1:0 x2:1919 y1:140 y2:939 w:1920 h:800 x:0 y:140 pts:96 t:0.096 limit:0.094 crop=1920:800:0:140
+frame=   48 fps=0.0 q=-0.0 Lsize=N/A time=00:00:02.00";
+    assert_eq!(parse_cropdetect(output), Some(crop(1920, 800, 0, 140)));
+
+    // 整段都是黑帧
+    let output = "[Parsed_cropdetect_0 @ 0x5581] x1:1919 x2:0 y1:1079 y2:0 w:-1904 h:-1072 x:1912 y:1076 pts:48 t:0.048 limit:0.094 crop=-1904:-1072:1912:1076";
+    assert_eq!(parse_cropdetect(output), None);
+    assert_eq!(parse_cropdetect("no crop here"), None);
+}
+
+#[test]
+fn settles_on_stable_crop() {
+    let letterbox = Some(crop(1920, 800, 0, 140));
+    // 暗场样本的范围偏小、全黑样本没有结果，都不影响
+    let dark = Some(crop(1200, 500, 300, 300));
+    assert_eq!(
+        stable_crop(&[letterbox, dark, letterbox, None, letterbox], 1920, 1080),
+        letterbox
+    );
+    // 竖屏视频的 ffprobe 宽高是旋转前的
+    assert_eq!(
+        stable_crop(&[Some(crop(800, 1920, 140, 0)); 3], 1920, 1080),
+        Some(crop(800, 1920, 140, 0))
+    );
+
+    // 有样本出现了黑边中的画面
+    let full = Some(crop(1920, 1080, 0, 0));
+    assert_eq!(stable_crop(&[letterbox, full, letterbox], 1920, 1080), None);
+    // 结果不一致
+    let a = Some(crop(1920, 800, 0, 200));
+    let b = Some(crop(1920, 800, 0, 80));
+    assert_eq!(stable_crop(&[a, b, letterbox], 1920, 1080), None);
+    // 多数样本太暗
+    assert_eq!(stable_crop(&[letterbox, None, None], 1920, 1080), None);
+    // 裁剪后画面太小
+    assert_eq!(
+        stable_crop(&[Some(crop(1920, 400, 0, 340)); 3], 1920, 1080),
+        None
+    );
+    // 只差几个像素
+    assert_eq!(
+        stable_crop(&[Some(crop(1916, 1076, 2, 2)); 3], 1920, 1080),
+        None
+    );
+}
+
+#[test]
+fn crops_before_scaling() {
+    let mut analysis = analysis(3840, 2160, "24/1");
+    analysis.crop = Some(crop(3840, 1600, 0, 280));
+    // 裁剪后 3840x1600 仍超过 1920x1080
+    assert_eq!(
+        video_filters(&analysis, &capped_preset()),
+        [
+            "crop=3840:1600:0:280",
+            "scale=w='if(gte(iw,ih),1920,1080)':h='if(gte(iw,ih),1080,1920)':force_original_aspect_ratio=decrease:force_divisible_by=2"
+        ]
+    );
+
+    // 裁掉上下黑边的宽银幕按宽度缩小到 1920x804，不按短边放大到 2579x1080
+    analysis.crop = Some(crop(3840, 1608, 0, 276));
+    assert_eq!(
+        video_filters(&analysis, &capped_preset()),
+        [
+            "crop=3840:1608:0:276",
+            "scale=w='if(gte(iw,ih),1920,1080)':h='if(gte(iw,ih),1080,1920)':force_original_aspect_ratio=decrease:force_divisible_by=2"
+        ]
+    );
+
+    analysis.crop = Some(crop(1920, 800, 0, 140));
+    assert_eq!(
+        video_filters(&analysis, &capped_preset()),
+        ["crop=1920:800:0:140"]
+    );
+    let copy = Preset::new("-c:v copy -c:a aac", "_AAC", "");
+    assert!(video_filters(&analysis, &copy).is_empty());
+}
+
+#[test]
+fn parses_idet_statistics() {
+    let output = "\
+[Parsed_idet_0 @ 0x55e1] Repeated Fields: Neither:   180 Top:    60 Bottom:    60
+[Parsed_idet_0 @ 0x55e1] Single frame detection: TFF:    90 BFF:     0 Progressive:   150 Undetermined:    60
+[Parsed_idet_0 @ 0x55e1] Multi frame detection: TFF:   110 BFF:     2 Progressive:   180 Undetermined:     8";
+    assert_eq!(
+        parse_idet(output),
+        Some(IdetCounts {
+            interlaced: 112,
+            progressive: 180,
+            undetermined: 8,
+            repeated: 120,
+            frames: 300,
+        })
+    );
+    assert_eq!(parse_idet("frame=  300 fps=0.0"), None);
+}
+
+#[test]
+fn classifies_scan_type() {
+    let counts = |interlaced, progressive, repeated| IdetCounts {
+        interlaced,
+        progressive,
+        undetermined: 10,
+        repeated,
+        frames: interlaced + progressive + 10,
+    };
+    assert_eq!(classify_scan(&[counts(240, 5, 2); 2]), Scan::Interlaced);
+    // 3:2 下拉：5 帧中 2 帧隔行，并有重复场
+    assert_eq!(classify_scan(&[counts(100, 150, 100)]), Scan::Telecined);
+    assert_eq!(classify_scan(&[counts(3, 290, 0)]), Scan::Progressive);
+    // 能判断的帧太少
+    assert_eq!(classify_scan(&[counts(40, 10, 0)]), Scan::Progressive);
+    assert_eq!(classify_scan(&[]), Scan::Progressive);
+}
+
+#[test]
+fn deinterlaces_before_other_filters() {
+    let mut analysis = analysis(720, 576, "25/1");
+    analysis.scan = Scan::Interlaced;
+    analysis.deinterlace = deinterlace_filter(Scan::Interlaced, "bwdif");
+    analysis.crop = Some(crop(720, 432, 0, 72));
+    assert_eq!(
+        video_filters(&analysis, &capped_preset()),
+        ["bwdif=mode=send_frame", "crop=720:432:0:72"]
+    );
+    assert_eq!(
+        deinterlace_filter(Scan::Telecined, "bwdif").as_deref(),
+        Some("fieldmatch,yadif=deint=interlaced,decimate")
+    );
+    assert_eq!(deinterlace_filter(Scan::Progressive, "bwdif"), None);
+}
@@ -0,0 +1,117 @@
+// 音频模式：预设的筛选、输出文件名、流选择和预设选项
+
+use std::path::Path;
+
+use ffmpeg_convert::analyze::{Analysis, Analyzer, Crop, video_filters};
+use ffmpeg_convert::audio::with_audio_streams;
+use ffmpeg_convert::config::{Settings, parse_config};
+use ffmpeg_convert::job::{is_mp4_family, output_path_with_extension};
+use ffmpeg_convert::media::MediaInfo;
+use ffmpeg_convert::preset::{Preset, builtin_audio_presets, presets_for_mode};
+
+#[test]
+fn keeps_presets_of_the_selected_mode() {
+    let mut video = Preset::new("-c:v libx265", "_H265", "");
+    video.fallback = Some(2);
+    let mut opus = Preset::audio("-c:a libopus", "_OPUS", "opus", "");
+    opus.fallback = Some(3);
+    let mut aac = Preset::audio("-c:a aac", "_AAC", "m4a", "");
+    aac.fallback = Some(0);
+    let flac = Preset::audio("-c:a flac", "_FLAC", "flac", "");
+
+    let audio = presets_for_mode(vec![video.clone(), opus, aac, flac], true);
+    assert_eq!(audio.len(), 3);
+    assert_eq!(audio[0].fallback, Some(2));
+    // 备用预设是视频预设，被丢弃
+    assert_eq!(audio[1].fallback, None);
+
+    let video = presets_for_mode(
+        vec![video, Preset::audio("-c:a flac", "_FLAC", "flac", "")],
+        false,
+    );
+    assert_eq!(video.len(), 1);
+    assert_eq!(video[0].fallback, None);
+}
+
+#[test]
+fn names_output_with_preset_extension() {
+    let presets = builtin_audio_presets();
+    assert!(presets.iter().all(|p| p.audio));
+    assert_eq!(
+        output_path_with_extension(
+            Path::new("music/song.flac"),
+            &presets[0].subfix,
+            presets[0].extension()
+        ),
+        Path::new("music/song_OPUS.opus")
+    );
+    assert_eq!(Preset::new("-c:v libx265", "_H265", "").extension(), "mp4");
+
+    assert!(is_mp4_family(Path::new("song_AAC.m4a")));
+    assert!(is_mp4_family(Path::new("movie.MOV")));
+    assert!(!is_mp4_family(Path::new("song_MP3.mp3")));
+}
+
+#[test]
+fn keeps_cover_art_only_where_supported() {
+    let aac = Preset::audio("-c:a aac -b:a 256k", "_AAC", "m4a", "");
+    assert_eq!(
+        with_audio_streams(&aac, Path::new("song.flac")).params,
+        "-map 0:a -map 0:v? -c:v copy -disposition:v attached_pic -c:a aac -b:a 256k"
+    );
+    // 视频文件的画面不是封面
+    assert_eq!(
+        with_audio_streams(&aac, Path::new("concert.mp4")).params,
+        "-vn -sn -dn -c:a aac -b:a 256k"
+    );
+    let opus = Preset::audio("-c:a libopus", "_OPUS", "opus", "");
+    assert_eq!(
+        with_audio_streams(&opus, Path::new("song.flac")).params,
+        "-vn -sn -dn -c:a libopus"
+    );
+}
+
+#[test]
+fn parses_audio_preset_options() {
+    let mut presets = Vec::new();
+    let mut settings = Settings::default();
+    let warnings = parse_config(
+        "-c:a alac # _ALAC # Apple 无损 # audio=1 ext=M4A\n\
+         -c:a aac # _AAC # 无效 # audio=1 ext=m4a.bak",
+        &mut presets,
+        &mut settings,
+    );
+    assert!(presets[0].audio);
+    assert_eq!(presets[0].extension(), "m4a");
+    assert_eq!(presets[1].extension(), "mp4");
+    assert_eq!(warnings.len(), 1);
+}
+
+#[test]
+fn audio_presets_are_not_analyzed_or_filtered() {
+    let analyzer = Analyzer {
+        ffprobe: "ffprobe".into(),
+        autocrop: true,
+        deinterlacer: Some("bwdif".to_string()),
+    };
+    let aac = Preset::audio("-c:a aac -b:a 256k", "_AAC", "m4a", "");
+    assert!(!analyzer.needed(&aac));
+    assert!(analyzer.needed(&Preset::new("-c:v libx265", "_H265", "")));
+
+    // 封面图片为直接复制的视频流，不能加滤镜
+    let analysis = Analysis {
+        info: MediaInfo::parse(
+            "[STREAM]\nindex=0\ncodec_type=video\ncodec_name=mjpeg\nwidth=600\nheight=600\n[/STREAM]\n",
+        ),
+        crop: Some(Crop {
+            width: 600,
+            height: 500,
+            x: 0,
+            y: 50,
+        }),
+        deinterlace: Some("bwdif=mode=send_frame".to_string()),
+        ..Default::default()
+    };
+    let aac = with_audio_streams(&aac, Path::new("song.flac"));
+    assert!(video_filters(&analysis, &aac).is_empty());
+}
@@ -0,0 +1,567 @@
+// 用 FakeBackend 测试批量转码的重试、备用预设、日志、体积统计和关机，不需要 ffmpeg
+
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::time::Duration;
+
+use ffmpeg_convert::batch::{Batch, BatchObserver, FileOutcome, ShutdownStatus};
+use ffmpeg_convert::discover::{VIDEO_EXTS, collect_video_files};
+use ffmpeg_convert::job::{FfmpegError, Job, JobFailure};
+use ffmpeg_convert::log::Logger;
+use ffmpeg_convert::nogain::{NoGainAction, NoGainPolicy};
+use ffmpeg_convert::preset::builtin_presets;
+use ffmpeg_convert::replace::{ReplaceOriginal, ReplaceRecord, records_in_log};
+use ffmpeg_convert::transcoder::{Control, Event};
+use ffmpeg_convert::trim::Trim;
+use ffmpeg_convert::{FakeBackend, FakeRun};
+
+const MINUTE: Duration = Duration::from_secs(60);
+
+// 每个测试使用独立的临时目录
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = std::env::temp_dir().join(format!(
+        "ffmpeg_convert_test_{}_{}",
+        name,
+        std::process::id()
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn input_file(dir: &Path, name: &str, size: usize) -> PathBuf {
+    let path = dir.join(name);
+    std::fs::write(&path, vec![0u8; size]).unwrap();
+    path
+}
+
+fn batch(dir: &Path, backend: &FakeBackend, retries: u32, shutdowns: &Arc<AtomicU32>) -> Batch {
+    let presets = builtin_presets();
+    let shutdowns = shutdowns.clone();
+    Batch {
+        preset_ffmpeg: vec![Some(PathBuf::from("ffmpeg")); presets.len()],
+        presets,
+        retries,
+        backend: Box::new(backend.clone()),
+        logger: Logger::new(dir.join("test.log")),
+        verifier: None,
+        analyzer: None,
+        crf_search: None,
+        remuxer: None,
+        trim: None,
+        concat: None,
+        no_gain: None,
+        replace: None,
+        shutdown: Some(Box::new(move || {
+            shutdowns.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        })),
+    }
+}
+
+fn log_content(dir: &Path) -> String {
+    std::fs::read_to_string(dir.join("test.log")).unwrap_or_default()
+}
+
+#[derive(Default)]
+struct Recorder {
+    progress: Vec<f64>,
+    failures: Vec<(String, Option<String>)>,
+    abort_at_progress: Option<usize>,
+    stop_after_first: bool,
+}
+
+impl BatchObserver for Recorder {
+    fn event(&mut self, _job: &Job, event: Event) -> Control {
+        if let Event::Progress(p) = event {
+            self.progress.push(p.percentage().unwrap());
+            if self.abort_at_progress == Some(self.progress.len()) {
+                return Control::Abort;
+            }
+        }
+        Control::Continue
+    }
+
+    fn attempt_failed(
+        &mut self,
+        failure: &JobFailure,
+        next: Option<&ffmpeg_convert::preset::Preset>,
+    ) {
+        self.failures
+            .push((failure.reason.clone(), next.map(|p| p.description.clone())));
+    }
+
+    fn stop_requested(&mut self) -> bool {
+        self.stop_after_first
+    }
+}
+
+#[test]
+fn converts_files_and_reports_sizes() {
+    let dir = temp_dir("sizes");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let b = input_file(&dir, "b_H264.mp4", 2000);
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::success(MINUTE, 500))
+        .push(FakeRun::success(MINUTE, 3000));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut recorder = Recorder::default();
+
+    let report = batch(&dir, &backend, 0, &shutdowns).run(&[a, b], 0, &mut recorder);
+
+    assert_eq!(report.converted(), 2);
+    assert!(!report.stopped);
+    // 输出文件名中的 _H264/_H265 会被去掉
+    assert_eq!(report.files[0].output, dir.join("a.mp4"));
+    assert_eq!(report.files[1].output, dir.join("b.mp4"));
+    assert_eq!(report.files[0].size_change_percent(), Some(-50.0));
+    assert_eq!(report.files[1].size_change_percent(), Some(50.0));
+    assert_eq!(recorder.progress, [25.0, 50.0, 75.0, 100.0].repeat(2));
+
+    let log = log_content(&dir);
+    assert_eq!(log.matches("] 输入: ").count(), 2);
+    assert!(log.contains("1000.00 B -> 500.00 B (-50.0%)"));
+    assert!(log.contains("1.95 KB -> 2.93 KB (50.0%)"));
+    assert!(log.contains("视频时长:"));
+
+    assert_eq!(report.shutdown, ShutdownStatus::Scheduled);
+    assert_eq!(shutdowns.load(Ordering::SeqCst), 1);
+    assert_eq!(backend.remaining(), 0);
+}
+
+#[test]
+fn retries_then_falls_back_to_alternative_preset() {
+    let dir = temp_dir("fallback");
+    let input = input_file(&dir, "a.mkv", 1000);
+
+    let backend = FakeBackend::new();
+    let amf_error = ["[hevc_amf @ 0000] DLL amfrt64.dll failed to open"];
+    backend
+        .push(FakeRun::exit(MINUTE, 1, &amf_error))
+        .push(FakeRun::exit(MINUTE, 1, &amf_error))
+        .push(FakeRun::success(MINUTE, 400));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut recorder = Recorder::default();
+
+    // 预设 2 (hevc_amf) 失败后改用预设 1 (libx265)
+    let report = batch(&dir, &backend, 1, &shutdowns).run(&[input], 1, &mut recorder);
+
+    let jobs = backend.jobs();
+    let encoders: Vec<bool> = jobs
+        .iter()
+        .map(|j| j.preset.params.contains("hevc_amf"))
+        .collect();
+    assert_eq!(encoders, vec![true, true, false]);
+
+    assert_eq!(report.converted(), 1);
+    assert_eq!(report.files[0].attempts, 3);
+    assert_eq!(report.files[0].preset, 0);
+    assert_eq!(recorder.failures.len(), 2);
+    assert!(recorder.failures[0].0.starts_with("编码器不可用"));
+    assert!(
+        recorder.failures[1]
+            .1
+            .as_deref()
+            .unwrap()
+            .contains("libx265")
+    );
+
+    let log = log_content(&dir);
+    assert_eq!(log.matches("] 失败: ").count(), 2);
+    assert!(log.contains("| [hevc_amf @ 0000] DLL amfrt64.dll failed to open"));
+    assert!(log.contains("第 1 次尝试失败，重试使用预设: H265 (hevc_amf)"));
+    assert!(log.contains("第 2 次尝试失败，重试使用预设: H265 (libx265)"));
+    assert!(log.contains("第 3 次尝试成功，使用预设: H265 (libx265)"));
+}
+
+#[test]
+fn falls_back_without_retrying_when_the_encoder_fails_to_open() {
+    let dir = temp_dir("open_encoder");
+    let input = input_file(&dir, "a.mkv", 1000);
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::exit(
+            MINUTE,
+            1,
+            &["Error while opening encoder for output stream #0:0 - maybe incorrect parameters"],
+        ))
+        .push(FakeRun::success(MINUTE, 400));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut recorder = Recorder::default();
+
+    // 硬件编码器初始化失败时常常只有这一行，同一预设不再重试，直接改用 libx265
+    let report = batch(&dir, &backend, 3, &shutdowns).run(&[input], 1, &mut recorder);
+
+    let jobs = backend.jobs();
+    assert_eq!(jobs.len(), 2);
+    assert!(jobs[0].preset.params.contains("hevc_amf"));
+    assert!(jobs[1].preset.params.contains("libx265"));
+    assert_eq!(report.converted(), 1);
+    assert_eq!(report.files[0].preset, 0);
+    assert!(recorder.failures[0].0.starts_with("编码参数无效"));
+    assert!(log_content(&dir).contains("第 1 次尝试失败，重试使用预设: H265 (libx265)"));
+}
+
+#[test]
+fn does_not_retry_unrecoverable_failures() {
+    let dir = temp_dir("no_space");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let b = input_file(&dir, "b.mkv", 1000);
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::exit(
+            MINUTE,
+            1,
+            &["av_interleaved_write_frame(): No space left on device"],
+        ))
+        .push(FakeRun::success(MINUTE, 400));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut recorder = Recorder::default();
+
+    let report = batch(&dir, &backend, 3, &shutdowns).run(&[a, b], 1, &mut recorder);
+
+    // 第一个文件失败后不重试，继续处理下一个文件
+    assert_eq!(backend.jobs().len(), 2);
+    assert_eq!(report.failed(), 1);
+    assert_eq!(report.converted(), 1);
+    let FileOutcome::Failed(failure) = &report.files[0].outcome else {
+        panic!("第一个文件应该失败");
+    };
+    assert!(!failure.retryable);
+    assert!(!failure.fallback);
+    assert!(failure.reason.starts_with("磁盘空间不足"));
+    assert_eq!(recorder.failures, vec![(failure.reason.clone(), None)]);
+    assert!(!log_content(&dir).contains("尝试失败"));
+
+    // 有失败但没有被中止，仍然关机
+    assert_eq!(report.shutdown, ShutdownStatus::Scheduled);
+}
+
+#[test]
+fn classifies_ffmpeg_errors_by_signature() {
+    let classify = |lines: &[&str]| {
+        FfmpegError::classify(&lines.iter().map(|l| l.to_string()).collect::<Vec<_>>())
+    };
+    // 缺少驱动时最后一行也是 "Error while opening encoder"，以更具体的原因为准
+    assert_eq!(
+        classify(&[
+            "[hevc_amf @ 0000] DLL amfrt64.dll failed to open",
+            "Error while opening encoder for output stream #0:0 - maybe incorrect parameters",
+        ]),
+        Some(FfmpegError::EncoderUnavailable)
+    );
+    assert_eq!(
+        classify(&[
+            "Error while opening encoder for output stream #0:0 - maybe incorrect parameters"
+        ]),
+        Some(FfmpegError::InvalidParameters)
+    );
+    assert!(!FfmpegError::InvalidParameters.is_retryable());
+    assert!(FfmpegError::InvalidParameters.allows_fallback());
+    assert!(!FfmpegError::InvalidData.allows_fallback());
+    // 打开输入或输出文件失败与编码器无关
+    assert_eq!(
+        classify(&["[in#0 @ 0000] Error opening input: failed to open file"]),
+        None
+    );
+}
+
+#[test]
+fn abort_cancels_batch_and_shutdown() {
+    let dir = temp_dir("abort");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let b = input_file(&dir, "b.mkv", 1000);
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::success(MINUTE, 400))
+        .push(FakeRun::success(MINUTE, 400));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut recorder = Recorder {
+        abort_at_progress: Some(2),
+        ..Recorder::default()
+    };
+
+    let report = batch(&dir, &backend, 0, &shutdowns).run(&[a, b], 0, &mut recorder);
+
+    assert_eq!(report.cancelled(), 1);
+    assert!(report.stopped);
+    assert_eq!(report.remaining, 1);
+    assert!(!dir.join("a.mp4").exists());
+    assert!(log_content(&dir).contains(&format!("已取消: {}", dir.join("a.mp4").display())));
+
+    assert_eq!(report.shutdown, ShutdownStatus::Cancelled);
+    assert_eq!(shutdowns.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn stops_after_current_file_when_requested() {
+    let dir = temp_dir("stop");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let b = input_file(&dir, "b.mkv", 1000);
+    let c = input_file(&dir, "c.mkv", 1000);
+
+    let backend = FakeBackend::new();
+    backend.push(FakeRun::success(MINUTE, 400));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut recorder = Recorder {
+        stop_after_first: true,
+        ..Recorder::default()
+    };
+
+    let report = batch(&dir, &backend, 0, &shutdowns).run(&[a, b, c], 0, &mut recorder);
+
+    assert_eq!(report.converted(), 1);
+    assert!(report.stopped);
+    assert_eq!(report.remaining, 2);
+    assert!(dir.join("a.mp4").exists());
+    assert_eq!(report.shutdown, ShutdownStatus::Cancelled);
+    assert_eq!(shutdowns.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn discards_outputs_without_enough_saving() {
+    let dir = temp_dir("no_gain");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let b = input_file(&dir, "b.mkv", 1000);
+    let c = input_file(&dir, "c.avi", 1000);
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::success(MINUTE, 500))
+        .push(FakeRun::success(MINUTE, 950))
+        .push(FakeRun::success(MINUTE, 1200));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+
+    let mut batch = batch(&dir, &backend, 0, &shutdowns);
+    batch.no_gain = Some(NoGainPolicy {
+        min_saving: 10.0,
+        action: NoGainAction::Delete,
+    });
+    let report = batch.run(&[a, b.clone(), c.clone()], 2, &mut Recorder::default());
+
+    assert_eq!(report.converted(), 1);
+    assert_eq!(report.no_gain(), 2);
+    assert!(dir.join("a_AV1.mp4").exists());
+    assert!(!dir.join("b_AV1.mp4").exists());
+    assert!(!dir.join("c_AV1.mp4").exists());
+    assert!(b.exists() && c.exists());
+    let FileOutcome::NoGain { kept, sizes, .. } = &report.files[2].outcome else {
+        panic!("第三个文件应该没有收益");
+    };
+    assert_eq!((kept, *sizes), (&None, (1000, 1200)));
+
+    let log = log_content(&dir);
+    assert!(log.contains("无收益: "));
+    assert!(log.contains("(体积变化 -5.0%，要求至少减少 10%)，已删除输出文件"));
+    assert!(log.contains(
+        "汇总: 共 3 个文件: 成功 1 个，无收益 2 个，失败 0 个，已取消 0 个  1000.00 B -> 500.00 B (-50.0%)"
+    ));
+}
+
+#[test]
+fn copies_original_when_there_is_no_gain() {
+    let dir = temp_dir("no_gain_copy");
+    let a = input_file(&dir, "a.mkv", 1000);
+
+    let backend = FakeBackend::new();
+    backend.push(FakeRun::success(MINUTE, 1500));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+
+    let mut batch = batch(&dir, &backend, 0, &shutdowns);
+    batch.no_gain = Some(NoGainPolicy {
+        min_saving: 0.0,
+        action: NoGainAction::Copy,
+    });
+    let report = batch.run(&[a], 2, &mut Recorder::default());
+
+    // 复制的原文件保留原扩展名
+    let copy = dir.join("a_AV1.mkv");
+    let FileOutcome::NoGain { kept, .. } = &report.files[0].outcome else {
+        panic!("应该没有收益");
+    };
+    assert_eq!(kept.as_ref(), Some(&copy));
+    assert_eq!(std::fs::metadata(&copy).unwrap().len(), 1000);
+    assert!(!dir.join("a_AV1.mp4").exists());
+    assert!(log_content(&dir).contains("已改为复制原文件"));
+}
+
+#[test]
+fn replaces_originals_and_undoes_from_log() {
+    let dir = temp_dir("replace");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let b = input_file(&dir, "b.mp4", 1000);
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::success(MINUTE, 500))
+        .push(FakeRun::success(MINUTE, 400));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+
+    let mut batch = batch(&dir, &backend, 0, &shutdowns);
+    batch.replace = Some(ReplaceOriginal::Archive(PathBuf::from("原文件")));
+    let report = batch.run(&[a.clone(), b.clone()], 2, &mut Recorder::default());
+
+    // 输出改用原文件名，原文件移到归档文件夹
+    let archive = dir.join("原文件");
+    let replaced = report.files[0].replaced.clone().unwrap().unwrap();
+    assert_eq!(replaced.output, dir.join("a.mp4"));
+    assert_eq!(replaced.original, Some(archive.join("a.mkv")));
+    assert!(!a.exists() && !dir.join("a_AV1.mp4").exists());
+    assert_eq!(std::fs::metadata(dir.join("a.mp4")).unwrap().len(), 500);
+    assert_eq!(std::fs::metadata(&b).unwrap().len(), 400);
+    assert_eq!(
+        std::fs::metadata(archive.join("b.mp4")).unwrap().len(),
+        1000
+    );
+
+    // 按日志撤销：原文件放回原处，输出改回原来的文件名
+    let records = records_in_log(&log_content(&dir));
+    assert_eq!(records.len(), 2);
+    for record in records.iter().rev() {
+        assert_eq!(record.undo(), Ok(true));
+    }
+    assert_eq!(std::fs::metadata(&a).unwrap().len(), 1000);
+    assert_eq!(std::fs::metadata(&b).unwrap().len(), 1000);
+    assert_eq!(std::fs::metadata(dir.join("a_AV1.mp4")).unwrap().len(), 500);
+    assert_eq!(std::fs::metadata(dir.join("b_AV1.mp4")).unwrap().len(), 400);
+    assert!(!dir.join("a.mp4").exists());
+
+    // 已撤销过的记录不再处理
+    assert_eq!(records[0].undo(), Ok(false));
+}
+
+#[test]
+fn skips_archived_originals_when_run_again() {
+    let dir = temp_dir("replace_again");
+    input_file(&dir, "a.mkv", 1000);
+    let archive_dir = PathBuf::from("原文件");
+    let discover = || {
+        collect_video_files(
+            &[dir.to_string_lossy().into_owned()],
+            &VIDEO_EXTS,
+            Some(&archive_dir),
+        )
+        .files
+    };
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::success(MINUTE, 500))
+        .push(FakeRun::success(MINUTE, 400));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut batch = batch(&dir, &backend, 0, &shutdowns);
+    batch.replace = Some(ReplaceOriginal::Archive(archive_dir.clone()));
+    batch.run(&discover(), 2, &mut Recorder::default());
+
+    // 再次处理同一文件夹时只找到替换后的输出，归档的原文件不再转码和归档
+    let archived = dir.join("原文件").join("a.mkv");
+    assert!(archived.exists());
+    let inputs = discover();
+    assert_eq!(inputs, vec![dir.canonicalize().unwrap().join("a.mp4")]);
+    batch.run(&inputs, 2, &mut Recorder::default());
+    assert_eq!(backend.jobs().len(), 2);
+    assert!(!dir.join("原文件").join("原文件").exists());
+    assert_eq!(std::fs::metadata(&archived).unwrap().len(), 1000);
+}
+
+#[test]
+fn skips_outputs_of_earlier_runs() {
+    let dir = temp_dir("discover_outputs");
+    for name in ["a.mkv", "a_H265.mp4", "a_SDR.mp4", "a_REMUX.mp4"] {
+        input_file(&dir, name, 10);
+    }
+    let files = collect_video_files(&[dir.to_string_lossy().into_owned()], &VIDEO_EXTS, None).files;
+    assert_eq!(files, vec![dir.canonicalize().unwrap().join("a.mkv")]);
+}
+
+#[test]
+fn undo_frees_the_name_of_an_original_in_the_recycle_bin() {
+    let dir = temp_dir("undo_recycle_bin");
+    // 输出与原文件同名，替换后原文件名上是输出
+    let output = input_file(&dir, "b.mp4", 400);
+    let record = ReplaceRecord {
+        original: output.clone(),
+        archived: None,
+        output: dir.join("b_AV1.mp4"),
+        replaced_by: output.clone(),
+    };
+
+    let error = record.undo().unwrap_err();
+    assert!(error.starts_with("输出已改回"));
+    assert!(error.contains("请从回收站还原"));
+    assert_eq!(std::fs::metadata(dir.join("b_AV1.mp4")).unwrap().len(), 400);
+    assert!(!output.exists());
+
+    // 从回收站还原后不再处理
+    input_file(&dir, "b.mp4", 1000);
+    assert_eq!(record.undo(), Ok(false));
+    assert_eq!(std::fs::metadata(&output).unwrap().len(), 1000);
+}
+
+#[test]
+fn outputs_keep_source_file_times() {
+    let dir = temp_dir("file_times");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let modified = std::time::SystemTime::UNIX_EPOCH + Duration::from_secs(1_500_000_000);
+    std::fs::File::options()
+        .write(true)
+        .open(&a)
+        .unwrap()
+        .set_modified(modified)
+        .unwrap();
+
+    let backend = FakeBackend::new();
+    backend.push(FakeRun::success(MINUTE, 500));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    batch(&dir, &backend, 0, &shutdowns).run(&[a], 2, &mut Recorder::default());
+
+    let output = std::fs::metadata(dir.join("a_AV1.mp4")).unwrap();
+    assert_eq!(output.modified().unwrap(), modified);
+}
+
+#[test]
+fn trims_with_sidecar_before_command_line_range() {
+    let dir = temp_dir("trim");
+    let a = input_file(&dir, "a.mkv", 1000);
+    let b = input_file(&dir, "b.mkv", 1000);
+    let c = input_file(&dir, "c.mkv", 1000);
+    std::fs::write(dir.join("a.mkv.trim"), "start = 1:30\nduration = 60\n").unwrap();
+    std::fs::write(dir.join("c.mkv.trim"), "start = 1:30\nlength = 60\n").unwrap();
+
+    let backend = FakeBackend::new();
+    backend
+        .push(FakeRun::success(MINUTE, 500))
+        .push(FakeRun::success(MINUTE, 500));
+    let shutdowns = Arc::new(AtomicU32::new(0));
+    let mut batch = batch(&dir, &backend, 1, &shutdowns);
+    batch.trim = Some(Trim {
+        start: Duration::ZERO,
+        end: Some(MINUTE),
+    });
+    let mut recorder = Recorder::default();
+
+    let report = batch.run(&[a, b, c], 0, &mut recorder);
+
+    let jobs = backend.jobs();
+    assert_eq!(
+        jobs[0].trim,
+        Some(Trim {
+            start: Duration::from_secs(90),
+            end: Some(Duration::from_secs(150)),
+        })
+    );
+    assert_eq!(jobs[1].trim, batch.trim);
+    // 剪辑设置无效时不转码也不重试
+    assert_eq!(jobs.len(), 2);
+    assert_eq!(report.converted(), 2);
+    assert_eq!(report.failed(), 1);
+    assert_eq!(recorder.failures.len(), 1);
+    assert!(recorder.failures[0].0.contains("未知的剪辑项"));
+    assert!(log_content(&dir).contains("剪辑: 00:01:30 - 00:02:30"));
+}
@@ -0,0 +1,163 @@
+// 合并多个文件：合并方式的选择、文件列表、concat 滤镜和校验用的合并信息
+
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use ffmpeg_convert::concat::{
+    ConcatMethod, combined_info, concat_list, concat_output_path, group_by_folder, plan_concat,
+};
+use ffmpeg_convert::media::MediaInfo;
+use ffmpeg_convert::preset::Preset;
+
+fn clip(width: u32, duration: f64, audio: bool) -> MediaInfo {
+    let mut probe = format!(
+        "[STREAM]\nindex=0\ncodec_type=video\ncodec_name=h264\nwidth={}\nheight=1080\npix_fmt=yuv420p\navg_frame_rate=30/1\nnb_frames={}\n[/STREAM]\n",
+        width,
+        duration * 30.0
+    );
+    if audio {
+        probe.push_str(
+            "[STREAM]\nindex=1\ncodec_type=audio\ncodec_name=aac\nsample_rate=48000\nchannels=2\n[/STREAM]\n",
+        );
+    }
+    probe.push_str(&format!("[FORMAT]\nduration={}\n[/FORMAT]\n", duration));
+    MediaInfo::parse(&probe)
+}
+
+fn inputs(names: &[&str]) -> Vec<PathBuf> {
+    names
+        .iter()
+        .map(|n| Path::new("/dashcam").join(n))
+        .collect()
+}
+
+#[test]
+fn joins_matching_files_with_demuxer() {
+    let inputs = inputs(&["GH01.MP4", "GH02.MP4"]);
+    let infos = [clip(1920, 60.0, true), clip(1920, 30.5, true)];
+    let list = Path::new("/tmp/list.txt");
+
+    let concat = plan_concat(&inputs, &infos, list).unwrap();
+    assert_eq!(
+        concat.method,
+        ConcatMethod::Demuxer {
+            list: list.to_path_buf()
+        }
+    );
+    assert_eq!(concat.duration, Some(Duration::from_secs_f64(90.5)));
+    assert_eq!(
+        concat.input_args(),
+        ["-f", "concat", "-safe", "0", "-i", "/tmp/list.txt"]
+    );
+
+    assert_eq!(
+        concat_list(&inputs, &infos),
+        "ffconcat version 1.0\nfile '/dashcam/GH01.MP4'\nduration 60.000\nfile '/dashcam/GH02.MP4'\nduration 30.500\n"
+    );
+    assert_eq!(
+        concat_list(&[PathBuf::from("/a/it's.mp4")], &[MediaInfo::default()]),
+        "ffconcat version 1.0\nfile '/a/it'\\''s.mp4'\n"
+    );
+
+    let combined = combined_info(&infos);
+    assert_eq!(combined.duration, Some(Duration::from_secs_f64(90.5)));
+    assert_eq!(combined.video_stream().unwrap().frames, Some(2715));
+}
+
+#[test]
+fn joins_mismatched_files_with_filter() {
+    let inputs = inputs(&["a.mp4", "b.mp4"]);
+    let infos = [clip(1920, 10.0, true), clip(1280, 10.0, false)];
+
+    let concat = plan_concat(&inputs, &infos, Path::new("list.txt")).unwrap();
+    assert_eq!(
+        concat.method,
+        ConcatMethod::Filter {
+            width: 1920,
+            height: 1080,
+            frame_rate: 30.0,
+            audio: false,
+        }
+    );
+    let args = concat.input_args();
+    assert_eq!(args[..4], ["-i", "/dashcam/a.mp4", "-i", "/dashcam/b.mp4"]);
+    assert_eq!(args[4], "-filter_complex");
+    assert!(
+        args[5].starts_with(
+            "[0:v:0]scale=1920:1080:force_original_aspect_ratio=decrease,pad=1920:1080:"
+        )
+    );
+    assert!(args[5].ends_with(";[v0][v1]concat=n=2:v=1:a=0[v]"));
+    assert_eq!(args[6..], ["-map", "[v]"]);
+
+    let no_video = MediaInfo::parse("[STREAM]\ncodec_type=audio\n[/STREAM]\n");
+    assert!(plan_concat(&inputs, &[clip(1920, 1.0, true), no_video], Path::new("l")).is_err());
+}
+
+#[test]
+fn moves_preset_filters_after_the_concat_filter() {
+    let inputs = inputs(&["a.mp4", "b.mp4"]);
+    let preset = Preset::new(
+        "-c:v libx265 -vf yadif=1,fps=30 -c:a aac -af loudnorm=I=-16",
+        "_H265",
+        "",
+    );
+
+    let concat = plan_concat(
+        &inputs,
+        &[clip(1920, 10.0, true), clip(1280, 10.0, true)],
+        Path::new("l"),
+    )
+    .unwrap();
+    let (concat, stripped) = concat.with_preset_filters(&preset);
+    assert_eq!(stripped.params, "-c:v libx265 -c:a aac");
+    let args = concat.input_args();
+    assert!(args[5].ends_with(
+        ";[v0][a0][v1][a1]concat=n=2:v=1:a=1[vc][ac];[vc]yadif=1,fps=30[v];[ac]loudnorm=I=-16[a]"
+    ));
+    assert_eq!(args[6..], ["-map", "[v]", "-map", "[a]"]);
+
+    // 测量响度时按相同的方式拼接声音
+    let loudness = concat.loudness_args("loudnorm=print_format=json").unwrap();
+    assert_eq!(loudness[4], "-filter_complex");
+    assert!(loudness[5].ends_with("[a0][a1]concat=n=2:v=0:a=1,loudnorm=print_format=json[a]"));
+    assert_eq!(loudness[6..], ["-map", "[a]", "-f", "null", "-"]);
+
+    // 直接拼接时滤镜留在预设中
+    let matching = [clip(1920, 10.0, true), clip(1920, 10.0, true)];
+    let concat = plan_concat(&inputs, &matching, Path::new("l")).unwrap();
+    let (concat, kept) = concat.with_preset_filters(&preset);
+    assert_eq!(kept.params, preset.params);
+    assert_eq!(concat.video_filter, None);
+    assert_eq!(
+        concat.loudness_args("loudnorm").unwrap(),
+        [
+            "-f", "concat", "-safe", "0", "-i", "l", "-map", "0:a:0?", "-vn", "-sn", "-dn", "-af",
+            "loudnorm", "-f", "null", "-"
+        ]
+    );
+
+    let silent = [clip(1920, 10.0, true), clip(1280, 10.0, false)];
+    let concat = plan_concat(&inputs, &silent, Path::new("l")).unwrap();
+    assert_eq!(concat.loudness_args("loudnorm"), None);
+}
+
+#[test]
+fn groups_inputs_by_folder() {
+    let files = [
+        PathBuf::from("/cam/front/1.mp4"),
+        PathBuf::from("/cam/rear/1.mp4"),
+        PathBuf::from("/cam/front/2.mp4"),
+    ];
+    assert_eq!(
+        group_by_folder(&files),
+        [
+            vec![files[0].clone(), files[2].clone()],
+            vec![files[1].clone()]
+        ]
+    );
+    assert_eq!(
+        concat_output_path(&files[0], "_AV1", "mp4"),
+        Path::new("/cam/front/1_concat_AV1.mp4")
+    );
+}
@@ -0,0 +1,110 @@
+// HDR 信息的读取、保留 HDR 的编码参数和转 SDR 的滤镜
+
+use ffmpeg_convert::analyze::{Analysis, video_filters};
+use ffmpeg_convert::hdr::{ContentLight, HdrFormat, HdrInfo, with_hdr};
+use ffmpeg_convert::media::MediaInfo;
+use ffmpeg_convert::preset::Preset;
+
+const PROBE_OUTPUT: &str = "\
+[STREAM]
+index=0
+codec_type=video
+codec_name=hevc
+width=3840
+height=2160
+avg_frame_rate=24000/1001
+color_space=bt2020nc
+color_transfer=smpte2084
+color_primaries=bt2020
+[SIDE_DATA]
+side_data_type=Content light level metadata
+max_content=1000
+max_average=400
+[/SIDE_DATA]
+nb_frames=1000
+[/STREAM]
+[FRAME]
+media_type=video
+[SIDE_DATA]
+side_data_type=Mastering display metadata
+red_x=35400/50000
+red_y=14600/50000
+green_x=8500/50000
+green_y=39850/50000
+blue_x=6550/50000
+blue_y=2300/50000
+white_point_x=15635/50000
+white_point_y=16450/50000
+min_luminance=50/10000
+max_luminance=10000000/10000
+[/SIDE_DATA]
+[/FRAME]
+";
+
+fn hdr10() -> HdrInfo {
+    let info = MediaInfo::parse(PROBE_OUTPUT);
+    HdrInfo::detect(info.video_stream().unwrap(), PROBE_OUTPUT).unwrap()
+}
+
+#[test]
+fn detects_hdr10_with_metadata() {
+    let info = MediaInfo::parse(PROBE_OUTPUT);
+    // 流中的附加数据段之后的字段仍属于该流
+    assert_eq!(info.video_stream().unwrap().frames, Some(1000));
+
+    let hdr = hdr10();
+    assert_eq!(hdr.format, HdrFormat::Pq);
+    assert_eq!(hdr.matrix, "bt2020nc");
+    assert_eq!(
+        hdr.content_light,
+        Some(ContentLight {
+            max_content: 1000,
+            max_average: 400
+        })
+    );
+    let mastering = hdr.mastering.unwrap();
+    assert_eq!(mastering.green, (0.17, 0.797));
+    assert_eq!(mastering.max_luminance, 1000.0);
+
+    let sdr = MediaInfo::parse("[STREAM]\ncodec_type=video\ncolor_transfer=bt709\n[/STREAM]\n");
+    assert_eq!(HdrInfo::detect(sdr.video_stream().unwrap(), ""), None);
+}
+
+#[test]
+fn passes_hdr_through_x265_and_svtav1() {
+    let hdr = hdr10();
+
+    let preset = Preset::new(
+        "-c:a aac -c:v libx265 -crf 23 -x265-params aq-mode=3",
+        "_H265",
+        "",
+    );
+    assert_eq!(
+        with_hdr(&preset, &hdr).params,
+        "-c:a aac -c:v libx265 -crf 23 -x265-params aq-mode=3:repeat-headers=1:colorprim=bt2020:transfer=smpte2084:colormatrix=bt2020nc:hdr10=1:hdr10-opt=1:master-display=G(8500,39850)B(6550,2300)R(35400,14600)WP(15635,16450)L(10000000,50):max-cll=1000,400 -pix_fmt yuv420p10le -color_primaries bt2020 -color_trc smpte2084 -colorspace bt2020nc"
+    );
+
+    let preset = Preset::new("-c:v libsvtav1 -crf 28 -pix_fmt yuv420p10le", "_AV1", "");
+    assert_eq!(
+        with_hdr(&preset, &hdr).params,
+        "-c:v libsvtav1 -crf 28 -pix_fmt yuv420p10le -color_primaries bt2020 -color_trc smpte2084 -colorspace bt2020nc -svtav1-params enable-hdr=1:color-primaries=9:transfer-characteristics=16:matrix-coefficients=9:mastering-display=G(0.1700,0.7970)B(0.1310,0.0460)R(0.7080,0.2920)WP(0.3127,0.3290)L(1000,0.005):content-light=1000,400"
+    );
+}
+
+#[test]
+fn tonemaps_only_hdr_input() {
+    let mut preset = Preset::new("-c:v libx265 -crf 23", "_SDR", "");
+    preset.tonemap = Some("hable".to_string());
+
+    let mut analysis = Analysis {
+        info: MediaInfo::parse(PROBE_OUTPUT),
+        ..Default::default()
+    };
+    assert!(video_filters(&analysis, &preset).is_empty());
+
+    analysis.hdr = Some(hdr10());
+    let filters = video_filters(&analysis, &preset);
+    assert_eq!(filters.len(), 1);
+    assert!(filters[0].contains("tonemap=hable"));
+    assert!(filters[0].ends_with("format=yuv420p"));
+}
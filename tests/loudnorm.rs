@@ -0,0 +1,101 @@
+// 响度标准化：loudnorm 输出的解析、第二遍的滤镜和预设选项
+
+use ffmpeg_convert::analyze::with_audio_filters;
+use ffmpeg_convert::config::{Settings, parse_config};
+use ffmpeg_convert::loudnorm::{
+    Loudness, LoudnessTarget, normalize_filters, parse_loudnorm_json, parse_sample_rate,
+};
+use ffmpeg_convert::preset::Preset;
+
+const MEASURE_OUTPUT: &str = "\
+Input #0, mov,mp4,m4a,3gp,3g2,mj2, from 'lecture.mp4':
+  Stream #0:1[0x2](und): Audio: aac (LC) (mp4a / 0x6134706D), 44100 Hz, mono, fltp, 64 kb/s (default)
+[Parsed_loudnorm_0 @ 0x5612]
+{
+\t\"input_i\" : \"-27.61\",
+\t\"input_tp\" : \"-4.47\",
+\t\"input_lra\" : \"18.06\",
+\t\"input_thresh\" : \"-39.20\",
+\t\"output_i\" : \"-16.58\",
+\t\"output_tp\" : \"-1.50\",
+\t\"output_lra\" : \"14.78\",
+\t\"output_thresh\" : \"-27.71\",
+\t\"normalization_type\" : \"dynamic\",
+\t\"target_offset\" : \"0.58\"
+}
+";
+
+fn measured() -> Loudness {
+    parse_loudnorm_json(MEASURE_OUTPUT).unwrap()
+}
+
+#[test]
+fn parses_first_pass_output() {
+    assert_eq!(
+        measured(),
+        Loudness {
+            integrated: -27.61,
+            true_peak: -4.47,
+            range: 18.06,
+            threshold: -39.2,
+            target_offset: 0.58,
+        }
+    );
+    assert_eq!(parse_sample_rate(MEASURE_OUTPUT), Some(44100));
+    assert_eq!(parse_loudnorm_json("Output file is empty"), None);
+}
+
+#[test]
+fn builds_second_pass_filters() {
+    let target = LoudnessTarget {
+        integrated: -16.0,
+        true_peak: -1.5,
+    };
+    assert_eq!(
+        normalize_filters(target, &measured(), Some(44100)),
+        [
+            "loudnorm=I=-16:TP=-1.5:LRA=18.06:measured_I=-27.61:measured_TP=-4.47:measured_LRA=18.06:measured_thresh=-39.2:offset=0.58:linear=true",
+            "aresample=44100"
+        ]
+    );
+    // 提高 11.6 LU 后峰值会超过 -1.5 dBTP，只能动态调整
+    assert!(!target.linear_possible(&measured()));
+    let quiet = Loudness {
+        true_peak: -14.0,
+        ..measured()
+    };
+    assert!(target.linear_possible(&quiet));
+}
+
+#[test]
+fn appends_after_existing_audio_filters() {
+    let filters = ["loudnorm".to_string()];
+    let preset = Preset::new("-af highpass=f=80 -c:a aac", "_AAC", "");
+    assert_eq!(
+        with_audio_filters(&preset, &filters).unwrap().params,
+        "-af highpass=f=80,loudnorm -c:a aac"
+    );
+}
+
+#[test]
+fn parses_loudnorm_options() {
+    let mut presets = Vec::new();
+    let mut settings = Settings::default();
+    let warnings = parse_config(
+        "-c:a aac -c:v libx265 # _H265 # 讲座 # loudnorm=-16 loudnorm_tp=-2\n\
+         -c:a copy -c:v libx265 # _H265 # 复制音频 # loudnorm=-16\n\
+         -c:a aac -c:v libx265 # _H265 # 无效 # loudnorm=3",
+        &mut presets,
+        &mut settings,
+    );
+    assert_eq!(
+        presets[0].loudnorm,
+        Some(LoudnessTarget {
+            integrated: -16.0,
+            true_peak: -2.0
+        })
+    );
+    assert_eq!(presets[1].loudnorm, None);
+    assert_eq!(presets[2].loudnorm, None);
+    assert_eq!(warnings.len(), 2);
+}
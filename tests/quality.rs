@@ -0,0 +1,121 @@
+// 画质评分解析、预设对比的片段选取和 CRF 搜索
+
+use std::time::Duration;
+
+use ffmpeg_convert::benchmark::segment_ranges;
+use ffmpeg_convert::config::{Settings, parse_config};
+use ffmpeg_convert::crfsearch::{
+    CrfSearchError, Metric, QualityTarget, highest_passing_crf, with_crf,
+};
+use ffmpeg_convert::preset::Preset;
+use ffmpeg_convert::quality::{QualityScores, parse_scores};
+
+#[test]
+fn parses_ffmpeg_quality_output() {
+    let output = "\
+[Parsed_ssim_4 @ 0x55d0] SSIM Y:0.990123 (20.045) U:0.993 (21.5) V:0.992 (21.1) All:0.991234 (20.5)
+[Parsed_psnr_5 @ 0x55d1] PSNR y:45.10 u:48.20 v:48.00 average:46.01 min:43.20 max:49.90
+[Parsed_libvmaf_6 @ 0x55d2] VMAF score: 95.123456";
+
+    let scores = parse_scores(output);
+    assert_eq!(scores.ssim, Some(0.991234));
+    assert_eq!(scores.psnr, Some(46.01));
+    assert_eq!(scores.vmaf, Some(95.123456));
+
+    let scores = parse_scores("PSNR y:inf u:inf v:inf average:inf min:inf max:inf");
+    assert_eq!(scores.psnr, Some(f64::INFINITY));
+    assert_eq!(scores.ssim, None);
+}
+
+#[test]
+fn averages_scores() {
+    let a = QualityScores {
+        ssim: Some(0.98),
+        psnr: Some(40.0),
+        vmaf: None,
+    };
+    let b = QualityScores {
+        ssim: Some(0.96),
+        psnr: Some(44.0),
+        vmaf: Some(90.0),
+    };
+
+    let average = QualityScores::average(&[a, b]);
+    assert!((average.ssim.unwrap() - 0.97).abs() < 1e-9);
+    assert_eq!(average.psnr, Some(42.0));
+    assert_eq!(average.vmaf, None);
+}
+
+#[test]
+fn spreads_segments_over_the_video() {
+    let secs = Duration::from_secs;
+
+    assert_eq!(
+        segment_ranges(secs(400), 3, secs(10)),
+        vec![
+            (secs(95), secs(10)),
+            (secs(195), secs(10)),
+            (secs(295), secs(10))
+        ]
+    );
+
+    // 视频太短时只取开头一段
+    assert_eq!(
+        segment_ranges(secs(20), 3, secs(10)),
+        vec![(secs(0), secs(20))]
+    );
+}
+
+#[test]
+fn finds_highest_crf_meeting_target() {
+    // 模拟画质随 CRF 线性下降: CRF 20 时 SSIM 0.99，每增加 1 下降 0.002
+    let ssim = |crf: u32| 0.99 - (crf as f64 - 20.0) * 0.002;
+    let mut tried = Vec::new();
+    let choice = highest_passing_crf((10, 40), 0.98, |crf| {
+        tried.push(crf);
+        Ok(ssim(crf))
+    })
+    .unwrap();
+    assert_eq!(choice.crf, 25);
+    assert!(choice.met);
+    assert!(tried.len() <= 5);
+
+    // 最小的 CRF 也达不到目标时使用最小的 CRF
+    let choice = highest_passing_crf((10, 40), 1.1, |crf| Ok(ssim(crf))).unwrap();
+    assert_eq!((choice.crf, choice.met), (10, false));
+
+    let error = highest_passing_crf((10, 40), 0.98, |_| Err(CrfSearchError::Cancelled));
+    assert_eq!(error, Err(CrfSearchError::Cancelled));
+}
+
+#[test]
+fn replaces_crf_in_preset() {
+    let preset = Preset::new("-c:a aac -c:v libx265 -crf 23 -preset slow", "_H265", "");
+    assert_eq!(
+        with_crf(&preset, 27).params,
+        "-c:a aac -c:v libx265 -crf 27 -preset slow"
+    );
+    let preset = Preset::new("-c:v libx264", "_H264", "");
+    assert_eq!(with_crf(&preset, 20).params, "-c:v libx264 -crf 20");
+}
+
+#[test]
+fn parses_quality_target_option() {
+    let mut presets = Vec::new();
+    let mut settings = Settings::default();
+    let warnings = parse_config(
+        "-c:v libx265 -crf 23 # _H265 # x265 # target_vmaf=93\n-c:v hevc_amf # _H265 # amf # target_ssim=0.98",
+        &mut presets,
+        &mut settings,
+    );
+    assert_eq!(
+        presets[0].quality_target,
+        Some(QualityTarget {
+            metric: Metric::Vmaf,
+            value: 93.0
+        })
+    );
+    // hevc_amf 没有 CRF，忽略目标画质
+    assert_eq!(presets[1].quality_target, None);
+    assert_eq!(warnings.len(), 1);
+}
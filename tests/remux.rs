@@ -0,0 +1,132 @@
+// 重新封装：流与输出封装的兼容性、复制和转码的参数
+
+use ffmpeg_convert::config::{Settings, parse_config};
+use ffmpeg_convert::media::MediaInfo;
+use ffmpeg_convert::preset::{Preset, builtin_presets};
+use ffmpeg_convert::remux::{StreamAction, copy_all_streams, plan_remux};
+
+fn stream(index: usize, codec_type: &str, codec_name: &str) -> String {
+    format!(
+        "[STREAM]\nindex={}\ncodec_type={}\ncodec_name={}\n[/STREAM]\n",
+        index, codec_type, codec_name
+    )
+}
+
+fn remux_preset() -> Preset {
+    builtin_presets().into_iter().find(|p| p.remux).unwrap()
+}
+
+#[test]
+fn copies_compatible_streams_and_converts_the_rest() {
+    let probe = [
+        stream(0, "video", "hevc"),
+        stream(1, "audio", "dts"),
+        stream(2, "audio", "aac"),
+        stream(3, "subtitle", "subrip"),
+        stream(4, "subtitle", "hdmv_pgs_subtitle"),
+        stream(5, "attachment", "ttf"),
+        "[FORMAT]\nformat_name=matroska,webm\n[/FORMAT]\n".to_string(),
+    ]
+    .concat();
+    let plan = plan_remux(&remux_preset(), &MediaInfo::parse(&probe));
+
+    let actions: Vec<&StreamAction> = plan.streams.iter().map(|(_, a)| a).collect();
+    assert_eq!(
+        actions,
+        [
+            &StreamAction::Copy,
+            &StreamAction::Encode("aac".to_string()),
+            &StreamAction::Copy,
+            &StreamAction::Encode("mov_text".to_string()),
+            &StreamAction::Drop,
+            &StreamAction::Drop,
+        ]
+    );
+    assert!(!plan.genpts);
+    assert_eq!(
+        plan.summary(),
+        "复制 2 个流，转码 2 个流 (dts -> aac, subrip -> mov_text)，丢弃 2 个流 (hdmv_pgs_subtitle, ttf)"
+    );
+
+    let preset = plan.apply(&remux_preset());
+    assert_eq!(
+        preset.params,
+        "-map 0:0 -c:0 copy -tag:0 hvc1 -map 0:1 -c:1 aac -map 0:2 -c:2 copy -map 0:3 -c:3 mov_text -crf 20 -preset medium -b:a 192k -movflags +faststart+use_metadata_tags"
+    );
+    assert_eq!(preset.input_params, "");
+}
+
+#[test]
+fn regenerates_timestamps_for_transport_streams() {
+    let probe = [
+        stream(0, "video", "h264"),
+        stream(1, "audio", "aac"),
+        stream(2, "data", "timed_id3"),
+        "[FORMAT]\nformat_name=mpegts\n[/FORMAT]\n".to_string(),
+    ]
+    .concat();
+    let plan = plan_remux(&remux_preset(), &MediaInfo::parse(&probe));
+    assert!(plan.genpts);
+
+    let preset = plan.apply(&remux_preset());
+    assert_eq!(preset.input_params, "-fflags +genpts");
+    assert!(
+        preset
+            .params
+            .starts_with("-map 0:0 -c:0 copy -map 0:1 -c:1 copy -crf")
+    );
+    assert!(!preset.params.contains("0:2"));
+}
+
+#[test]
+fn keeps_cover_art_and_falls_back_to_copying_everything() {
+    let probe = [
+        stream(0, "video", "mpeg1video"),
+        stream(1, "audio", "pcm_s16le"),
+        stream(2, "video", "mjpeg").replace("[/STREAM]", "DISPOSITION:attached_pic=1\n[/STREAM]"),
+    ]
+    .concat();
+    let mut preset = Preset::new("-c:v libx265 -crf 24 -c:a copy", "", "");
+    preset.remux = true;
+    let plan = plan_remux(&preset, &MediaInfo::parse(&probe));
+    let actions: Vec<&StreamAction> = plan.streams.iter().map(|(_, a)| a).collect();
+    // 参数中的音频为 copy 时改用 aac 转码不兼容的音轨
+    assert_eq!(
+        actions,
+        [
+            &StreamAction::Encode("libx265".to_string()),
+            &StreamAction::Encode("aac".to_string()),
+            &StreamAction::Copy,
+        ]
+    );
+
+    assert_eq!(
+        copy_all_streams(&preset).params,
+        "-map 0 -c copy -crf 24 -movflags +faststart+use_metadata_tags"
+    );
+    // 扩展名与输出时判断 MP4 类封装的方式一致，不区分大小写
+    preset.extension = Some("MOV".to_string());
+    assert!(
+        copy_all_streams(&preset)
+            .params
+            .ends_with("-movflags +faststart+use_metadata_tags")
+    );
+    preset.extension = Some("mkv".to_string());
+    assert_eq!(copy_all_streams(&preset).params, "-map 0 -c copy -crf 24");
+}
+
+#[test]
+fn parses_remux_option() {
+    let mut presets = Vec::new();
+    let mut settings = Settings::default();
+    let warnings = parse_config(
+        "-c:v libx264 -c:a aac # _REMUX # 重新封装 # remux=1 ext=mkv\n\
+         -c:v libx264 -c:a aac # _REMUX # 目标大小 # remux=1 target_size=25M",
+        &mut presets,
+        &mut settings,
+    );
+    assert!(presets[0].remux);
+    assert_eq!(presets[0].extension(), "mkv");
+    assert_eq!(presets[1].target, None);
+    assert_eq!(warnings.len(), 1);
+}
@@ -0,0 +1,135 @@
+// 目标大小/码率的解析、码率计算和两遍编码参数
+
+use std::time::Duration;
+
+use ffmpeg_convert::progress::Progress;
+use ffmpeg_convert::target::{
+    RateTarget, parse_audio_bitrate, parse_bitrate, parse_size, rate_args, video_bitrate,
+};
+
+#[test]
+fn parses_sizes_and_bitrates() {
+    assert_eq!(parse_size("25M"), Some(25 * 1024 * 1024));
+    assert_eq!(parse_size("700MB"), Some(700 * 1024 * 1024));
+    assert_eq!(parse_size("1.5G"), Some(1536 * 1024 * 1024));
+    assert_eq!(parse_size("1000"), Some(1000));
+    assert_eq!(parse_bitrate("2500k"), Some(2_500_000));
+    assert_eq!(parse_bitrate("8M"), Some(8_000_000));
+    assert_eq!(parse_bitrate("0k"), None);
+    assert_eq!(parse_bitrate("abc"), None);
+}
+
+#[test]
+fn computes_video_bitrate_from_size() {
+    // 100 秒、目标 12.5 MB (100 Mbit)：总码率 1000k，扣除 2% 封装开销和 128k 音频
+    let bitrate = video_bitrate(
+        RateTarget::Size(12_500_000),
+        Duration::from_secs(100),
+        128_000,
+    )
+    .unwrap();
+    assert_eq!(bitrate, 852_000);
+
+    assert_eq!(
+        video_bitrate(RateTarget::Bitrate(2_000_000), Duration::ZERO, 128_000),
+        Ok(2_000_000)
+    );
+    assert!(
+        video_bitrate(
+            RateTarget::Size(1_000_000),
+            Duration::from_secs(600),
+            128_000
+        )
+        .is_err()
+    );
+    assert!(video_bitrate(RateTarget::Size(1_000_000), Duration::ZERO, 0).is_err());
+}
+
+#[test]
+fn builds_two_pass_args() {
+    let args = [
+        "-c:a", "aac", "-c:v", "libx265", "-crf", "23", "-preset", "slow",
+    ];
+    assert_eq!(
+        rate_args(&args, "libx265", 852_000, Some(1)),
+        [
+            "-c:a",
+            "aac",
+            "-c:v",
+            "libx265",
+            "-preset",
+            "slow",
+            "-b:v",
+            "852k",
+            "-x265-params",
+            "pass=1:stats=ffmpegConvert2pass.log"
+        ]
+    );
+
+    let args = ["-c:v", "libx265", "-x265-params", "aq-mode=3", "-crf", "20"];
+    assert_eq!(
+        rate_args(&args, "libx265", 1_000_000, Some(2)),
+        [
+            "-c:v",
+            "libx265",
+            "-b:v",
+            "1000k",
+            "-x265-params",
+            "aq-mode=3:pass=2:stats=ffmpegConvert2pass.log"
+        ]
+    );
+
+    let args = [
+        "-c:v",
+        "libaom-av1",
+        "-crf",
+        "28",
+        "-b:v",
+        "0",
+        "-row-mt",
+        "1",
+    ];
+    assert_eq!(
+        rate_args(&args, "libaom-av1", 500_000, Some(2)),
+        [
+            "-c:v",
+            "libaom-av1",
+            "-row-mt",
+            "1",
+            "-b:v",
+            "500k",
+            "-pass",
+            "2",
+            "-passlogfile",
+            "ffmpegConvert2pass"
+        ]
+    );
+}
+
+#[test]
+fn parses_audio_bitrate_from_stream_info() {
+    let line = "  Stream #0:1(und): Audio: aac (LC) (mp4a / 0x6134706D), 48000 Hz, stereo, fltp, 128 kb/s (default)";
+    assert_eq!(parse_audio_bitrate(line), Some(128_000));
+    assert_eq!(
+        parse_audio_bitrate("  Stream #0:0: Video: h264, yuv420p, 1920x1080, 5000 kb/s"),
+        None
+    );
+    assert_eq!(
+        parse_audio_bitrate("  Stream #0:1: Audio: flac, 48000 Hz, stereo, s16"),
+        None
+    );
+}
+
+#[test]
+fn two_pass_progress_spans_both_passes() {
+    let progress = |pass| Progress {
+        current_time: Duration::from_secs(30),
+        total: Some(Duration::from_secs(60)),
+        speed_str: "1.00x".to_string(),
+        elapsed: Duration::from_secs(10),
+        pass,
+    };
+    assert_eq!(progress(None).percentage(), Some(50.0));
+    assert_eq!(progress(Some((1, 2))).percentage(), Some(25.0));
+    assert_eq!(progress(Some((2, 2))).percentage(), Some(75.0));
+}
@@ -0,0 +1,87 @@
+// 剪辑范围：时间的解析、剪辑设置文件、ffmpeg 参数和样本位置
+
+use std::time::Duration;
+
+use ffmpeg_convert::trim::{Trim, parse_time, parse_trim, sample_ranges};
+
+fn secs(s: f64) -> Duration {
+    Duration::from_secs_f64(s)
+}
+
+#[test]
+fn parses_times() {
+    assert_eq!(parse_time("90"), Some(secs(90.0)));
+    assert_eq!(parse_time(" 1:30.5 "), Some(secs(90.5)));
+    assert_eq!(parse_time("01:02:03.25"), Some(secs(3723.25)));
+    assert_eq!(parse_time("1:2:3:4"), None);
+    assert_eq!(parse_time("-5"), None);
+    assert_eq!(parse_time("abc"), None);
+}
+
+#[test]
+fn builds_ranges_from_end_or_duration() {
+    let trim = Trim::from_parts(Some(secs(90.0)), None, Some(secs(60.0)))
+        .unwrap()
+        .unwrap();
+    assert_eq!(trim.end, Some(secs(150.0)));
+    assert_eq!(trim.input_args(), ["-ss", "90.000"]);
+    assert_eq!(trim.output_args(), ["-t", "60.000"]);
+    assert_eq!(trim.to_string(), "00:01:30 - 00:02:30");
+    // 结束时间超过文件长度时到文件末尾为止
+    assert_eq!(trim.duration_within(secs(120.0)), secs(30.0));
+
+    let to_end = Trim::from_parts(Some(secs(30.0)), None, None)
+        .unwrap()
+        .unwrap();
+    assert!(to_end.output_args().is_empty());
+    assert_eq!(to_end.duration_within(secs(100.0)), secs(70.0));
+
+    let from_start = Trim::from_parts(None, Some(secs(10.0)), None)
+        .unwrap()
+        .unwrap();
+    assert!(from_start.input_args().is_empty());
+
+    assert_eq!(Trim::from_parts(None, None, None), Ok(None));
+    assert!(Trim::from_parts(Some(secs(10.0)), Some(secs(5.0)), None).is_err());
+    assert!(Trim::from_parts(None, Some(secs(5.0)), Some(secs(5.0))).is_err());
+}
+
+#[test]
+fn parses_sidecar_text() {
+    assert_eq!(
+        parse_trim("// 只要中间一段\nstart = 00:10:00\nend = 00:12:30\n"),
+        Ok(Some(Trim {
+            start: secs(600.0),
+            end: Some(secs(750.0)),
+        }))
+    );
+    assert_eq!(parse_trim("# 空\n"), Ok(None));
+    assert!(parse_trim("start = 1:xx").is_err());
+    assert!(parse_trim("00:10:00").is_err());
+}
+
+#[test]
+fn samples_only_inside_the_trimmed_range() {
+    let trim = Trim {
+        start: secs(600.0),
+        end: Some(secs(900.0)),
+    };
+    // 10 分钟到 15 分钟之间均匀截取 2 段
+    assert_eq!(
+        sample_ranges(Some(trim), secs(3600.0), 2, secs(10.0)),
+        [(secs(695.0), secs(10.0)), (secs(795.0), secs(10.0))]
+    );
+    assert_eq!(
+        sample_ranges(None, secs(3600.0), 2, secs(10.0)),
+        [(secs(1195.0), secs(10.0)), (secs(2395.0), secs(10.0))]
+    );
+    // 剪辑范围比样本短时整段作为一个样本
+    let short = Trim {
+        start: secs(100.0),
+        end: Some(secs(105.0)),
+    };
+    assert_eq!(
+        sample_ranges(Some(short), secs(3600.0), 2, secs(10.0)),
+        [(secs(100.0), secs(5.0))]
+    );
+}
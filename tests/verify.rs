@@ -0,0 +1,59 @@
+// 输出校验中时长和帧数的比较
+
+use std::time::Duration;
+
+use ffmpeg_convert::media::MediaInfo;
+use ffmpeg_convert::verify::compare_media;
+
+fn media(duration: f64, fps: f64, frames: Option<u64>) -> MediaInfo {
+    MediaInfo::parse(&format!(
+        "[STREAM]\nindex=0\ncodec_type=video\ncodec_name=h264\navg_frame_rate={}/1000\n{}\n[/STREAM]\n[FORMAT]\nduration={}\n[/FORMAT]\n",
+        (fps * 1000.0) as u64,
+        frames.map_or("nb_frames=N/A".to_string(), |n| format!(
+            "nb_read_packets={}",
+            n
+        )),
+        duration
+    ))
+}
+
+const TOLERANCE: Duration = Duration::from_secs(1);
+
+#[test]
+fn accepts_output_within_tolerance() {
+    let input = media(600.0, 25.0, Some(15000));
+    assert_eq!(
+        compare_media(&input, &media(600.4, 25.0, Some(15010)), TOLERANCE),
+        Ok(())
+    );
+
+    // 帧率减半时帧数也应减半
+    assert_eq!(
+        compare_media(&input, &media(600.0, 12.5, Some(7500)), TOLERANCE),
+        Ok(())
+    );
+}
+
+#[test]
+fn rejects_truncated_output() {
+    let input = media(600.0, 25.0, Some(15000));
+
+    let err = compare_media(&input, &media(412.5, 25.0, Some(10312)), TOLERANCE).unwrap_err();
+    assert!(err.starts_with("输出时长 412.50 秒"), "{}", err);
+
+    // 时长正常但丢帧
+    let err = compare_media(&input, &media(600.0, 25.0, Some(14000)), TOLERANCE).unwrap_err();
+    assert!(err.starts_with("输出 14000 帧"), "{}", err);
+
+    let err = compare_media(&input, &media(600.0, 25.0, None), TOLERANCE).unwrap_err();
+    assert_eq!(err, "无法读取输出帧数");
+}
+
+#[test]
+fn skips_frame_check_when_input_frames_unknown() {
+    let input = media(600.0, 25.0, None);
+    assert_eq!(
+        compare_media(&input, &media(600.0, 25.0, Some(1)), TOLERANCE),
+        Ok(())
+    );
+}